@@ -53,7 +53,7 @@ async fn add_leaf(
     timeout(
         REQUEST_TIMEOUT,
         client
-            .post(&format!("{}/add-leaf", base_url))
+            .post(format!("{}/add-leaf", base_url))
             .json(&json!({ "leaf": leaf }))
             .send(),
     )
@@ -69,7 +69,7 @@ async fn add_leaves(
     timeout(
         REQUEST_TIMEOUT,
         client
-            .post(&format!("{}/add-leaves", base_url))
+            .post(format!("{}/add-leaves", base_url))
             .json(&json!({ "leaves": leaves }))
             .send(),
     )
@@ -80,7 +80,7 @@ async fn add_leaves(
 async fn get_root(client: &Client, base_url: &str) -> Result<(), Box<dyn std::error::Error>> {
     timeout(
         REQUEST_TIMEOUT,
-        client.get(&format!("{}/get-root", base_url)).send(),
+        client.get(format!("{}/get-root", base_url)).send(),
     )
     .await??;
     Ok(())
@@ -94,7 +94,7 @@ async fn get_proof(
     timeout(
         REQUEST_TIMEOUT,
         client
-            .post(&format!("{}/get-proof", base_url))
+            .post(format!("{}/get-proof", base_url))
             .json(&json!({ "index": index }))
             .send(),
     )
@@ -3,8 +3,6 @@ use futures::future::join_all;
 use rand::{Rng, random};
 use reqwest::Client;
 use serde_json::json;
-use std::sync::Arc;
-use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::Duration;
 use tokio::time::timeout;
 
@@ -29,6 +27,7 @@ async fn setup_client() -> Client {
         .unwrap()
 }
 
+#[allow(dead_code)]
 async fn add_leaf_single(
     client: &Client,
     base_url: &str,
@@ -37,7 +36,7 @@ async fn add_leaf_single(
     let response = timeout(
         REQUEST_TIMEOUT,
         client
-            .post(&format!("{}/add-leaf", base_url))
+            .post(format!("{}/add-leaf", base_url))
             .json(&json!({ "leaf": leaf }))
             .send(),
     )
@@ -57,7 +56,7 @@ async fn add_leaves_batch(
     let response = timeout(
         REQUEST_TIMEOUT,
         client
-            .post(&format!("{}/add-leaves", base_url))
+            .post(format!("{}/add-leaves", base_url))
             .json(&json!({ "leaves": leaves }))
             .send(),
     )
@@ -75,7 +74,7 @@ async fn get_root(
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let response = timeout(
         REQUEST_TIMEOUT,
-        client.get(&format!("{}/get-root", base_url)).send(),
+        client.get(format!("{}/get-root", base_url)).send(),
     )
     .await??;
 
@@ -86,13 +85,14 @@ async fn get_root(
     Ok(())
 }
 
+#[allow(dead_code)]
 async fn get_num_leaves(
     client: &Client,
     base_url: &str,
 ) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
     let response = timeout(
         REQUEST_TIMEOUT,
-        client.get(&format!("{}/get-num-leaves", base_url)).send(),
+        client.get(format!("{}/get-num-leaves", base_url)).send(),
     )
     .await??;
 
@@ -112,7 +112,7 @@ async fn get_proof(
     let response = timeout(
         REQUEST_TIMEOUT,
         client
-            .post(&format!("{}/get-proof", base_url))
+            .post(format!("{}/get-proof", base_url))
             .json(&json!({ "index": index }))
             .send(),
     )
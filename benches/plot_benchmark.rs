@@ -33,7 +33,7 @@ async fn add_leaf_single(
     let response = timeout(
         REQUEST_TIMEOUT,
         client
-            .post(&format!("{}/add-leaf", base_url))
+            .post(format!("{}/add-leaf", base_url))
             .json(&json!({ "leaf": leaf }))
             .send(),
     )
@@ -53,7 +53,7 @@ async fn add_leaves_batch(
     let response = timeout(
         REQUEST_TIMEOUT,
         client
-            .post(&format!("{}/add-leaves", base_url))
+            .post(format!("{}/add-leaves", base_url))
             .json(&json!({ "leaves": leaves }))
             .send(),
     )
@@ -68,7 +68,7 @@ async fn add_leaves_batch(
 async fn get_root(client: &Client, base_url: &str) -> Result<(), Box<dyn std::error::Error>> {
     let response = timeout(
         REQUEST_TIMEOUT,
-        client.get(&format!("{}/get-root", base_url)).send(),
+        client.get(format!("{}/get-root", base_url)).send(),
     )
     .await??;
 
@@ -86,7 +86,7 @@ async fn get_proof(
     let response = timeout(
         REQUEST_TIMEOUT,
         client
-            .post(&format!("{}/get-proof", base_url))
+            .post(format!("{}/get-proof", base_url))
             .json(&json!({ "index": index }))
             .send(),
     )
@@ -166,7 +166,7 @@ fn bench_batch_sizes(c: &mut Criterion) {
     let mut group = c.benchmark_group("Batch Size Comparison");
 
     for &size in &[1, 5, 10, 25, 50, 100] {
-        group.bench_function(&format!("batch size {}", size), |b| {
+        group.bench_function(format!("batch size {}", size), |b| {
             b.iter(|| async {
                 let leaves: Vec<String> = (0..size)
                     .map(|_| format!("{:064x}", random::<u64>()))
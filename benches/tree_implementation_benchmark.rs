@@ -84,13 +84,10 @@ fn bench_write_operations(c: &mut Criterion) {
             BenchmarkId::new("heap_add_batch_leaves", batch_size),
             &batch_size,
             |b, &batch_size| {
-                b.iter_with_setup(
-                    || IncrementalMerkleTree::new(),
-                    |mut tree| {
-                        let leaves = generate_test_data(batch_size);
-                        tree.add_leaves(black_box(leaves)).unwrap();
-                    },
-                );
+                b.iter_with_setup(IncrementalMerkleTree::new, |mut tree| {
+                    let leaves = generate_test_data(batch_size);
+                    tree.add_leaves(black_box(leaves)).unwrap();
+                });
             },
         );
 
@@ -129,7 +126,7 @@ fn bench_read_operations(c: &mut Criterion) {
             |b, &tree_size| {
                 b.iter_with_setup(
                     || setup_heap_tree_with_data(tree_size),
-                    |mut tree| {
+                    |tree| {
                         black_box(tree.root());
                     },
                 );
@@ -143,7 +140,7 @@ fn bench_read_operations(c: &mut Criterion) {
                 b.iter_with_setup(
                     || setup_lmdb_tree_with_data(tree_size),
                     |(tree, _temp_dir)| {
-                        black_box(tree.root());
+                        black_box(tree.root().unwrap());
                     },
                 );
             },
@@ -170,7 +167,7 @@ fn bench_read_operations(c: &mut Criterion) {
                 b.iter_with_setup(
                     || setup_lmdb_tree_with_data(tree_size),
                     |(tree, _temp_dir)| {
-                        black_box(tree.num_leaves());
+                        black_box(tree.num_leaves().unwrap());
                     },
                 );
             },
@@ -188,7 +185,7 @@ fn bench_read_operations(c: &mut Criterion) {
                         |tree| {
                             let mut rng = rand::rng();
                             let index = rng.random_range(0..tree_size);
-                            black_box(tree.get_proof(black_box(index)));
+                            black_box(tree.get_proof(black_box(index)).unwrap());
                         },
                     );
                 },
@@ -203,7 +200,7 @@ fn bench_read_operations(c: &mut Criterion) {
                         |(tree, _temp_dir)| {
                             let mut rng = rand::rng();
                             let index = rng.random_range(0..tree_size);
-                            black_box(tree.get_proof(black_box(index)));
+                            black_box(tree.get_proof(black_box(index)).unwrap());
                         },
                     );
                 },
@@ -226,7 +223,7 @@ fn bench_proof_verification(c: &mut Criterion) {
             |b, &tree_size| {
                 b.iter_with_setup(
                     || {
-                        let mut tree = setup_heap_tree_with_data(tree_size);
+                        let tree = setup_heap_tree_with_data(tree_size);
                         let leaves = generate_test_data(tree_size);
                         let mut rng = rand::rng();
                         let index = rng.random_range(0..tree_size);
@@ -260,7 +257,7 @@ fn bench_proof_verification(c: &mut Criterion) {
                         let index = rng.random_range(0..tree_size);
                         let leaf = &leaves[index];
                         let proof = tree.get_proof(index).unwrap();
-                        let root = tree.root().unwrap();
+                        let root = tree.root().unwrap().unwrap();
                         (tree, temp_dir, leaf.clone(), proof, root, index)
                     },
                     |(tree, _temp_dir, leaf, proof, root, index)| {
@@ -312,7 +309,7 @@ fn bench_scaling_performance(c: &mut Criterion) {
                     |(tree, _temp_dir)| {
                         let leaves = generate_test_data(tree_size);
                         tree.add_leaves(black_box(leaves)).unwrap();
-                        black_box(tree.root());
+                        black_box(tree.root().unwrap());
                     },
                 );
             },
@@ -0,0 +1,148 @@
+//! Vector kept in fixed-size chunks behind `Arc`s.
+//!
+//! Cloning a `ChunkedVec` shares every chunk; a write copies the chunk it
+//! lands in only while another clone still holds it. The trees keep their
+//! leaves and cached levels in one, so a snapshot costs a pointer per chunk
+//! and the next write copies at most one chunk per level it touches.
+
+use std::ops::{Index, IndexMut};
+use std::sync::Arc;
+
+/// Elements per chunk.
+pub const CHUNK_SIZE: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkedVec<T> {
+    // Every chunk but the last holds exactly `CHUNK_SIZE` elements
+    chunks: Vec<Arc<Vec<T>>>,
+    len: usize,
+}
+
+impl<T> ChunkedVec<T> {
+    pub const fn new() -> Self {
+        Self {
+            chunks: Vec::new(),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.chunks.get(index / CHUNK_SIZE)?.get(index % CHUNK_SIZE)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.iter_from(0)
+    }
+
+    /// Elements from `start` on.
+    pub fn iter_from(&self, start: usize) -> impl Iterator<Item = &T> {
+        self.chunks
+            .get(start / CHUNK_SIZE..)
+            .unwrap_or_default()
+            .iter()
+            .flat_map(|chunk| chunk.iter())
+            .skip(start % CHUNK_SIZE)
+    }
+
+    /// The elements in order, one contiguous slice per chunk.
+    pub fn chunks(&self) -> impl Iterator<Item = &[T]> {
+        self.chunks.iter().map(|chunk| chunk.as_slice())
+    }
+}
+
+impl<T: Clone> ChunkedVec<T> {
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        let chunk = self.chunks.get_mut(index / CHUNK_SIZE)?;
+        Arc::make_mut(chunk).get_mut(index % CHUNK_SIZE)
+    }
+
+    pub fn push(&mut self, value: T) {
+        if self.len.is_multiple_of(CHUNK_SIZE) {
+            self.chunks.push(Arc::new(Vec::with_capacity(CHUNK_SIZE)));
+        }
+        let last = self.chunks.last_mut().expect("a chunk was just added");
+        Arc::make_mut(last).push(value);
+        self.len += 1;
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        if len >= self.len {
+            return;
+        }
+
+        self.chunks.truncate(len.div_ceil(CHUNK_SIZE));
+        let kept = self.chunks.len().saturating_sub(1) * CHUNK_SIZE;
+        if let Some(last) = self.chunks.last_mut()
+            && last.len() > len - kept
+        {
+            Arc::make_mut(last).truncate(len - kept);
+        }
+        self.len = len;
+    }
+
+    pub fn to_vec(&self) -> Vec<T> {
+        self.iter().cloned().collect()
+    }
+}
+
+impl<T> Default for ChunkedVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Index<usize> for ChunkedVec<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        let len = self.len;
+        self.get(index).unwrap_or_else(|| {
+            panic!(
+                "index out of bounds: the len is {} but the index is {}",
+                len, index
+            )
+        })
+    }
+}
+
+impl<T: Clone> IndexMut<usize> for ChunkedVec<T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        let len = self.len;
+        self.get_mut(index).unwrap_or_else(|| {
+            panic!(
+                "index out of bounds: the len is {} but the index is {}",
+                len, index
+            )
+        })
+    }
+}
+
+impl<T: Clone> Extend<T> for ChunkedVec<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.push(value);
+        }
+    }
+}
+
+impl<T: Clone> FromIterator<T> for ChunkedVec<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut vec = Self::new();
+        vec.extend(iter);
+        vec
+    }
+}
+
+impl<T: Clone> From<Vec<T>> for ChunkedVec<T> {
+    fn from(vec: Vec<T>) -> Self {
+        vec.into_iter().collect()
+    }
+}
@@ -0,0 +1,317 @@
+use crate::error::MerkleError;
+use crate::hasher::{Keccak256Hasher, MerkleHasher};
+use crate::nodes::{HASH_SIZE, Hash, to_hash};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Maximum height of a tree; fixed-depth trees may not exceed it.
+pub const MAX_LEVELS: usize = 32;
+pub const MAX_LEAVES: usize = 1 << MAX_LEVELS;
+
+/// Levels with at least this many nodes are hashed on the rayon thread pool.
+/// Smaller ones are cheaper to hash than to hand out to workers.
+pub const PARALLEL_LEVEL_SIZE: usize = 1 << 12;
+
+/// Number of children per node a tree may be built with.
+pub const SUPPORTED_ARITIES: [usize; 4] = [2, 4, 8, 16];
+
+// RFC 6962 domain separation prefixes
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+/// How leaves and internal nodes are turned into tree hashes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HashScheme {
+    /// Leaves are used as-is and nodes are `H(left || right)`, or the hash
+    /// of all children concatenated in wider trees.
+    #[default]
+    Raw,
+    /// Leaves are `H(0x00 || data)` and nodes are `H(0x01 || left || right)`,
+    /// so an internal node can never be presented as a leaf.
+    Rfc6962,
+    /// Leaves are `H(H(data))` and nodes hash the sorted pair
+    /// `H(min(a, b) || max(a, b))`, as in OpenZeppelin's `MerkleProof` and
+    /// `StandardMerkleTree`. Proofs then verify without the leaf index.
+    #[serde(rename = "openzeppelin")]
+    OpenZeppelin,
+}
+
+impl FromStr for HashScheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(Self::Raw),
+            "rfc6962" => Ok(Self::Rfc6962),
+            "openzeppelin" => Ok(Self::OpenZeppelin),
+            _ => Err(format!("Unknown hash scheme: {}", s)),
+        }
+    }
+}
+
+/// What happens to the last node of a level that has an odd number of nodes,
+/// or in a wider tree to the last group of a level that has fewer children
+/// than the arity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OddNodeStrategy {
+    /// Pair the node with itself. `[a, b, c]` and `[a, b, c, c]` share a root
+    /// (CVE-2012-2459), so only use this for compatibility. Wider trees fill
+    /// the group with copies of its last child.
+    #[default]
+    Duplicate,
+    /// Move the node up a level unchanged, as RFC 6962 does. Proofs have no
+    /// sibling for that level. Only binary trees can promote.
+    Promote,
+    /// Pair the node with the zero hash of its level.
+    ZeroPad,
+}
+
+impl FromStr for OddNodeStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "duplicate" => Ok(Self::Duplicate),
+            "promote" => Ok(Self::Promote),
+            "zero_pad" => Ok(Self::ZeroPad),
+            _ => Err(format!("Unknown odd node strategy: {}", s)),
+        }
+    }
+}
+
+/// Parameters that determine how a tree hashes its nodes. Trees built with
+/// different configs produce different roots, so LMDB trees persist them.
+#[derive(Debug, Clone)]
+pub struct TreeConfig {
+    pub hasher: Arc<dyn MerkleHasher>,
+    pub scheme: HashScheme,
+    pub odd_node: OddNodeStrategy,
+    /// When set, the tree always has this many levels above the leaves and
+    /// empty positions hold zero hashes, as in the Ethereum deposit contract.
+    /// Odd nodes are then always zero padded.
+    pub depth: Option<usize>,
+    /// Children per internal node, one of `SUPPORTED_ARITIES`. Proofs carry
+    /// `arity - 1` siblings per level.
+    pub arity: usize,
+}
+
+impl TreeConfig {
+    pub fn new<H: MerkleHasher + 'static>(hasher: H) -> Self {
+        Self {
+            hasher: Arc::new(hasher),
+            scheme: HashScheme::default(),
+            odd_node: OddNodeStrategy::default(),
+            depth: None,
+            arity: 2,
+        }
+    }
+
+    /// Config for a fixed-depth tree padded with zero hashes.
+    pub fn fixed_depth<H: MerkleHasher + 'static>(hasher: H, depth: usize) -> Self {
+        Self {
+            odd_node: OddNodeStrategy::ZeroPad,
+            depth: Some(depth),
+            ..Self::new(hasher)
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), MerkleError> {
+        let hash_size = self.hasher.hash(&[]).len();
+        if hash_size != HASH_SIZE {
+            return Err(MerkleError::InvalidArgument(format!(
+                "{} produces {}-byte hashes, expected {}",
+                self.hasher.id(),
+                hash_size,
+                HASH_SIZE
+            )));
+        }
+        if !SUPPORTED_ARITIES.contains(&self.arity) {
+            return Err(MerkleError::InvalidArgument(format!(
+                "Unsupported arity {}, expected one of {:?}",
+                self.arity, SUPPORTED_ARITIES
+            )));
+        }
+        if self.arity > 2 && self.scheme == HashScheme::OpenZeppelin {
+            return Err(MerkleError::InvalidArgument(
+                "The openzeppelin scheme hashes pairs and needs arity 2".to_string(),
+            ));
+        }
+        if self.arity > 2 && self.odd_node_strategy() == OddNodeStrategy::Promote {
+            return Err(MerkleError::InvalidArgument(
+                "The promote odd node strategy needs arity 2".to_string(),
+            ));
+        }
+
+        match self.depth {
+            Some(depth) if depth * self.arity.ilog2() as usize > MAX_LEVELS => {
+                Err(MerkleError::InvalidArgument(format!(
+                    "Tree depth {} with arity {} exceeds the maximum of {} leaves",
+                    depth, self.arity, MAX_LEAVES
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn max_leaves(&self) -> usize {
+        self.depth
+            .map_or(MAX_LEAVES, |depth| self.arity.pow(depth as u32))
+    }
+
+    /// Number of leaves under a node at `level`.
+    pub fn level_span(&self, level: usize) -> usize {
+        self.arity.pow(level as u32)
+    }
+
+    /// Whether `level`, holding `level_size` nodes, is the root level.
+    pub fn is_root_level(&self, level: usize, level_size: usize) -> bool {
+        match self.depth {
+            Some(depth) => level >= depth,
+            None => level_size <= 1,
+        }
+    }
+
+    /// Whether every earlier root is a combination of subtrees of the later
+    /// ones, as consistency proofs need: a binary tree without a fixed depth
+    /// that promotes unpaired nodes, like an RFC 9162 tree.
+    pub fn supports_consistency_proofs(&self) -> bool {
+        self.arity == 2 && self.depth.is_none() && self.odd_node == OddNodeStrategy::Promote
+    }
+
+    /// Root of a tree without leaves, if the tree has one.
+    pub fn empty_root(&self) -> Option<Vec<u8>> {
+        self.depth
+            .map(|depth| self.zero_hashes(depth).swap_remove(depth))
+    }
+
+    /// The strategy actually applied; fixed-depth trees always zero pad.
+    pub fn odd_node_strategy(&self) -> OddNodeStrategy {
+        match self.depth {
+            Some(_) => OddNodeStrategy::ZeroPad,
+            None => self.odd_node,
+        }
+    }
+
+    /// Hash stored at level 0 for `leaf`.
+    pub fn hash_leaf(&self, leaf: &[u8]) -> Vec<u8> {
+        match self.scheme {
+            HashScheme::Raw => leaf.to_vec(),
+            HashScheme::Rfc6962 => self.hasher.hash_parts(&[&[LEAF_PREFIX], leaf]),
+            HashScheme::OpenZeppelin => self.hasher.hash(&self.hasher.hash(leaf)),
+        }
+    }
+
+    /// Level 0 hashes for `leaves`, in order. Only for schemes that hash
+    /// their leaves: raw leaves need not be `HASH_SIZE` bytes.
+    pub fn hash_leaves<T: AsRef<[u8]> + Sync>(
+        &self,
+        leaves: &[T],
+    ) -> Result<Vec<Hash>, MerkleError> {
+        let hash_leaf = |leaf: &T| to_hash(self.hash_leaf(leaf.as_ref()));
+        if leaves.len() < PARALLEL_LEVEL_SIZE {
+            return leaves.iter().map(hash_leaf).collect();
+        }
+        leaves.par_iter().map(hash_leaf).collect()
+    }
+
+    /// Leaf for raw data sent by a client, chosen so that the data is hashed
+    /// exactly once by the scheme: raw trees store `H(data)`, while RFC 6962
+    /// and OpenZeppelin trees store the data itself and hash it as
+    /// `H(0x00 || data)` and `H(H(data))` respectively.
+    pub fn preimage_leaf(&self, preimage: &[u8]) -> Vec<u8> {
+        match self.scheme {
+            HashScheme::Raw => self.hasher.hash(preimage),
+            HashScheme::Rfc6962 | HashScheme::OpenZeppelin => preimage.to_vec(),
+        }
+    }
+
+    pub fn hash_node(&self, left: &[u8], right: &[u8]) -> Vec<u8> {
+        self.hash_nodes(&[left, right])
+    }
+
+    /// Hashes a parent from all `arity` of its children, in order.
+    pub fn hash_nodes(&self, children: &[&[u8]]) -> Vec<u8> {
+        match (self.scheme, children) {
+            (HashScheme::Rfc6962, _) => {
+                let mut parts = Vec::with_capacity(children.len() + 1);
+                parts.push([NODE_PREFIX].as_slice());
+                parts.extend_from_slice(children);
+                self.hasher.hash_parts(&parts)
+            }
+            (HashScheme::OpenZeppelin, &[left, right]) if left > right => {
+                self.hasher.hash_pair(right, left)
+            }
+            (_, &[left, right]) => self.hasher.hash_pair(left, right),
+            _ => self.hasher.hash_parts(children),
+        }
+    }
+
+    /// Hashes a parent from its children. The last group of a level may have
+    /// fewer than `arity` children; the odd node strategy then fills it, and
+    /// `zero` is the zero hash of the children's level.
+    pub fn hash_children<T: AsRef<[u8]>>(&self, children: &[T], zero: &[u8]) -> Vec<u8> {
+        let mut parts: Vec<&[u8]> = children.iter().map(AsRef::as_ref).collect();
+        if parts.len() < self.arity {
+            let last = parts[parts.len() - 1];
+            let filler = match self.odd_node_strategy() {
+                OddNodeStrategy::Duplicate => last,
+                OddNodeStrategy::Promote => return last.to_vec(),
+                OddNodeStrategy::ZeroPad => zero,
+            };
+            parts.resize(self.arity, filler);
+        }
+        self.hash_nodes(&parts)
+    }
+
+    /// Parents of `nodes`, a level whose groups start at its first node, with
+    /// `zero` the zero hash of that level. Each group is hashed on its own,
+    /// so the parallel path gives the same parents as the sequential one.
+    /// A node that the promote strategy moves up has no parent of its own
+    /// and is left out.
+    pub fn hash_level<T: AsRef<[u8]> + Sync>(
+        &self,
+        nodes: &[T],
+        zero: &[u8],
+    ) -> Result<Vec<Hash>, MerkleError> {
+        let nodes = match self.odd_node_strategy() {
+            OddNodeStrategy::Promote if nodes.len() % 2 == 1 => &nodes[..nodes.len() - 1],
+            _ => nodes,
+        };
+        let hash_group = |children: &[T]| to_hash(self.hash_children(children, zero));
+        if nodes.len() < PARALLEL_LEVEL_SIZE {
+            return nodes.chunks(self.arity).map(hash_group).collect();
+        }
+        nodes.par_chunks(self.arity).map(hash_group).collect()
+    }
+
+    /// Sibling recorded in a proof for a missing child of a group whose last
+    /// child is `last`, or `None` when the level is skipped.
+    pub fn unpaired_sibling(&self, last: &[u8], zero: &[u8]) -> Option<Vec<u8>> {
+        match self.odd_node_strategy() {
+            OddNodeStrategy::Duplicate => Some(last.to_vec()),
+            OddNodeStrategy::Promote => None,
+            OddNodeStrategy::ZeroPad => Some(zero.to_vec()),
+        }
+    }
+
+    /// Zero hashes for levels `0..=levels`: level 0 is all zero bytes and each
+    /// level above is the node hash of `arity` copies of the one below.
+    pub fn zero_hashes(&self, levels: usize) -> Vec<Vec<u8>> {
+        let mut zeros = vec![vec![0u8; self.hasher.hash(&[]).len()]];
+        for level in 0..levels {
+            let children = vec![zeros[level].as_slice(); self.arity];
+            zeros.push(self.hash_nodes(&children));
+        }
+        zeros
+    }
+}
+
+impl Default for TreeConfig {
+    fn default() -> Self {
+        Self::new(Keccak256Hasher)
+    }
+}
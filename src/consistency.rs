@@ -0,0 +1,84 @@
+#[cfg(feature = "server")]
+use crate::error::MerkleError;
+#[cfg(feature = "server")]
+use crate::history::{LevelView, missing_nodes};
+use serde::{Deserialize, Serialize};
+
+/// Proof that the tree with `old_size` leaves is a prefix of the tree with
+/// `new_size` leaves (RFC 9162, section 2.1.4).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConsistencyProof {
+    pub old_size: usize,
+    pub new_size: usize,
+    pub hashes: Vec<String>,
+}
+
+/// Builds the consistency proof between two sizes of the tree in `view`.
+///
+/// Only trees built like RFC 9162 trees, which promote unpaired nodes, have
+/// every earlier root as a combination of subtrees of the later ones.
+#[cfg(feature = "server")]
+pub(crate) fn build_consistency_proof(
+    view: &LevelView,
+    old_size: usize,
+    new_size: usize,
+) -> Result<ConsistencyProof, MerkleError> {
+    if !view.config.supports_consistency_proofs() {
+        return Err(MerkleError::InvalidArgument(
+            "Consistency proofs require a binary tree without a fixed depth that promotes odd nodes".to_string(),
+        ));
+    }
+    if old_size == 0 || old_size > new_size || new_size > view.num_leaves {
+        return Err(MerkleError::InvalidArgument(
+            "Invalid tree sizes for consistency proof".to_string(),
+        ));
+    }
+    view.check_provable(old_size)?;
+    view.check_provable(new_size)?;
+
+    let mut hashes = Vec::new();
+    subproof(view, old_size, 0, new_size, true, &mut hashes).ok_or_else(missing_nodes)?;
+
+    Ok(ConsistencyProof {
+        old_size,
+        new_size,
+        hashes: hashes.into_iter().map(hex::encode).collect(),
+    })
+}
+
+// SUBPROOF(m, D[start:end], complete) from RFC 9162, section 2.1.4.1
+#[cfg(feature = "server")]
+fn subproof(
+    view: &LevelView,
+    m: usize,
+    start: usize,
+    end: usize,
+    complete: bool,
+    hashes: &mut Vec<Vec<u8>>,
+) -> Option<()> {
+    let n = end - start;
+    if m == n {
+        if !complete {
+            hashes.push(subtree_hash(view, start, end)?);
+        }
+        return Some(());
+    }
+
+    // Largest power of two smaller than n
+    let k = 1 << (usize::BITS - 1 - (n - 1).leading_zeros());
+    if m <= k {
+        subproof(view, m, start, start + k, complete, hashes)?;
+        hashes.push(subtree_hash(view, start + k, end)?);
+    } else {
+        subproof(view, m - k, start + k, end, false, hashes)?;
+        hashes.push(subtree_hash(view, start, start + k)?);
+    }
+    Some(())
+}
+
+// MTH(D[start:end]); `start` is always aligned to the subtree's level
+#[cfg(feature = "server")]
+fn subtree_hash(view: &LevelView, start: usize, end: usize) -> Option<Vec<u8>> {
+    let level = (end - start).next_power_of_two().trailing_zeros() as usize;
+    view.node_at(level, start >> level, end)
+}
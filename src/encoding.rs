@@ -0,0 +1,362 @@
+//! Compact binary encoding of proofs.
+//!
+//! Every encoding starts with a one-byte kind tag. Integers are big-endian
+//! and the hashes of a proof are written as one block: the hash width as a
+//! `u8`, the number of hashes as a `u32`, then the raw hashes back to back.
+//! All hashes in a block must have the same, non-zero width. Decoding rejects
+//! unknown tags and trailing bytes, so each proof has exactly one encoding.
+
+use crate::config::{HashScheme, OddNodeStrategy};
+use crate::consistency::ConsistencyProof;
+use crate::error::MerkleError;
+use crate::multiproof::MerkleMultiProof;
+use crate::range_proof::RangeProof;
+use crate::verify::{InclusionProof, MerkleProof};
+
+const MERKLE_PROOF_TAG: u8 = 0x01;
+const INCLUSION_PROOF_TAG: u8 = 0x02;
+const MULTIPROOF_TAG: u8 = 0x03;
+const CONSISTENCY_PROOF_TAG: u8 = 0x04;
+const RANGE_PROOF_TAG: u8 = 0x05;
+
+// Stored in place of the depth of a tree without a fixed depth
+const NO_DEPTH: u8 = u8::MAX;
+
+/// Canonical binary form of a proof.
+pub trait BinaryEncoding: Sized {
+    fn encode(&self) -> Result<Vec<u8>, MerkleError>;
+
+    fn decode(bytes: &[u8]) -> Result<Self, MerkleError>;
+}
+
+impl BinaryEncoding for MerkleProof {
+    fn encode(&self) -> Result<Vec<u8>, MerkleError> {
+        let mut out = vec![MERKLE_PROOF_TAG];
+        write_hashes(&mut out, &self.siblings)?;
+        Ok(out)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, MerkleError> {
+        let mut reader = Reader::new(bytes, MERKLE_PROOF_TAG)?;
+        let siblings = reader.hashes()?;
+        reader.finish()?;
+        Ok(Self { siblings })
+    }
+}
+
+impl BinaryEncoding for InclusionProof {
+    fn encode(&self) -> Result<Vec<u8>, MerkleError> {
+        let mut out = vec![INCLUSION_PROOF_TAG];
+        out.push(self.version);
+        write_short_bytes(&mut out, self.hash_algorithm.as_bytes())?;
+        out.push(match self.hash_scheme {
+            HashScheme::Raw => 0,
+            HashScheme::Rfc6962 => 1,
+            HashScheme::OpenZeppelin => 2,
+        });
+        out.push(match self.odd_node {
+            OddNodeStrategy::Duplicate => 0,
+            OddNodeStrategy::Promote => 1,
+            OddNodeStrategy::ZeroPad => 2,
+        });
+        out.push(match self.depth {
+            Some(depth) => u8::try_from(depth)
+                .ok()
+                .filter(|&depth| depth != NO_DEPTH)
+                .ok_or_else(|| invalid_proof("Tree depth too large"))?,
+            None => NO_DEPTH,
+        });
+        out.push(u8::try_from(self.arity).map_err(|_| invalid_proof("Arity too large"))?);
+        out.extend_from_slice(&(self.tree_size as u64).to_be_bytes());
+        out.extend_from_slice(&(self.leaf_index as u64).to_be_bytes());
+
+        let leaf = hex::decode(&self.leaf).map_err(|_| invalid_proof("Invalid hex in proof"))?;
+        let leaf_len = u32::try_from(leaf.len()).map_err(|_| invalid_proof("Leaf too large"))?;
+        out.extend_from_slice(&leaf_len.to_be_bytes());
+        out.extend_from_slice(&leaf);
+
+        let root = hex::decode(&self.root).map_err(|_| invalid_proof("Invalid hex in proof"))?;
+        write_short_bytes(&mut out, &root)?;
+        write_hashes(&mut out, &self.siblings)?;
+        Ok(out)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, MerkleError> {
+        let mut reader = Reader::new(bytes, INCLUSION_PROOF_TAG)?;
+        let version = reader.u8()?;
+
+        let hash_algorithm = reader.short_bytes()?;
+        let hash_algorithm = String::from_utf8(hash_algorithm.to_vec())
+            .map_err(|_| invalid_proof("Invalid hash algorithm"))?;
+        let hash_scheme = match reader.u8()? {
+            0 => HashScheme::Raw,
+            1 => HashScheme::Rfc6962,
+            2 => HashScheme::OpenZeppelin,
+            _ => return Err(invalid_proof("Unknown hash scheme")),
+        };
+        let odd_node = match reader.u8()? {
+            0 => OddNodeStrategy::Duplicate,
+            1 => OddNodeStrategy::Promote,
+            2 => OddNodeStrategy::ZeroPad,
+            _ => return Err(invalid_proof("Unknown odd node strategy")),
+        };
+        let depth = match reader.u8()? {
+            NO_DEPTH => None,
+            depth => Some(usize::from(depth)),
+        };
+        let arity = usize::from(reader.u8()?);
+        let tree_size = reader.usize()?;
+        let leaf_index = reader.usize()?;
+
+        let leaf_len = reader.u32()? as usize;
+        let leaf = hex::encode(reader.take(leaf_len)?);
+        let root = hex::encode(reader.short_bytes()?);
+        let siblings = reader.hashes()?;
+        reader.finish()?;
+
+        Ok(Self {
+            version,
+            hash_algorithm,
+            hash_scheme,
+            odd_node,
+            depth,
+            arity,
+            tree_size,
+            leaf_index,
+            leaf,
+            root,
+            siblings,
+        })
+    }
+}
+
+impl BinaryEncoding for MerkleMultiProof {
+    fn encode(&self) -> Result<Vec<u8>, MerkleError> {
+        let mut out = vec![MULTIPROOF_TAG];
+        let count =
+            u32::try_from(self.indices.len()).map_err(|_| invalid_proof("Too many indices"))?;
+        out.extend_from_slice(&count.to_be_bytes());
+        for &index in &self.indices {
+            out.extend_from_slice(&(index as u64).to_be_bytes());
+        }
+        write_hashes(&mut out, &self.siblings)?;
+        Ok(out)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, MerkleError> {
+        let mut reader = Reader::new(bytes, MULTIPROOF_TAG)?;
+        let count = reader.u32()? as usize;
+        let indices = (0..count)
+            .map(|_| reader.usize())
+            .collect::<Result<Vec<_>, _>>()?;
+        let siblings = reader.hashes()?;
+        reader.finish()?;
+        Ok(Self { indices, siblings })
+    }
+}
+
+impl BinaryEncoding for ConsistencyProof {
+    fn encode(&self) -> Result<Vec<u8>, MerkleError> {
+        let mut out = vec![CONSISTENCY_PROOF_TAG];
+        out.extend_from_slice(&(self.old_size as u64).to_be_bytes());
+        out.extend_from_slice(&(self.new_size as u64).to_be_bytes());
+        write_hashes(&mut out, &self.hashes)?;
+        Ok(out)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, MerkleError> {
+        let mut reader = Reader::new(bytes, CONSISTENCY_PROOF_TAG)?;
+        let old_size = reader.usize()?;
+        let new_size = reader.usize()?;
+        let hashes = reader.hashes()?;
+        reader.finish()?;
+        Ok(Self {
+            old_size,
+            new_size,
+            hashes,
+        })
+    }
+}
+
+impl BinaryEncoding for RangeProof {
+    fn encode(&self) -> Result<Vec<u8>, MerkleError> {
+        let mut out = vec![RANGE_PROOF_TAG];
+        out.extend_from_slice(&(self.start as u64).to_be_bytes());
+        out.extend_from_slice(&(self.end as u64).to_be_bytes());
+        out.extend_from_slice(&(self.tree_size as u64).to_be_bytes());
+        write_hashes(&mut out, &self.siblings)?;
+        Ok(out)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, MerkleError> {
+        let mut reader = Reader::new(bytes, RANGE_PROOF_TAG)?;
+        let start = reader.usize()?;
+        let end = reader.usize()?;
+        let tree_size = reader.usize()?;
+        let siblings = reader.hashes()?;
+        reader.finish()?;
+        Ok(Self {
+            start,
+            end,
+            tree_size,
+            siblings,
+        })
+    }
+}
+
+/// Whether an `Accept` header value prefers the binary encoding,
+/// `application/octet-stream`, to JSON. Each type takes the quality of the
+/// most specific media range that matches it (RFC 9110, section 12.5.1);
+/// a type with `q=0` is refused and ties go to JSON.
+pub fn prefers_binary(accept: &str) -> bool {
+    let binary = media_quality(accept, "application", "octet-stream");
+    binary > 0.0 && binary > media_quality(accept, "application", "json")
+}
+
+fn media_quality(accept: &str, kind: &str, subtype: &str) -> f32 {
+    // (specificity, quality) of the best matching range so far
+    let mut best: Option<(u8, f32)> = None;
+
+    for range in accept.split(',') {
+        let mut parts = range.split(';');
+        let Some((range_kind, range_subtype)) =
+            parts.next().and_then(|media| media.split_once('/'))
+        else {
+            continue;
+        };
+        let (range_kind, range_subtype) = (range_kind.trim(), range_subtype.trim());
+        let specificity = if range_kind.eq_ignore_ascii_case(kind) {
+            if range_subtype.eq_ignore_ascii_case(subtype) {
+                2
+            } else if range_subtype == "*" {
+                1
+            } else {
+                continue;
+            }
+        } else if range_kind == "*" && range_subtype == "*" {
+            0
+        } else {
+            continue;
+        };
+
+        let quality = match parts
+            .filter_map(|param| param.split_once('='))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+        {
+            Some((_, value)) => match value.trim().parse::<f32>() {
+                Ok(quality) if (0.0..=1.0).contains(&quality) => quality,
+                _ => continue,
+            },
+            None => 1.0,
+        };
+
+        if best.is_none_or(|(best_specificity, _)| specificity > best_specificity) {
+            best = Some((specificity, quality));
+        }
+    }
+
+    best.map_or(0.0, |(_, quality)| quality)
+}
+
+fn invalid_proof(message: &str) -> MerkleError {
+    MerkleError::InvalidProof(message.to_string())
+}
+
+fn write_short_bytes(out: &mut Vec<u8>, bytes: &[u8]) -> Result<(), MerkleError> {
+    out.push(u8::try_from(bytes.len()).map_err(|_| invalid_proof("Field longer than 255 bytes"))?);
+    out.extend_from_slice(bytes);
+    Ok(())
+}
+
+fn write_hashes(out: &mut Vec<u8>, hashes: &[String]) -> Result<(), MerkleError> {
+    let hashes = hashes
+        .iter()
+        .map(hex::decode)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| invalid_proof("Invalid hex in proof"))?;
+
+    let width = hashes.first().map_or(0, Vec::len);
+    if hashes.iter().any(|hash| hash.len() != width) {
+        return Err(invalid_proof("Proof hashes differ in width"));
+    }
+    // A count of empty hashes could not be told apart from no hashes
+    if width == 0 && !hashes.is_empty() {
+        return Err(invalid_proof("Empty hash in proof"));
+    }
+
+    out.push(u8::try_from(width).map_err(|_| invalid_proof("Hash wider than 255 bytes"))?);
+    let count = u32::try_from(hashes.len()).map_err(|_| invalid_proof("Too many hashes"))?;
+    out.extend_from_slice(&count.to_be_bytes());
+    for hash in hashes {
+        out.extend_from_slice(&hash);
+    }
+    Ok(())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8], tag: u8) -> Result<Self, MerkleError> {
+        let mut reader = Self { bytes };
+        if reader.u8()? != tag {
+            return Err(invalid_proof("Unexpected proof kind"));
+        }
+        Ok(reader)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], MerkleError> {
+        if self.bytes.len() < len {
+            return Err(invalid_proof("Truncated proof"));
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, MerkleError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, MerkleError> {
+        let bytes = self.take(4)?.try_into().unwrap();
+        Ok(u32::from_be_bytes(bytes))
+    }
+
+    fn usize(&mut self) -> Result<usize, MerkleError> {
+        let bytes = self.take(8)?.try_into().unwrap();
+        usize::try_from(u64::from_be_bytes(bytes)).map_err(|_| invalid_proof("Value too large"))
+    }
+
+    fn short_bytes(&mut self) -> Result<&'a [u8], MerkleError> {
+        let len = usize::from(self.u8()?);
+        self.take(len)
+    }
+
+    fn hashes(&mut self) -> Result<Vec<String>, MerkleError> {
+        let width = usize::from(self.u8()?);
+        let count = self.u32()? as usize;
+        if count == 0 && width != 0 {
+            return Err(invalid_proof("Non-canonical empty hash list"));
+        }
+        if width == 0 && count != 0 {
+            return Err(invalid_proof("Zero-width hashes in proof"));
+        }
+
+        let bytes = self.take(
+            width
+                .checked_mul(count)
+                .ok_or_else(|| invalid_proof("Truncated proof"))?,
+        )?;
+        Ok(bytes.chunks(width.max(1)).map(hex::encode).collect())
+    }
+
+    fn finish(self) -> Result<(), MerkleError> {
+        if self.bytes.is_empty() {
+            Ok(())
+        } else {
+            Err(invalid_proof("Trailing bytes after proof"))
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use std::error::Error;
+use std::fmt;
+
+/// Error returned by the trees, their storage and the proof codecs.
+#[derive(Debug)]
+pub enum MerkleError {
+    /// The tree cannot take more leaves.
+    CapacityExceeded { max_leaves: usize },
+    /// A leaf index lies outside the tree of `size` leaves.
+    IndexOutOfRange { index: usize, size: usize },
+    /// No leaf of the tree has the value looked up.
+    LeafNotFound,
+    /// The request does not make sense for this tree, such as a tree size
+    /// larger than the tree or an invalid config.
+    InvalidArgument(String),
+    /// A stored tree was created with a config other than the one it is
+    /// opened with.
+    ConfigMismatch(String),
+    /// Reading or writing LMDB failed.
+    Storage(Box<dyn Error + Send + Sync>),
+    /// Stored data could not be decoded or does not fit the tree.
+    Corrupted(String),
+    /// A proof is malformed or cannot be represented.
+    InvalidProof(String),
+}
+
+impl MerkleError {
+    /// Stable machine-readable name of the error kind.
+    pub fn code(&self) -> &'static str {
+        match self {
+            MerkleError::CapacityExceeded { .. } => "capacity_exceeded",
+            MerkleError::IndexOutOfRange { .. } => "index_out_of_range",
+            MerkleError::LeafNotFound => "leaf_not_found",
+            MerkleError::InvalidArgument(_) => "invalid_argument",
+            MerkleError::ConfigMismatch(_) => "config_mismatch",
+            MerkleError::Storage(_) => "storage_error",
+            MerkleError::Corrupted(_) => "corrupted_data",
+            MerkleError::InvalidProof(_) => "invalid_proof",
+        }
+    }
+
+    /// HTTP status code the server answers this error with.
+    pub fn http_status(&self) -> u16 {
+        match self {
+            MerkleError::CapacityExceeded { .. } | MerkleError::ConfigMismatch(_) => 409,
+            MerkleError::IndexOutOfRange { .. } | MerkleError::LeafNotFound => 404,
+            MerkleError::InvalidArgument(_) => 400,
+            MerkleError::InvalidProof(_) => 422,
+            MerkleError::Storage(_) | MerkleError::Corrupted(_) => 500,
+        }
+    }
+}
+
+impl fmt::Display for MerkleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MerkleError::CapacityExceeded { max_leaves } => write!(
+                f,
+                "Exceeded max number of leaves in merkle tree ({})",
+                max_leaves
+            ),
+            MerkleError::IndexOutOfRange { index, size } => write!(
+                f,
+                "Leaf index {} out of range for a tree of {} leaves",
+                index, size
+            ),
+            MerkleError::LeafNotFound => write!(f, "Leaf not found in the tree"),
+            MerkleError::InvalidArgument(message) => write!(f, "{}", message),
+            MerkleError::ConfigMismatch(message) => write!(f, "Config mismatch: {}", message),
+            MerkleError::Storage(e) => write!(f, "Storage error: {}", e),
+            MerkleError::Corrupted(message) => write!(f, "Corrupted data: {}", message),
+            MerkleError::InvalidProof(message) => write!(f, "Invalid proof: {}", message),
+        }
+    }
+}
+
+impl Error for MerkleError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MerkleError::Storage(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+impl From<lmdb::Error> for MerkleError {
+    fn from(e: lmdb::Error) -> Self {
+        MerkleError::Storage(Box::new(e))
+    }
+}
+
+#[cfg(feature = "server")]
+impl From<bincode::Error> for MerkleError {
+    fn from(e: bincode::Error) -> Self {
+        MerkleError::Corrupted(e.to_string())
+    }
+}
@@ -0,0 +1,102 @@
+use sha2::Sha256;
+use sha3::{Digest, Keccak256, Sha3_256};
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// Hash function used to build and verify Merkle trees.
+///
+/// Implementations must be deterministic: the same parts always produce the
+/// same digest. `id` is persisted in LMDB metadata, so it must stay stable.
+pub trait MerkleHasher: Debug + Send + Sync {
+    fn id(&self) -> &str;
+
+    /// Hashes the concatenation of `parts` without allocating the joined input.
+    fn hash_parts(&self, parts: &[&[u8]]) -> Vec<u8>;
+
+    fn hash(&self, data: &[u8]) -> Vec<u8> {
+        self.hash_parts(&[data])
+    }
+
+    fn hash_pair(&self, left: &[u8], right: &[u8]) -> Vec<u8> {
+        self.hash_parts(&[left, right])
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Keccak256Hasher;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sha256Hasher;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sha3_256Hasher;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Blake3Hasher;
+
+impl MerkleHasher for Keccak256Hasher {
+    fn id(&self) -> &str {
+        "keccak256"
+    }
+
+    fn hash_parts(&self, parts: &[&[u8]]) -> Vec<u8> {
+        let mut hasher = Keccak256::new();
+        for part in parts {
+            hasher.update(part);
+        }
+        hasher.finalize().to_vec()
+    }
+}
+
+impl MerkleHasher for Sha256Hasher {
+    fn id(&self) -> &str {
+        "sha256"
+    }
+
+    fn hash_parts(&self, parts: &[&[u8]]) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        for part in parts {
+            hasher.update(part);
+        }
+        hasher.finalize().to_vec()
+    }
+}
+
+impl MerkleHasher for Sha3_256Hasher {
+    fn id(&self) -> &str {
+        "sha3-256"
+    }
+
+    fn hash_parts(&self, parts: &[&[u8]]) -> Vec<u8> {
+        let mut hasher = Sha3_256::new();
+        for part in parts {
+            hasher.update(part);
+        }
+        hasher.finalize().to_vec()
+    }
+}
+
+impl MerkleHasher for Blake3Hasher {
+    fn id(&self) -> &str {
+        "blake3"
+    }
+
+    fn hash_parts(&self, parts: &[&[u8]]) -> Vec<u8> {
+        let mut hasher = blake3::Hasher::new();
+        for part in parts {
+            hasher.update(part);
+        }
+        hasher.finalize().as_bytes().to_vec()
+    }
+}
+
+/// Looks up one of the built-in hashers by its `id`.
+pub fn hasher_from_id(id: &str) -> Option<Arc<dyn MerkleHasher>> {
+    match id {
+        "keccak256" => Some(Arc::new(Keccak256Hasher)),
+        "sha256" => Some(Arc::new(Sha256Hasher)),
+        "sha3-256" => Some(Arc::new(Sha3_256Hasher)),
+        "blake3" => Some(Arc::new(Blake3Hasher)),
+        _ => None,
+    }
+}
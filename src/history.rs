@@ -0,0 +1,283 @@
+use crate::chunked::ChunkedVec;
+use crate::config::{OddNodeStrategy, TreeConfig};
+use crate::error::MerkleError;
+use crate::nodes::{LeafUpdate, Level, caches_leaves};
+use crate::verify::MerkleProof;
+use std::collections::BTreeMap;
+
+/// Read-only view of the cached levels of a tree with `num_leaves` leaves
+/// that can rebuild the nodes of the tree as it was at any earlier size.
+///
+/// A cached node at `level` and `index` covers the leaves
+/// `[index * span, min((index + 1) * span, num_leaves))`, where `span` is
+/// `arity^level`. It is also
+/// the node of an earlier tree of `size` leaves whenever that range ends at
+/// or before `size`; only the nodes on the right edge of the earlier tree
+/// have to be rehashed, which takes O(log n) per node.
+///
+/// A leaf updated after the tree outgrew `size` does not change the root at
+/// `size`: `updates` keeps the value it replaced, and the nodes above it are
+/// rehashed with that value. Proofs are built from the current nodes and
+/// fail for such a size.
+pub(crate) struct LevelView<'a> {
+    pub config: &'a TreeConfig,
+    pub zero_hashes: &'a [Vec<u8>],
+    /// Level 0 of a raw-scheme tree; other trees cache their leaf hashes
+    /// and may leave this empty.
+    pub leaves: &'a ChunkedVec<Vec<u8>>,
+    pub levels: &'a [Level],
+    /// Every leaf update, oldest first.
+    pub updates: &'a ChunkedVec<LeafUpdate>,
+    pub num_leaves: usize,
+}
+
+impl<'a> LevelView<'a> {
+    /// Number of nodes at `level` of the full tree.
+    pub fn level_size(&self, level: usize) -> usize {
+        self.num_leaves.div_ceil(self.config.level_span(level))
+    }
+
+    /// Node `index` of `level` of the full tree, as cached.
+    pub fn cached_node(&self, level: usize, index: usize) -> Option<&'a [u8]> {
+        if level == 0 && !caches_leaves(self.config) {
+            return self.leaves.get(index).map(Vec::as_slice);
+        }
+
+        let stored = self.levels.get(level)?;
+        if let Some(hash) = stored.get(index) {
+            return Some(hash);
+        }
+
+        // A promoted node is not stored again; it is the last node below
+        let promoted = level > 0
+            && self.config.odd_node_strategy() == OddNodeStrategy::Promote
+            && index == stored.len()
+            && index * 2 + 1 == self.level_size(level - 1);
+        if promoted {
+            self.cached_node(level - 1, index * 2)
+        } else {
+            None
+        }
+    }
+
+    /// Root of the full tree, as cached.
+    pub fn cached_root(&self) -> Option<Vec<u8>> {
+        if self.num_leaves == 0 {
+            return self.config.empty_root();
+        }
+        self.cached_node(self.levels.len().checked_sub(1)?, 0)
+            .map(<[u8]>::to_vec)
+    }
+
+    /// Whether the cache holds every node of the full tree up to its root.
+    pub fn is_complete(&self) -> bool {
+        if self.num_leaves == 0 {
+            return true;
+        }
+
+        let mut level = 0;
+        while !self.config.is_root_level(level, self.level_size(level)) {
+            level += 1;
+        }
+        self.levels.len() == level + 1
+            && (0..=level).all(|level| {
+                let size = self.level_size(level);
+                size == 0 || self.cached_node(level, size - 1).is_some()
+            })
+    }
+
+    /// Root of the tree as it was when it last had `size` leaves, before
+    /// any leaf was updated at a larger size.
+    pub fn root_at(&self, size: usize) -> Result<Vec<u8>, MerkleError> {
+        self.check_size(size)?;
+        if size == 0 {
+            return self.config.empty_root().ok_or_else(|| {
+                MerkleError::InvalidArgument("A tree without leaves has no root".to_string())
+            });
+        }
+
+        let mut level = 0;
+        while !self
+            .config
+            .is_root_level(level, size.div_ceil(self.config.level_span(level)))
+        {
+            level += 1;
+        }
+        let previous = self.previous_leaves(size);
+        self.node_before(level, 0, size, &previous)
+            .ok_or_else(missing_nodes)
+    }
+
+    /// Fails if a leaf below `size` was updated once the tree had grown past
+    /// it: proofs for `size` are built from the current leaves and would not
+    /// match its root.
+    pub fn check_provable(&self, size: usize) -> Result<(), MerkleError> {
+        let first = self.first_update_after(size);
+        if self
+            .updates
+            .iter_from(first)
+            .any(|update| update.index < size)
+        {
+            return Err(MerkleError::InvalidArgument(format!(
+                "Leaves of the tree at size {} were updated since, so its root cannot be proven",
+                size
+            )));
+        }
+        Ok(())
+    }
+
+    // Position of the first update made once the tree had more than `size`
+    // leaves. Updates are logged as the tree grows, so their sizes never
+    // decrease.
+    fn first_update_after(&self, size: usize) -> usize {
+        let (mut low, mut high) = (0, self.updates.len());
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if self.updates[mid].size <= size {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        low
+    }
+
+    // Leaves below `size` that were updated once the tree had grown past it,
+    // with the value they had before the first such update
+    fn previous_leaves(&self, size: usize) -> BTreeMap<usize, &'a [u8]> {
+        let mut previous = BTreeMap::new();
+        for update in self.updates.iter_from(self.first_update_after(size)) {
+            if update.index < size {
+                previous
+                    .entry(update.index)
+                    .or_insert(update.previous.as_slice());
+            }
+        }
+        previous
+    }
+
+    // Like `node_at`, with the leaves in `previous` put back first
+    fn node_before(
+        &self,
+        level: usize,
+        index: usize,
+        size: usize,
+        previous: &BTreeMap<usize, &[u8]>,
+    ) -> Option<Vec<u8>> {
+        let span = self.config.level_span(level);
+        let start = index * span;
+        if previous
+            .range(start..start.saturating_add(span))
+            .next()
+            .is_none()
+        {
+            return self.node_at(level, index, size);
+        }
+
+        if level == 0 {
+            let leaf = previous[&index];
+            return Some(if caches_leaves(self.config) {
+                self.config.hash_leaf(leaf)
+            } else {
+                leaf.to_vec()
+            });
+        }
+        let arity = self.config.arity;
+        let children: Vec<Vec<u8>> = (index * arity..(index + 1) * arity)
+            .map_while(|child| self.node_before(level - 1, child, size, previous))
+            .collect();
+        Some(
+            self.config
+                .hash_children(&children, &self.zero_hashes[level - 1]),
+        )
+    }
+
+    /// Inclusion proof for leaf `index` in the tree as it was with `size`
+    /// leaves.
+    pub fn proof_at(&self, index: usize, size: usize) -> Result<MerkleProof, MerkleError> {
+        self.check_size(size)?;
+        if index >= size {
+            return Err(MerkleError::IndexOutOfRange { index, size });
+        }
+        self.check_provable(size)?;
+
+        let arity = self.config.arity;
+        let mut siblings = Vec::new();
+        let mut current_index = index;
+        let mut level_size = size;
+        let mut current_level = 0;
+
+        while !self.config.is_root_level(current_level, level_size) {
+            let group_start = current_index - current_index % arity;
+            let group_end = level_size.min(group_start + arity);
+
+            for sibling_index in (group_start..group_end).filter(|&i| i != current_index) {
+                let sibling = self
+                    .node_at(current_level, sibling_index, size)
+                    .ok_or_else(missing_nodes)?;
+                siblings.push(hex::encode(sibling));
+            }
+
+            if group_end - group_start < arity {
+                // Short group: duplicate the last child, pad with zero, or skip
+                // the level
+                let last = self
+                    .node_at(current_level, group_end - 1, size)
+                    .ok_or_else(missing_nodes)?;
+                let zero = &self.zero_hashes[current_level];
+                if let Some(filler) = self.config.unpaired_sibling(&last, zero) {
+                    let missing = group_start + arity - group_end;
+                    siblings.extend(std::iter::repeat_n(hex::encode(filler), missing));
+                }
+            }
+
+            current_index /= arity;
+            level_size = level_size.div_ceil(arity);
+            current_level += 1;
+        }
+
+        Ok(MerkleProof { siblings })
+    }
+
+    pub fn check_size(&self, size: usize) -> Result<(), MerkleError> {
+        if size > self.num_leaves {
+            return Err(MerkleError::InvalidArgument(format!(
+                "Tree size {} exceeds the {} leaves of the tree",
+                size, self.num_leaves
+            )));
+        }
+        Ok(())
+    }
+
+    /// Node `index` of `level` in the tree as it was with `size` leaves, or
+    /// `None` if that tree has no such node.
+    pub fn node_at(&self, level: usize, index: usize, size: usize) -> Option<Vec<u8>> {
+        let span = self.config.level_span(level);
+        let start = index * span;
+        if start >= size || size > self.num_leaves {
+            return None;
+        }
+
+        if (start + span).min(self.num_leaves) <= size {
+            return self.cached_node(level, index).map(<[u8]>::to_vec);
+        }
+
+        // The cached node also covers leaves appended after `size`
+        let arity = self.config.arity;
+        let children: Vec<Vec<u8>> = (index * arity..(index + 1) * arity)
+            .map_while(|child| self.node_at(level - 1, child, size))
+            .collect();
+        if children.is_empty() {
+            return None;
+        }
+        Some(
+            self.config
+                .hash_children(&children, &self.zero_hashes[level - 1]),
+        )
+    }
+}
+
+/// Error for a cache that lacks nodes the tree should have.
+pub(crate) fn missing_nodes() -> MerkleError {
+    MerkleError::Corrupted("Tree cache is missing nodes".to_string())
+}
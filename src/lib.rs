@@ -1,6 +1,52 @@
+pub mod chunked;
+pub mod config;
+pub mod consistency;
+pub mod encoding;
+pub mod error;
+pub mod hasher;
+#[cfg(feature = "server")]
+mod history;
+#[cfg(feature = "server")]
+pub mod lmdb_mmr;
+#[cfg(feature = "server")]
 pub mod lmdb_tree;
+#[cfg(feature = "server")]
 pub mod merkle_tree;
+pub mod mmr;
+pub mod multiproof;
+pub mod nodes;
+pub mod preimage;
+pub mod range_proof;
+#[cfg(feature = "server")]
+pub mod snapshot;
+pub mod sparse_tree;
+pub mod standard_tree;
+#[cfg(feature = "server")]
 pub mod storage;
+#[cfg(feature = "server")]
+pub mod tree;
+pub mod verify;
+pub use chunked::*;
+pub use config::*;
+pub use consistency::*;
+pub use encoding::*;
+pub use error::*;
+pub use hasher::*;
+#[cfg(feature = "server")]
 pub use lmdb_tree::*;
+#[cfg(feature = "server")]
 pub use merkle_tree::*;
+pub use mmr::*;
+pub use multiproof::*;
+pub use nodes::*;
+pub use preimage::*;
+pub use range_proof::*;
+#[cfg(feature = "server")]
+pub use snapshot::*;
+pub use sparse_tree::*;
+pub use standard_tree::*;
+#[cfg(feature = "server")]
 pub use storage::*;
+#[cfg(feature = "server")]
+pub use tree::*;
+pub use verify::*;
@@ -0,0 +1,53 @@
+//! LMDB persistence for `MerkleMountainRange`.
+
+use crate::config::TreeConfig;
+use crate::error::MerkleError;
+use crate::mmr::{MerkleMountainRange, MmrStore, validate_config};
+use crate::storage::{LmdbStorage, MmrMetadata};
+
+impl MerkleMountainRange<LmdbStorage> {
+    /// Opens the MMR at `storage_path`, keeping the config it was created with.
+    pub fn new_with_storage(storage_path: &str) -> Result<Self, MerkleError> {
+        let storage = LmdbStorage::new(storage_path)?;
+        let config = match storage.get_mmr_metadata()? {
+            Some(metadata) => metadata.config()?,
+            None => TreeConfig::default(),
+        };
+        Self::open(storage, config)
+    }
+
+    /// Opens the MMR at `storage_path`, failing if it was created with a
+    /// different config.
+    pub fn new_with_storage_and_config(
+        storage_path: &str,
+        config: TreeConfig,
+    ) -> Result<Self, MerkleError> {
+        let storage = LmdbStorage::new(storage_path)?;
+        if let Some(metadata) = storage.get_mmr_metadata()? {
+            metadata.check_config(&config)?;
+        }
+        Self::open(storage, config)
+    }
+
+    fn open(storage: LmdbStorage, config: TreeConfig) -> Result<Self, MerkleError> {
+        validate_config(&config)?;
+        if storage.get_mmr_metadata()?.is_none() {
+            storage.store_mmr_metadata(&MmrMetadata::new(&config))?;
+        }
+        Self::with_store(storage, config)
+    }
+}
+
+impl MmrStore for LmdbStorage {
+    fn size(&self) -> Result<usize, MerkleError> {
+        self.get_mmr_size()
+    }
+
+    fn get_node(&self, pos: usize) -> Result<Option<Vec<u8>>, MerkleError> {
+        self.get_mmr_node(pos)
+    }
+
+    fn append_nodes(&mut self, first_pos: usize, nodes: &[Vec<u8>]) -> Result<(), MerkleError> {
+        self.append_mmr_nodes(first_pos, nodes)
+    }
+}
@@ -1,131 +1,261 @@
-use crate::merkle_tree::MerkleProof;
+use crate::chunked::ChunkedVec;
+use crate::config::{MAX_LEVELS, TreeConfig};
+use crate::consistency::{self, ConsistencyProof};
+use crate::error::MerkleError;
+use crate::history::{LevelView, missing_nodes};
+use crate::multiproof::{self, MerkleMultiProof};
+use crate::nodes::{self, LeafUpdate, Level};
+use crate::range_proof::{self, RangeProof};
+use crate::snapshot::TreeSnapshot;
 use crate::storage::{LmdbStorage, TreeMetadata};
-use sha3::{Digest, Keccak256};
-
-const MAX_LEVELS: usize = 32;
-const MAX_LEAVES: usize = 1 << MAX_LEVELS;
+use crate::tree::MerkleTree;
+use crate::verify::{self, InclusionProof, MerkleProof};
+use arc_swap::ArcSwapOption;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+// Leaves, cached levels and leaf updates as stored; like the updates in the
+// database, `updates` is never pruned
+#[derive(Debug, Clone)]
+struct LoadedTree {
+    leaves: ChunkedVec<Vec<u8>>,
+    levels: Vec<Level>,
+    updates: ChunkedVec<LeafUpdate>,
+    num_leaves: usize,
+}
 
 #[derive(Debug)]
 pub struct LmdbMerkleTree {
-    storage: LmdbStorage,
+    // Shared with snapshots, which read preimages and leaf positions from it
+    storage: Arc<LmdbStorage>,
+    // The tree as last read or written, shared chunk by chunk with
+    // snapshots so that taking one does not read storage again
+    loaded: ArcSwapOption<LoadedTree>,
+    // Held by writers from reading `loaded` until they publish their copy,
+    // so that concurrent writes cannot start from the same tree
+    write_lock: Mutex<()>,
     max_leaves: usize,
+    config: TreeConfig,
+    zero_hashes: Arc<[Vec<u8>]>,
 }
 
 impl LmdbMerkleTree {
-    pub fn new(storage_path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+    /// Opens the tree at `storage_path`, keeping the config it was created with.
+    pub fn new(storage_path: &str) -> Result<Self, MerkleError> {
+        let storage = LmdbStorage::new(storage_path)?;
+        let config = match storage.get_metadata()? {
+            Some(metadata) => metadata.config()?,
+            None => TreeConfig::default(),
+        };
+        Self::open(storage, config)
+    }
+
+    /// Opens the tree at `storage_path`, failing if it was created with a
+    /// different config.
+    pub fn with_config(storage_path: &str, config: TreeConfig) -> Result<Self, MerkleError> {
         let storage = LmdbStorage::new(storage_path)?;
+        if let Some(metadata) = storage.get_metadata()? {
+            metadata.check_config(&config)?;
+        }
+        Self::open(storage, config)
+    }
 
-        let max_leaves = if let Some(metadata) = storage.get_metadata()? {
-            metadata.max_leaves
-        } else {
-            MAX_LEAVES
+    fn open(storage: LmdbStorage, config: TreeConfig) -> Result<Self, MerkleError> {
+        config.validate()?;
+
+        let max_leaves = match storage.get_metadata()? {
+            Some(metadata) => metadata.max_leaves,
+            None => {
+                // Record the config right away so a reopened empty tree keeps it
+                let max_leaves = config.max_leaves();
+                let num_leaves = storage.get_all_leaves()?.len();
+                storage.store_metadata(&TreeMetadata::new(num_leaves, max_leaves, &config))?;
+                max_leaves
+            }
         };
 
-        Ok(Self {
-            storage,
+        let tree = Self {
+            storage: Arc::new(storage),
+            loaded: ArcSwapOption::empty(),
+            write_lock: Mutex::new(()),
             max_leaves,
-        })
-    }
+            zero_hashes: config.zero_hashes(MAX_LEVELS).into(),
+            config,
+        };
 
-    pub fn add_leaf(&self, leaf: Vec<u8>) -> Result<(), &'static str> {
-        let current_count = self.num_leaves();
-        if current_count >= self.max_leaves {
-            return Err("Exceeded max number of leaves in merkle tree");
+        let num_leaves = tree.num_leaves()?;
+        tree.check_leaf_index(num_leaves)?;
+
+        // Caches written in an older layout are rebuilt
+        let mut loaded = (*tree.load()?).clone();
+        if !tree.level_view(&loaded).is_complete() {
+            loaded.levels.clear();
+            nodes::extend_levels(
+                &tree.config,
+                &tree.zero_hashes,
+                &mut loaded.levels,
+                &loaded.leaves,
+                0,
+            )?;
+            let mut batch = tree.storage.begin_batch()?;
+            batch.store_cache_batch(&loaded.levels)?;
+            if let Some(root) = tree.level_view(&loaded).cached_root() {
+                batch.store_root(&root)?;
+            }
+            batch.commit()?;
+            tree.loaded.store(Some(Arc::new(loaded)));
         }
 
-        self.storage
-            .store_leaf(current_count, &leaf)
-            .map_err(|_| "Failed to store leaf")?;
-
-        self.recompute_and_store_tree()
-            .map_err(|_| "Failed to recompute tree")?;
+        Ok(tree)
+    }
 
-        Ok(())
+    pub fn config(&self) -> &TreeConfig {
+        &self.config
     }
 
-    pub fn add_leaves(&self, leaves: Vec<Vec<u8>>) -> Result<(), &'static str> {
-        let current_count = self.num_leaves();
-        if current_count + leaves.len() > self.max_leaves {
-            return Err("Exceeded max number of leaves in merkle tree");
-        }
+    pub fn add_leaf(&self, leaf: Vec<u8>) -> Result<(), MerkleError> {
+        self.add_leaves(vec![leaf])
+    }
 
-        self.storage
-            .append_leaves(current_count, &leaves)
-            .map_err(|_| "Failed to store leaves")?;
+    pub fn add_leaves(&self, leaves: Vec<Vec<u8>>) -> Result<(), MerkleError> {
+        self.append_leaves(&leaves, None)
+    }
 
-        self.recompute_and_store_tree()
-            .map_err(|_| "Failed to recompute tree")?;
+    /// Appends the leaf for `preimage`, as given by
+    /// `TreeConfig::preimage_leaf`, and returns it. With `keep_preimage` the
+    /// preimage is stored so it can be returned with proofs.
+    pub fn add_leaf_preimage(
+        &self,
+        preimage: Vec<u8>,
+        keep_preimage: bool,
+    ) -> Result<Vec<u8>, MerkleError> {
+        let mut leaves = self.add_leaf_preimages(vec![preimage], keep_preimage)?;
+        Ok(leaves.remove(0))
+    }
 
-        Ok(())
+    pub fn add_leaf_preimages(
+        &self,
+        preimages: Vec<Vec<u8>>,
+        keep_preimages: bool,
+    ) -> Result<Vec<Vec<u8>>, MerkleError> {
+        let leaves: Vec<Vec<u8>> = preimages
+            .iter()
+            .map(|preimage| self.config.preimage_leaf(preimage))
+            .collect();
+        self.append_leaves(&leaves, keep_preimages.then_some(preimages.as_slice()))?;
+        Ok(leaves)
     }
 
-    pub fn num_leaves(&self) -> usize {
-        if let Ok(Some(metadata)) = self.storage.get_metadata() {
-            metadata.num_leaves
-        } else {
-            self.storage
-                .get_all_leaves()
-                .map(|leaves| leaves.len())
-                .unwrap_or(0)
+    /// Preimage of leaf `index`, if one hashing to it was added with
+    /// `keep_preimage`.
+    pub fn get_preimage(&self, index: usize) -> Result<Option<Vec<u8>>, MerkleError> {
+        match self.load()?.leaves.get(index) {
+            Some(leaf) => self.storage.get_preimage(leaf),
+            None => Ok(None),
         }
     }
 
-    pub fn root(&self) -> Option<Vec<u8>> {
-        if self.num_leaves() == 0 {
-            return None;
+    /// Replaces the leaf at `index`, rehashing only its path to the root.
+    pub fn update_leaf(&self, index: usize, leaf: Vec<u8>) -> Result<(), MerkleError> {
+        let _writer = self.lock_writes();
+        let num_leaves = self.num_leaves()?;
+        if index >= num_leaves {
+            return Err(MerkleError::IndexOutOfRange {
+                index,
+                size: num_leaves,
+            });
         }
 
-        self.storage.get_root().ok().flatten()
+        let mut loaded = (*self.load()?).clone();
+        let update = LeafUpdate {
+            index,
+            size: num_leaves,
+            previous: std::mem::replace(&mut loaded.leaves[index], leaf),
+        };
+        nodes::update_levels(
+            &self.config,
+            &self.zero_hashes,
+            &mut loaded.levels,
+            &loaded.leaves,
+            index,
+        )?;
+        let root = self
+            .level_view(&loaded)
+            .cached_root()
+            .ok_or_else(missing_nodes)?;
+
+        let mut batch = self.storage.begin_batch()?;
+        batch.store_leaf(index, &loaded.leaves[index])?;
+        batch.store_leaf_update(loaded.updates.len(), &update)?;
+        batch.store_cache_nodes(&loaded.levels, index..index + 1, self.config.arity)?;
+        batch.store_root(&root)?;
+        batch.commit()?;
+
+        loaded.updates.push(update);
+        self.loaded.store(Some(Arc::new(loaded)));
+        self.storage.sync()
     }
 
-    pub fn get_proof(&self, index: usize) -> Option<MerkleProof> {
-        let num_leaves = self.num_leaves();
-        if index >= num_leaves {
-            return None;
-        }
+    /// Indices of the leaves equal to `leaf`, in ascending order.
+    pub fn get_leaf_indices(&self, leaf: &[u8]) -> Result<Vec<usize>, MerkleError> {
+        self.storage.get_leaf_indices(leaf, self.num_leaves()?)
+    }
 
-        let cache_levels = self.storage.get_all_cache_levels().ok()?;
-        if cache_levels.is_empty() {
-            return None;
+    pub fn num_leaves(&self) -> Result<usize, MerkleError> {
+        match self.storage.get_metadata()? {
+            Some(metadata) => Ok(metadata.num_leaves),
+            None => Ok(self.storage.get_all_leaves()?.len()),
         }
+    }
 
-        let mut siblings = Vec::new();
-        let mut current_index = index;
-        let mut current_level = 0;
+    pub fn root(&self) -> Result<Option<Vec<u8>>, MerkleError> {
+        if self.num_leaves()? == 0 {
+            return Ok(self.config.empty_root());
+        }
 
-        for _ in 0..MAX_LEVELS {
-            let level_hashes = cache_levels.get(current_level)?;
-            let level_size = if current_level == 0 {
-                num_leaves
-            } else {
-                level_hashes.len()
-            };
+        match self.storage.get_root()? {
+            Some(root) => Ok(Some(root)),
+            None => Err(MerkleError::Corrupted(
+                "Root is missing from storage".to_string(),
+            )),
+        }
+    }
 
-            if level_size <= 1 {
-                break;
-            }
+    pub fn get_proof(&self, index: usize) -> Result<MerkleProof, MerkleError> {
+        self.get_proof_at(index, self.num_leaves()?)
+    }
 
-            let sibling_index = if current_index % 2 == 0 {
-                current_index + 1
-            } else {
-                current_index - 1
-            };
-
-            if sibling_index < level_size {
-                if let Some(sibling_hash) = level_hashes.get(sibling_index) {
-                    siblings.push(hex::encode(sibling_hash));
-                }
-            } else {
-                if let Some(self_hash) = level_hashes.get(current_index) {
-                    siblings.push(hex::encode(self_hash));
-                }
-            }
+    /// Proof for leaf `index` against the root the tree had at `tree_size`.
+    pub fn get_proof_at(&self, index: usize, tree_size: usize) -> Result<MerkleProof, MerkleError> {
+        self.level_view(&*self.load()?).proof_at(index, tree_size)
+    }
 
-            current_index /= 2;
-            current_level += 1;
-        }
+    /// Self-describing proof for leaf `index` against the current root.
+    pub fn get_inclusion_proof(&self, index: usize) -> Result<InclusionProof, MerkleError> {
+        self.get_inclusion_proof_at(index, self.num_leaves()?)
+    }
 
-        Some(MerkleProof { siblings })
+    /// Self-describing proof for leaf `index` against the root at `tree_size`.
+    pub fn get_inclusion_proof_at(
+        &self,
+        index: usize,
+        tree_size: usize,
+    ) -> Result<InclusionProof, MerkleError> {
+        let loaded = self.load()?;
+        let view = self.level_view(&loaded);
+        let proof = view.proof_at(index, tree_size)?;
+        let root = view.root_at(tree_size)?;
+        let leaf = self.storage.get_leaf(index)?.ok_or_else(|| {
+            MerkleError::Corrupted(format!("Leaf {} is missing from storage", index))
+        })?;
+
+        Ok(InclusionProof::new(
+            &self.config,
+            index,
+            &leaf,
+            tree_size,
+            &root,
+            proof,
+        ))
     }
 
     pub fn verify_proof(
@@ -135,84 +265,289 @@ impl LmdbMerkleTree {
         root: &[u8],
         leaf_index: usize,
     ) -> bool {
-        let mut current_hash = leaf.to_vec();
-        let mut current_index = leaf_index;
-
-        for sibling_hex in proof.siblings.iter() {
-            let sibling = match hex::decode(sibling_hex) {
-                Ok(bytes) => bytes,
-                Err(_) => return false,
-            };
-
-            current_hash = if current_index % 2 == 0 {
-                self.hash_pair(&current_hash, &sibling)
-            } else {
-                self.hash_pair(&sibling, &current_hash)
-            };
-
-            current_index /= 2;
-        }
+        self.num_leaves()
+            .is_ok_and(|size| self.verify_proof_at(leaf, proof, root, leaf_index, size))
+    }
+
+    /// Checks a proof from `get_proof_at` against the root at `tree_size`.
+    pub fn verify_proof_at(
+        &self,
+        leaf: &[u8],
+        proof: &MerkleProof,
+        root: &[u8],
+        leaf_index: usize,
+        tree_size: usize,
+    ) -> bool {
+        verify::verify_proof(&self.config, leaf, proof, root, leaf_index, tree_size)
+    }
 
-        current_hash == root
+    /// Proof for all of `indices` at once, sharing their common siblings.
+    pub fn get_multiproof(&self, indices: &[usize]) -> Result<MerkleMultiProof, MerkleError> {
+        multiproof::build_multiproof(&self.level_view(&*self.load()?), indices)
     }
 
-    fn recompute_and_store_tree(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let leaves = self.storage.get_all_leaves()?;
+    /// Checks `leaves`, in the order of `proof.indices`, against `root`.
+    pub fn verify_multiproof(
+        &self,
+        leaves: &[Vec<u8>],
+        proof: &MerkleMultiProof,
+        root: &[u8],
+    ) -> bool {
+        self.num_leaves()
+            .is_ok_and(|size| verify::verify_multiproof(&self.config, leaves, proof, root, size))
+    }
 
-        if leaves.is_empty() {
-            self.storage.clear_cache()?;
-            let metadata = TreeMetadata {
-                num_leaves: 0,
-                max_leaves: self.max_leaves,
-            };
-            self.storage.store_metadata(&metadata)?;
+    /// Proof that `start..end` are the leaves at those positions.
+    pub fn get_range_proof(&self, start: usize, end: usize) -> Result<RangeProof, MerkleError> {
+        self.get_range_proof_at(start, end, self.num_leaves()?)
+    }
+
+    /// Range proof for `start..end` against the root the tree had at
+    /// `tree_size`.
+    pub fn get_range_proof_at(
+        &self,
+        start: usize,
+        end: usize,
+        tree_size: usize,
+    ) -> Result<RangeProof, MerkleError> {
+        range_proof::build_range_proof(&self.level_view(&*self.load()?), start, end, tree_size)
+    }
+
+    /// Checks `leaves` against the positions in `proof` and `root`.
+    pub fn verify_range_proof(&self, leaves: &[Vec<u8>], proof: &RangeProof, root: &[u8]) -> bool {
+        verify::verify_range_proof(&self.config, leaves, proof, root)
+    }
+
+    /// Proof that the tree at `old_size` is a prefix of the tree at `new_size`.
+    pub fn consistency_proof(
+        &self,
+        old_size: usize,
+        new_size: usize,
+    ) -> Result<ConsistencyProof, MerkleError> {
+        consistency::build_consistency_proof(&self.level_view(&*self.load()?), old_size, new_size)
+    }
+
+    pub fn verify_consistency(
+        &self,
+        old_root: &[u8],
+        new_root: &[u8],
+        proof: &ConsistencyProof,
+    ) -> bool {
+        verify::verify_consistency(&self.config, old_root, new_root, proof)
+    }
+
+    /// Root of the tree as it was with `size` leaves, rebuilt from the
+    /// cached levels; `update_leaf` changes only the current one.
+    pub fn root_at(&self, size: usize) -> Result<Vec<u8>, MerkleError> {
+        self.level_view(&*self.load()?).root_at(size)
+    }
+
+    /// Immutable copy of the tree for readers. It shares the chunks of the
+    /// tree's leaves and levels, kept in memory since they were last read or
+    /// written; preimages stay in storage.
+    pub fn snapshot(&self) -> Result<TreeSnapshot, MerkleError> {
+        let loaded = self.load()?;
+        let snapshot = TreeSnapshot::from_parts(
+            self.config.clone(),
+            self.zero_hashes.clone(),
+            loaded.leaves.clone(),
+            loaded.levels.clone(),
+            loaded.updates.clone(),
+        );
+        Ok(snapshot.with_storage(self.storage.clone()))
+    }
+
+    // Databases written before the leaf index existed have the leaves but
+    // not their index, which shows on the last leaf
+    fn check_leaf_index(&self, num_leaves: usize) -> Result<(), MerkleError> {
+        let Some(last) = num_leaves.checked_sub(1) else {
             return Ok(());
+        };
+        if let Some(leaf) = self.storage.get_leaf(last)?
+            && !self
+                .storage
+                .get_leaf_indices(&leaf, num_leaves)?
+                .contains(&last)
+        {
+            self.storage.rebuild_leaf_index()?;
         }
+        Ok(())
+    }
 
-        let mut cache_levels = vec![leaves.clone()];
-        let mut current_level = 0;
+    // The tree as stored, read from storage only the first time. Writes
+    // change a copy and publish it once it is committed.
+    fn load(&self) -> Result<Arc<LoadedTree>, MerkleError> {
+        if let Some(loaded) = self.loaded.load_full() {
+            return Ok(loaded);
+        }
 
-        while cache_levels[current_level].len() > 1 {
-            if current_level >= MAX_LEVELS {
-                return Err("Exceeded max levels".into());
-            }
+        let num_leaves = self.num_leaves()?;
+        let loaded = Arc::new(LoadedTree {
+            leaves: self.stored_leaves(num_leaves)?,
+            levels: self.storage.get_all_cache_levels()?,
+            updates: self.storage.get_leaf_updates()?.into(),
+            num_leaves,
+        });
+        self.loaded.store(Some(loaded.clone()));
+        Ok(loaded)
+    }
 
-            let mut next_level_hashes = Vec::new();
-            let current_hashes = &cache_levels[current_level];
+    // Held for the whole of a write, see `write_lock`
+    fn lock_writes(&self) -> MutexGuard<'_, ()> {
+        self.write_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
 
-            for chunk in current_hashes.chunks(2) {
-                let left = chunk.get(0).unwrap();
-                let right = chunk.get(1).unwrap_or(left);
-                let hash = self.hash_pair(left, right);
-                next_level_hashes.push(hash);
-            }
+    fn level_view<'a>(&'a self, loaded: &'a LoadedTree) -> LevelView<'a> {
+        LevelView {
+            config: &self.config,
+            zero_hashes: &self.zero_hashes,
+            leaves: &loaded.leaves,
+            levels: &loaded.levels,
+            updates: &loaded.updates,
+            num_leaves: loaded.num_leaves,
+        }
+    }
 
-            cache_levels.push(next_level_hashes);
-            current_level += 1;
+    // The first `count` stored leaves
+    fn stored_leaves(&self, count: usize) -> Result<ChunkedVec<Vec<u8>>, MerkleError> {
+        let mut leaves: ChunkedVec<Vec<u8>> = self.storage.get_all_leaves()?.into();
+        if leaves.len() < count {
+            return Err(MerkleError::Corrupted(format!(
+                "{} leaves stored for a tree of {}",
+                leaves.len(),
+                count
+            )));
         }
+        leaves.truncate(count);
+        Ok(leaves)
+    }
 
-        self.storage.store_cache_batch(&cache_levels)?;
+    // Appends `leaves`, and their preimages if given, in one transaction
+    fn append_leaves(
+        &self,
+        leaves: &[Vec<u8>],
+        preimages: Option<&[Vec<u8>]>,
+    ) -> Result<(), MerkleError> {
+        let _writer = self.lock_writes();
+        let current_count = self.num_leaves()?;
+        if current_count + leaves.len() > self.max_leaves {
+            return Err(MerkleError::CapacityExceeded {
+                max_leaves: self.max_leaves,
+            });
+        }
 
-        if let Some(root_level) = cache_levels.last() {
-            if let Some(root) = root_level.first() {
-                self.storage.store_root(root)?;
-            }
+        let mut loaded = (*self.load()?).clone();
+        loaded.leaves.extend(leaves.iter().cloned());
+        loaded.num_leaves = loaded.leaves.len();
+        nodes::extend_levels(
+            &self.config,
+            &self.zero_hashes,
+            &mut loaded.levels,
+            &loaded.leaves,
+            current_count,
+        )?;
+        let root = self.level_view(&loaded).cached_root();
+
+        let mut batch = self.storage.begin_batch()?;
+        batch.append_leaves(current_count, leaves)?;
+        if let Some(preimages) = preimages {
+            batch.store_preimages(leaves, preimages)?;
+        }
+        let metadata = TreeMetadata::new(loaded.num_leaves, self.max_leaves, &self.config);
+        batch.store_metadata(&metadata)?;
+        // Only the right edge of the tree is rehashed
+        batch.store_cache_nodes(
+            &loaded.levels,
+            current_count..loaded.num_leaves,
+            self.config.arity,
+        )?;
+        if let Some(root) = root {
+            batch.store_root(&root)?;
         }
+        batch.commit()?;
 
-        let metadata = TreeMetadata {
-            num_leaves: leaves.len(),
-            max_leaves: self.max_leaves,
-        };
-        self.storage.store_metadata(&metadata)?;
-        self.storage.sync()?;
+        self.loaded.store(Some(Arc::new(loaded)));
+        self.storage.sync()
+    }
+}
 
-        Ok(())
+impl MerkleTree for LmdbMerkleTree {
+    fn config(&self) -> &TreeConfig {
+        &self.config
+    }
+
+    fn append_batch(&mut self, leaves: Vec<Vec<u8>>) -> Result<(), MerkleError> {
+        self.add_leaves(leaves)
+    }
+
+    fn append_preimages(
+        &mut self,
+        preimages: Vec<Vec<u8>>,
+        keep: bool,
+    ) -> Result<Vec<Vec<u8>>, MerkleError> {
+        self.add_leaf_preimages(preimages, keep)
+    }
+
+    fn preimage(&self, index: usize) -> Result<Option<Vec<u8>>, MerkleError> {
+        self.get_preimage(index)
+    }
+
+    fn update(&mut self, index: usize, leaf: Vec<u8>) -> Result<(), MerkleError> {
+        self.update_leaf(index, leaf)
+    }
+
+    fn leaf_indices(&self, leaf: &[u8]) -> Result<Vec<usize>, MerkleError> {
+        self.get_leaf_indices(leaf)
+    }
+
+    fn len(&self) -> Result<usize, MerkleError> {
+        self.num_leaves()
+    }
+
+    fn root(&self) -> Result<Option<Vec<u8>>, MerkleError> {
+        LmdbMerkleTree::root(self)
+    }
+
+    fn root_at(&self, size: usize) -> Result<Vec<u8>, MerkleError> {
+        LmdbMerkleTree::root_at(self, size)
+    }
+
+    fn proof_at(&self, index: usize, tree_size: usize) -> Result<MerkleProof, MerkleError> {
+        self.get_proof_at(index, tree_size)
+    }
+
+    fn inclusion_proof(
+        &self,
+        index: usize,
+        tree_size: usize,
+    ) -> Result<InclusionProof, MerkleError> {
+        self.get_inclusion_proof_at(index, tree_size)
+    }
+
+    fn multiproof(&self, indices: &[usize]) -> Result<MerkleMultiProof, MerkleError> {
+        self.get_multiproof(indices)
+    }
+
+    fn range_proof(
+        &self,
+        start: usize,
+        end: usize,
+        tree_size: usize,
+    ) -> Result<RangeProof, MerkleError> {
+        self.get_range_proof_at(start, end, tree_size)
+    }
+
+    fn consistency_proof(
+        &self,
+        old_size: usize,
+        new_size: usize,
+    ) -> Result<ConsistencyProof, MerkleError> {
+        LmdbMerkleTree::consistency_proof(self, old_size, new_size)
     }
 
-    fn hash_pair(&self, left: &[u8], right: &[u8]) -> Vec<u8> {
-        let mut hasher = Keccak256::new();
-        hasher.update(left);
-        hasher.update(right);
-        hasher.finalize().to_vec()
+    fn snapshot(&self) -> Result<TreeSnapshot, MerkleError> {
+        LmdbMerkleTree::snapshot(self)
     }
 }
@@ -1,8 +1,9 @@
+use arc_swap::ArcSwap;
 use axum::{
     Router,
-    extract::State,
-    http::StatusCode,
-    response::Json,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Json, Response},
     routing::{get, post},
 };
 use serde::{Deserialize, Serialize};
@@ -10,35 +11,140 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 use tower_http::cors::CorsLayer;
 
-mod lmdb_tree;
-mod merkle_tree;
-mod storage;
-use lmdb_tree::LmdbMerkleTree;
-use merkle_tree::{IncrementalMerkleTree, MerkleProof};
+use merkle_tree_api::config::TreeConfig;
+use merkle_tree_api::consistency::ConsistencyProof;
+use merkle_tree_api::encoding::{BinaryEncoding, prefers_binary};
+use merkle_tree_api::error::MerkleError;
+use merkle_tree_api::hasher::hasher_from_id;
+use merkle_tree_api::lmdb_tree::LmdbMerkleTree;
+use merkle_tree_api::merkle_tree::IncrementalMerkleTree;
+use merkle_tree_api::multiproof::MerkleMultiProof;
+use merkle_tree_api::preimage::{LeafEncoding, decode_preimage};
+use merkle_tree_api::range_proof::RangeProof;
+use merkle_tree_api::snapshot::TreeSnapshot;
+use merkle_tree_api::sparse_tree::{SparseKey, SparseMerkleProof, SparseMerkleTree, SparseStore};
+use merkle_tree_api::storage::LmdbStorage;
+use merkle_tree_api::tree::MerkleTree;
+use merkle_tree_api::verify::InclusionProof;
+
+// Writes go through the lock; reads are served from the latest snapshot,
+// which each write replaces, so they never wait behind a write.
+struct TreeAppState<T: MerkleTree> {
+    tree: Arc<RwLock<T>>,
+    snapshot: Arc<ArcSwap<TreeSnapshot>>,
+}
+
+// Derived Clone would require `T: Clone`
+impl<T: MerkleTree> Clone for TreeAppState<T> {
+    fn clone(&self) -> Self {
+        Self {
+            tree: self.tree.clone(),
+            snapshot: self.snapshot.clone(),
+        }
+    }
+}
+
+impl<T: MerkleTree> TreeAppState<T> {
+    fn new(tree: T) -> Self {
+        let snapshot = tree.snapshot().expect("Failed to read the tree");
+        Self {
+            tree: Arc::new(RwLock::new(tree)),
+            snapshot: Arc::new(ArcSwap::from_pointee(snapshot)),
+        }
+    }
 
-#[derive(Clone)]
-struct AppState {
-    tree: Arc<RwLock<IncrementalMerkleTree>>,
+    // Called with the write lock held, so snapshots are published in write order
+    fn publish(&self, tree: &T) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+        let snapshot = tree.snapshot().map_err(merkle_error)?;
+        self.snapshot.store(Arc::new(snapshot));
+        Ok(())
+    }
 }
 
-#[derive(Clone)]
-struct LmdbAppState {
-    tree: Arc<RwLock<LmdbMerkleTree>>,
+struct SparseAppState<S: SparseStore> {
+    tree: Arc<RwLock<SparseMerkleTree<S>>>,
 }
 
+// Derived Clone would require `S: Clone`
+impl<S: SparseStore> Clone for SparseAppState<S> {
+    fn clone(&self) -> Self {
+        Self {
+            tree: self.tree.clone(),
+        }
+    }
+}
+
+// Either `leaf` as hex or `data` for the server to hash. `store_preimage`
+// keeps the data in the LMDB tree so it is returned with proofs.
 #[derive(Deserialize)]
 struct AddLeafRequest {
-    leaf: String,
+    leaf: Option<String>,
+    data: Option<String>,
+    #[serde(default)]
+    encoding: LeafEncoding,
+    #[serde(default)]
+    store_preimage: bool,
 }
 
 #[derive(Deserialize)]
 struct AddLeavesRequest {
-    leaves: Vec<String>,
+    leaves: Option<Vec<String>>,
+    data: Option<Vec<String>>,
+    #[serde(default)]
+    encoding: LeafEncoding,
+    #[serde(default)]
+    store_preimage: bool,
 }
 
 #[derive(Deserialize)]
-struct GetProofRequest {
+struct UpdateLeafRequest {
     index: usize,
+    leaf: String,
+}
+
+// Either `index` for a single proof or `indices` for a multiproof. A single
+// proof can target the root of an earlier `tree_size`.
+#[derive(Deserialize)]
+struct GetProofRequest {
+    index: Option<usize>,
+    indices: Option<Vec<usize>>,
+    tree_size: Option<usize>,
+}
+
+// Proof for the first position of `leaf` in the tree at `tree_size`
+#[derive(Deserialize)]
+struct GetProofByLeafRequest {
+    leaf: String,
+    tree_size: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct RootQuery {
+    size: Option<usize>,
+}
+
+// Leaves at positions `[start, end)` of the tree at `tree_size`
+#[derive(Deserialize)]
+struct GetRangeProofRequest {
+    start: usize,
+    end: usize,
+    tree_size: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct GetConsistencyProofRequest {
+    old_size: usize,
+    new_size: usize,
+}
+
+#[derive(Serialize)]
+struct AddLeafResponse {
+    leaf: String,
+}
+
+#[derive(Serialize)]
+struct AddLeavesResponse {
+    leaves: Vec<String>,
 }
 
 #[derive(Serialize)]
@@ -46,276 +152,595 @@ struct NumLeavesResponse {
     num_leaves: usize,
 }
 
+#[derive(Serialize)]
+struct LeafIndexResponse {
+    indices: Vec<usize>,
+}
+
 #[derive(Serialize)]
 struct RootResponse {
     root: String,
 }
 
 #[derive(Serialize)]
-struct ProofResponse {
-    proof: MerkleProof,
+#[serde(untagged)]
+enum ProofResponse {
+    Single {
+        proof: InclusionProof,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        preimage: Option<String>,
+    },
+    Multi {
+        proof: MerkleMultiProof,
+    },
+}
+
+impl ProofResponse {
+    fn encode(&self) -> Result<Vec<u8>, MerkleError> {
+        match self {
+            // The binary format has no field for the preimage
+            ProofResponse::Single {
+                preimage: Some(_), ..
+            } => Err(MerkleError::InvalidProof(
+                "Proofs with a preimage have no binary encoding".to_string(),
+            )),
+            ProofResponse::Single { proof, .. } => proof.encode(),
+            ProofResponse::Multi { proof } => proof.encode(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SparseInsertRequest {
+    key: String,
+    value: String,
+}
+
+#[derive(Deserialize)]
+struct SparseKeyRequest {
+    key: String,
+}
+
+#[derive(Serialize)]
+struct SparseValueResponse {
+    value: Option<String>,
+}
+
+#[derive(Serialize)]
+struct SparseProofResponse {
+    proof: SparseMerkleProof,
+}
+
+#[derive(Serialize)]
+struct RangeProofResponse {
+    proof: RangeProof,
+}
+
+#[derive(Serialize)]
+struct ConsistencyProofResponse {
+    proof: ConsistencyProof,
 }
 
 #[derive(Serialize)]
 struct ErrorResponse {
     error: String,
+    code: String,
 }
 
-async fn add_leaf(
-    State(state): State<AppState>,
+const OCTET_STREAM: &str = "application/octet-stream";
+
+fn wants_binary(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(prefers_binary)
+}
+
+fn binary_response(
+    encoded: Result<Vec<u8>, MerkleError>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    match encoded {
+        Ok(bytes) => Ok(([(header::CONTENT_TYPE, OCTET_STREAM)], bytes).into_response()),
+        // The proof exists but has no binary form, so only the format is refused
+        Err(e) => Err((StatusCode::NOT_ACCEPTABLE, merkle_error(e).1)),
+    }
+}
+
+fn merkle_error(e: MerkleError) -> (StatusCode, Json<ErrorResponse>) {
+    let status = StatusCode::from_u16(e.http_status()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    (
+        status,
+        Json(ErrorResponse {
+            error: e.to_string(),
+            code: e.code().to_string(),
+        }),
+    )
+}
+
+fn bad_request(error: &str) -> (StatusCode, Json<ErrorResponse>) {
+    merkle_error(MerkleError::InvalidArgument(error.to_string()))
+}
+
+enum LeafInput {
+    Leaves(Vec<Vec<u8>>),
+    Preimages(Vec<Vec<u8>>),
+}
+
+fn parse_leaf_input(
+    leaves: Option<Vec<String>>,
+    data: Option<Vec<String>>,
+    encoding: LeafEncoding,
+) -> Result<LeafInput, (StatusCode, Json<ErrorResponse>)> {
+    match (leaves, data) {
+        (Some(leaves), None) => leaves
+            .iter()
+            .map(hex::decode)
+            .collect::<Result<_, _>>()
+            .map(LeafInput::Leaves)
+            .map_err(|_| bad_request("Invalid hex string")),
+        (None, Some(data)) => data
+            .iter()
+            .map(|data| decode_preimage(data, encoding))
+            .collect::<Result<_, _>>()
+            .map(LeafInput::Preimages)
+            .map_err(merkle_error),
+        _ => Err(bad_request("Send either hex leaves or data to hash")),
+    }
+}
+
+// Tree handlers, shared by every `MerkleTree` backend
+async fn add_leaf<T: MerkleTree>(
+    State(state): State<TreeAppState<T>>,
     Json(payload): Json<AddLeafRequest>,
-) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
-    let leaf_bytes = hex::decode(&payload.leaf).map_err(|_| {
-        (
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: "Invalid hex string".to_string(),
-            }),
-        )
-    })?;
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let input = parse_leaf_input(
+        payload.leaf.map(|leaf| vec![leaf]),
+        payload.data.map(|data| vec![data]),
+        payload.encoding,
+    )?;
 
     let mut tree = state.tree.write().await;
-    tree.add_leaf(leaf_bytes).map_err(|e| {
-        (
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: e.to_string(),
-            }),
-        )
-    })?;
+    let response = match input {
+        LeafInput::Leaves(mut leaves) => {
+            tree.append(leaves.remove(0)).map_err(merkle_error)?;
+            StatusCode::OK.into_response()
+        }
+        LeafInput::Preimages(preimages) => {
+            // The preimage is stored under the leaf it gives
+            let mut leaves = tree
+                .append_preimages(preimages, payload.store_preimage)
+                .map_err(merkle_error)?;
+            Json(AddLeafResponse {
+                leaf: hex::encode(leaves.remove(0)),
+            })
+            .into_response()
+        }
+    };
 
-    Ok(StatusCode::OK)
+    state.publish(&tree)?;
+    Ok(response)
 }
 
-async fn add_leaves(
-    State(state): State<AppState>,
+async fn add_leaves<T: MerkleTree>(
+    State(state): State<TreeAppState<T>>,
     Json(payload): Json<AddLeavesRequest>,
-) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
-    let leaves_bytes: Result<Vec<_>, _> = payload
-        .leaves
-        .iter()
-        .map(|leaf_hex| {
-            hex::decode(leaf_hex).map_err(|_| {
-                (
-                    StatusCode::BAD_REQUEST,
-                    Json(ErrorResponse {
-                        error: "Invalid hex string in leaves".to_string(),
-                    }),
-                )
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let input = parse_leaf_input(payload.leaves, payload.data, payload.encoding)?;
+
+    let mut tree = state.tree.write().await;
+    let response = match input {
+        LeafInput::Leaves(leaves) => {
+            tree.append_batch(leaves).map_err(merkle_error)?;
+            StatusCode::OK.into_response()
+        }
+        LeafInput::Preimages(preimages) => {
+            let leaves = tree
+                .append_preimages(preimages, payload.store_preimage)
+                .map_err(merkle_error)?;
+            Json(AddLeavesResponse {
+                leaves: leaves.iter().map(hex::encode).collect(),
             })
-        })
-        .collect();
+            .into_response()
+        }
+    };
+
+    state.publish(&tree)?;
+    Ok(response)
+}
 
-    let leaves_bytes = leaves_bytes?;
+async fn update_leaf<T: MerkleTree>(
+    State(state): State<TreeAppState<T>>,
+    Json(payload): Json<UpdateLeafRequest>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    let leaf_bytes = hex::decode(&payload.leaf).map_err(|_| bad_request("Invalid hex string"))?;
 
+    // Exclusive lock so concurrent updates cannot interleave their path rewrites
     let mut tree = state.tree.write().await;
-    tree.add_leaves(leaves_bytes).map_err(|e| {
-        (
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: e.to_string(),
-            }),
-        )
-    })?;
+    tree.update(payload.index, leaf_bytes)
+        .map_err(merkle_error)?;
 
+    state.publish(&tree)?;
     Ok(StatusCode::OK)
 }
 
-async fn get_num_leaves(State(state): State<AppState>) -> Json<NumLeavesResponse> {
-    let tree = state.tree.read().await;
+async fn get_num_leaves<T: MerkleTree>(
+    State(state): State<TreeAppState<T>>,
+) -> Json<NumLeavesResponse> {
     Json(NumLeavesResponse {
-        num_leaves: tree.num_leaves(),
+        num_leaves: state.snapshot.load().len(),
     })
 }
 
-async fn get_root(
-    State(state): State<AppState>,
+async fn get_root<T: MerkleTree>(
+    State(state): State<TreeAppState<T>>,
 ) -> Result<Json<RootResponse>, (StatusCode, Json<ErrorResponse>)> {
-    let maybe_root = {
-        let mut tree = state.tree.write().await;
-        tree.root().map(hex::encode)
-    };
-
-    match maybe_root {
+    match state.snapshot.load().root().map(hex::encode) {
         Some(root) => Ok(Json(RootResponse { root })),
-        None => Err((
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: "Tree is empty".to_string(),
-            }),
-        )),
+        None => Err(bad_request("Tree is empty")),
     }
 }
 
-async fn get_proof(
-    State(state): State<AppState>,
+async fn get_root_at<T: MerkleTree>(
+    State(state): State<TreeAppState<T>>,
+    Query(query): Query<RootQuery>,
+) -> Result<Json<RootResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let snapshot = state.snapshot.load();
+    let root = match query.size {
+        Some(size) => snapshot.root_at(size).map_err(merkle_error)?,
+        None => snapshot
+            .root()
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| bad_request("Tree is empty"))?,
+    };
+
+    Ok(Json(RootResponse {
+        root: hex::encode(root),
+    }))
+}
+
+async fn get_proof<T: MerkleTree>(
+    State(state): State<TreeAppState<T>>,
+    headers: HeaderMap,
     Json(payload): Json<GetProofRequest>,
-) -> Result<Json<ProofResponse>, (StatusCode, Json<ErrorResponse>)> {
-    let maybe_proof = {
-        let tree = state.tree.read().await;
-        tree.get_proof(payload.index)
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    if payload.indices.is_some() && payload.tree_size.is_some() {
+        return Err(bad_request("tree_size is only supported for single proofs"));
+    }
+
+    let response = {
+        let snapshot = state.snapshot.load();
+        match (payload.indices, payload.index) {
+            (Some(indices), _) => ProofResponse::Multi {
+                proof: snapshot.get_multiproof(&indices).map_err(merkle_error)?,
+            },
+            (None, Some(index)) => {
+                let tree_size = payload.tree_size.unwrap_or_else(|| snapshot.len());
+                let proof = snapshot
+                    .get_inclusion_proof_at(index, tree_size)
+                    .map_err(merkle_error)?;
+                ProofResponse::Single {
+                    preimage: snapshot
+                        .get_preimage(proof.leaf_index)
+                        .map_err(merkle_error)?
+                        .map(hex::encode),
+                    proof,
+                }
+            }
+            (None, None) => return Err(bad_request("Send either index or indices")),
+        }
     };
 
-    match maybe_proof {
-        Some(proof) => Ok(Json(ProofResponse { proof })),
-        None => Err((
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: "Invalid index, tree cache invalid, or tree is empty".to_string(),
-            }),
-        )),
+    if wants_binary(&headers) {
+        return binary_response(response.encode());
     }
+    Ok(Json(response).into_response())
 }
 
-// LMDB handlers
-async fn lmdb_add_leaf(
-    State(state): State<LmdbAppState>,
-    Json(payload): Json<AddLeafRequest>,
+async fn get_leaf_index<T: MerkleTree>(
+    State(state): State<TreeAppState<T>>,
+    Path(leaf): Path<String>,
+) -> Result<Json<LeafIndexResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let leaf = hex::decode(&leaf).map_err(|_| bad_request("Invalid hex string"))?;
+
+    let indices = state
+        .snapshot
+        .load()
+        .get_leaf_indices(&leaf)
+        .map_err(merkle_error)?;
+    if indices.is_empty() {
+        return Err(merkle_error(MerkleError::LeafNotFound));
+    }
+
+    Ok(Json(LeafIndexResponse { indices }))
+}
+
+async fn get_proof_by_leaf<T: MerkleTree>(
+    State(state): State<TreeAppState<T>>,
+    headers: HeaderMap,
+    Json(payload): Json<GetProofByLeafRequest>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let leaf = hex::decode(&payload.leaf).map_err(|_| bad_request("Invalid hex string"))?;
+
+    let response = {
+        let snapshot = state.snapshot.load();
+        let tree_size = payload.tree_size.unwrap_or_else(|| snapshot.len());
+        let index = snapshot
+            .get_leaf_indices(&leaf)
+            .map_err(merkle_error)?
+            .into_iter()
+            .find(|&index| index < tree_size)
+            .ok_or_else(|| merkle_error(MerkleError::LeafNotFound))?;
+
+        let proof = snapshot
+            .get_inclusion_proof_at(index, tree_size)
+            .map_err(merkle_error)?;
+        ProofResponse::Single {
+            preimage: snapshot
+                .get_preimage(index)
+                .map_err(merkle_error)?
+                .map(hex::encode),
+            proof,
+        }
+    };
+
+    if wants_binary(&headers) {
+        return binary_response(response.encode());
+    }
+    Ok(Json(response).into_response())
+}
+
+async fn get_range_proof<T: MerkleTree>(
+    State(state): State<TreeAppState<T>>,
+    headers: HeaderMap,
+    Json(payload): Json<GetRangeProofRequest>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let proof = {
+        let snapshot = state.snapshot.load();
+        let tree_size = payload.tree_size.unwrap_or_else(|| snapshot.len());
+        snapshot
+            .get_range_proof_at(payload.start, payload.end, tree_size)
+            .map_err(merkle_error)?
+    };
+
+    if wants_binary(&headers) {
+        return binary_response(proof.encode());
+    }
+    Ok(Json(RangeProofResponse { proof }).into_response())
+}
+
+async fn get_consistency_proof<T: MerkleTree>(
+    State(state): State<TreeAppState<T>>,
+    headers: HeaderMap,
+    Json(payload): Json<GetConsistencyProofRequest>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let proof = state
+        .snapshot
+        .load()
+        .consistency_proof(payload.old_size, payload.new_size)
+        .map_err(merkle_error)?;
+
+    if wants_binary(&headers) {
+        return binary_response(proof.encode());
+    }
+    Ok(Json(ConsistencyProofResponse { proof }).into_response())
+}
+
+// Sparse tree handlers, shared by the heap and LMDB backed trees
+fn parse_sparse_key(key: &str) -> Result<SparseKey, (StatusCode, Json<ErrorResponse>)> {
+    hex::decode(key)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| bad_request("Key must be a 32-byte hex string"))
+}
+
+async fn sparse_insert<S: SparseStore>(
+    State(state): State<SparseAppState<S>>,
+    Json(payload): Json<SparseInsertRequest>,
 ) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
-    let leaf_bytes = hex::decode(&payload.leaf).map_err(|_| {
-        (
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: "Invalid hex string".to_string(),
-            }),
-        )
-    })?;
+    let key = parse_sparse_key(&payload.key)?;
+    let value = hex::decode(&payload.value).map_err(|_| bad_request("Invalid hex string"))?;
 
-    let tree = state.tree.read().await;
-    tree.add_leaf(leaf_bytes).map_err(|e| {
-        (
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: e.to_string(),
-            }),
-        )
-    })?;
+    let mut tree = state.tree.write().await;
+    tree.insert(key, value).map_err(merkle_error)?;
 
     Ok(StatusCode::OK)
 }
 
-async fn lmdb_add_leaves(
-    State(state): State<LmdbAppState>,
-    Json(payload): Json<AddLeavesRequest>,
-) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
-    let leaves_bytes: Result<Vec<_>, _> = payload
-        .leaves
-        .iter()
-        .map(|leaf_hex| {
-            hex::decode(leaf_hex).map_err(|_| {
-                (
-                    StatusCode::BAD_REQUEST,
-                    Json(ErrorResponse {
-                        error: "Invalid hex string in leaves".to_string(),
-                    }),
-                )
-            })
-        })
-        .collect();
+async fn sparse_remove<S: SparseStore>(
+    State(state): State<SparseAppState<S>>,
+    Json(payload): Json<SparseKeyRequest>,
+) -> Result<Json<SparseValueResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let key = parse_sparse_key(&payload.key)?;
+
+    let mut tree = state.tree.write().await;
+    let previous = tree.remove(key).map_err(merkle_error)?;
+
+    Ok(Json(SparseValueResponse {
+        value: previous.map(hex::encode),
+    }))
+}
 
-    let leaves_bytes = leaves_bytes?;
+async fn sparse_get<S: SparseStore>(
+    State(state): State<SparseAppState<S>>,
+    Json(payload): Json<SparseKeyRequest>,
+) -> Result<Json<SparseValueResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let key = parse_sparse_key(&payload.key)?;
 
     let tree = state.tree.read().await;
-    tree.add_leaves(leaves_bytes).map_err(|e| {
-        (
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: e.to_string(),
-            }),
-        )
-    })?;
+    let value = tree.get(&key).map_err(merkle_error)?;
 
-    Ok(StatusCode::OK)
+    Ok(Json(SparseValueResponse {
+        value: value.map(hex::encode),
+    }))
 }
 
-async fn lmdb_get_num_leaves(State(state): State<LmdbAppState>) -> Json<NumLeavesResponse> {
+async fn sparse_get_root<S: SparseStore>(
+    State(state): State<SparseAppState<S>>,
+) -> Result<Json<RootResponse>, (StatusCode, Json<ErrorResponse>)> {
     let tree = state.tree.read().await;
-    Json(NumLeavesResponse {
-        num_leaves: tree.num_leaves(),
-    })
+    let root = tree.root().map_err(merkle_error)?;
+
+    Ok(Json(RootResponse {
+        root: hex::encode(root),
+    }))
 }
 
-async fn lmdb_get_root(
-    State(state): State<LmdbAppState>,
-) -> Result<Json<RootResponse>, (StatusCode, Json<ErrorResponse>)> {
-    let maybe_root = {
-        let tree = state.tree.read().await;
-        tree.root().map(hex::encode)
-    };
+async fn sparse_get_proof<S: SparseStore>(
+    State(state): State<SparseAppState<S>>,
+    Json(payload): Json<SparseKeyRequest>,
+) -> Result<Json<SparseProofResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let key = parse_sparse_key(&payload.key)?;
 
-    match maybe_root {
-        Some(root) => Ok(Json(RootResponse { root })),
-        None => Err((
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: "Tree is empty".to_string(),
-            }),
-        )),
-    }
+    let tree = state.tree.read().await;
+    let proof = tree.get_proof(key).map_err(merkle_error)?;
+
+    Ok(Json(SparseProofResponse { proof }))
 }
 
-async fn lmdb_get_proof(
-    State(state): State<LmdbAppState>,
-    Json(payload): Json<GetProofRequest>,
-) -> Result<Json<ProofResponse>, (StatusCode, Json<ErrorResponse>)> {
-    let maybe_proof = {
-        let tree = state.tree.read().await;
-        tree.get_proof(payload.index)
-    };
+fn tree_routes<T>(state: TreeAppState<T>) -> Router
+where
+    T: MerkleTree + Send + Sync + 'static,
+{
+    Router::new()
+        .route("/add-leaf", post(add_leaf::<T>))
+        .route("/add-leaves", post(add_leaves::<T>))
+        .route("/update-leaf", post(update_leaf::<T>))
+        .route("/get-num-leaves", get(get_num_leaves::<T>))
+        .route("/get-root", get(get_root::<T>))
+        .route("/root", get(get_root_at::<T>))
+        .route("/get-proof", post(get_proof::<T>))
+        .route("/leaf-index/{leaf}", get(get_leaf_index::<T>))
+        .route("/get-proof-by-leaf", post(get_proof_by_leaf::<T>))
+        .route("/get-range-proof", post(get_range_proof::<T>))
+        .route("/get-consistency-proof", post(get_consistency_proof::<T>))
+        .with_state(state)
+}
 
-    match maybe_proof {
-        Some(proof) => Ok(Json(ProofResponse { proof })),
-        None => Err((
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: "Invalid index, tree cache invalid, or tree is empty".to_string(),
-            }),
-        )),
-    }
+fn env_or_default<T>(name: &str) -> T
+where
+    T: std::str::FromStr<Err = String> + Default,
+{
+    std::env::var(name)
+        .map(|value| {
+            value
+                .parse()
+                .unwrap_or_else(|e| panic!("Invalid {}: {}", name, e))
+        })
+        .unwrap_or_default()
+}
+
+// Hashing parameters of a tree, for the startup output
+fn describe_config(config: &TreeConfig) -> String {
+    format!(
+        "{} ({:?} scheme, {:?} odd nodes, depth {:?}, arity {})",
+        config.hasher.id(),
+        config.scheme,
+        config.odd_node_strategy(),
+        config.depth,
+        config.arity
+    )
 }
 
 #[tokio::main]
 async fn main() {
-    let heap_tree = Arc::new(RwLock::new(IncrementalMerkleTree::new()));
+    let hash_algorithm =
+        std::env::var("HASH_ALGORITHM").unwrap_or_else(|_| "keccak256".to_string());
+    let hasher = hasher_from_id(&hash_algorithm)
+        .unwrap_or_else(|| panic!("Unsupported HASH_ALGORITHM: {}", hash_algorithm));
+    let config = TreeConfig {
+        hasher,
+        scheme: env_or_default("HASH_SCHEME"),
+        odd_node: env_or_default("ODD_NODE_STRATEGY"),
+        depth: std::env::var("TREE_DEPTH").ok().map(|depth| {
+            depth
+                .parse()
+                .unwrap_or_else(|e| panic!("Invalid TREE_DEPTH: {}", e))
+        }),
+        arity: std::env::var("TREE_ARITY").map_or(2, |arity| {
+            arity
+                .parse()
+                .unwrap_or_else(|e| panic!("Invalid TREE_ARITY: {}", e))
+        }),
+    };
+
+    let heap_tree = IncrementalMerkleTree::with_config(config.clone())
+        .unwrap_or_else(|e| panic!("Invalid tree config: {}", e));
 
     let storage_path =
         std::env::var("STORAGE_PATH").unwrap_or_else(|_| "./merkle_tree.db".to_string());
-    let lmdb_tree = match LmdbMerkleTree::new(&storage_path) {
+    let lmdb_tree = match LmdbMerkleTree::with_config(&storage_path, config.clone()) {
         Ok(tree) => {
             println!("Loaded existing LMDB merkle tree from: {}", storage_path);
-            Arc::new(RwLock::new(tree))
+            tree
         }
-        Err(e) => {
+        Err(MerkleError::ConfigMismatch(e)) => {
             println!(
-                "Failed to load from storage ({}), creating new LMDB tree: {}",
+                "Storage ({}) does not match the configured hashing, using its stored config: {}",
                 storage_path, e
             );
-            Arc::new(RwLock::new(LmdbMerkleTree::new(&storage_path).unwrap()))
+            LmdbMerkleTree::new(&storage_path)
+                .unwrap_or_else(|e| panic!("Failed to open {}: {}", storage_path, e))
+        }
+        Err(e) => panic!("Failed to open {}: {}", storage_path, e),
+    };
+    let lmdb_config = lmdb_tree.config().clone();
+
+    let sparse_tree = SparseMerkleTree::with_hasher(config.hasher.clone());
+
+    let sparse_storage_path =
+        std::env::var("SPARSE_STORAGE_PATH").unwrap_or_else(|_| "./sparse_tree.db".to_string());
+    let lmdb_sparse_tree = match SparseMerkleTree::new_with_storage_and_hasher(
+        &sparse_storage_path,
+        config.hasher.clone(),
+    ) {
+        Ok(tree) => tree,
+        Err(MerkleError::ConfigMismatch(e)) => {
+            println!(
+                "Sparse storage ({}) does not match the configured hashing, using its stored hasher: {}",
+                sparse_storage_path, e
+            );
+            SparseMerkleTree::new_with_storage(&sparse_storage_path)
+                .unwrap_or_else(|e| panic!("Failed to open {}: {}", sparse_storage_path, e))
         }
+        Err(e) => panic!("Failed to open {}: {}", sparse_storage_path, e),
     };
 
-    let heap_state = AppState { tree: heap_tree };
-    let lmdb_state = LmdbAppState { tree: lmdb_tree };
-
-    let heap_routes = Router::new()
-        .route("/add-leaf", post(add_leaf))
-        .route("/add-leaves", post(add_leaves))
-        .route("/get-num-leaves", get(get_num_leaves))
-        .route("/get-root", get(get_root))
-        .route("/get-proof", post(get_proof))
-        .with_state(heap_state);
-
-    let lmdb_routes = Router::new()
-        .route("/lmdb/add-leaf", post(lmdb_add_leaf))
-        .route("/lmdb/add-leaves", post(lmdb_add_leaves))
-        .route("/lmdb/get-num-leaves", get(lmdb_get_num_leaves))
-        .route("/lmdb/get-root", get(lmdb_get_root))
-        .route("/lmdb/get-proof", post(lmdb_get_proof))
-        .with_state(lmdb_state);
+    let heap_state = TreeAppState::new(heap_tree);
+    let lmdb_state = TreeAppState::new(lmdb_tree);
+    let sparse_state = SparseAppState {
+        tree: Arc::new(RwLock::new(sparse_tree)),
+    };
+    let lmdb_sparse_state = SparseAppState {
+        tree: Arc::new(RwLock::new(lmdb_sparse_tree)),
+    };
+
+    let heap_routes = tree_routes(heap_state);
+    let lmdb_routes = Router::new().nest("/lmdb", tree_routes(lmdb_state));
+
+    let sparse_routes = Router::new()
+        .route("/sparse/insert", post(sparse_insert))
+        .route("/sparse/remove", post(sparse_remove))
+        .route("/sparse/get", post(sparse_get))
+        .route("/sparse/get-root", get(sparse_get_root))
+        .route("/sparse/get-proof", post(sparse_get_proof))
+        .with_state(sparse_state);
+
+    let lmdb_sparse_routes = Router::new()
+        .route("/lmdb/sparse/insert", post(sparse_insert::<LmdbStorage>))
+        .route("/lmdb/sparse/remove", post(sparse_remove::<LmdbStorage>))
+        .route("/lmdb/sparse/get", post(sparse_get::<LmdbStorage>))
+        .route("/lmdb/sparse/get-root", get(sparse_get_root::<LmdbStorage>))
+        .route(
+            "/lmdb/sparse/get-proof",
+            post(sparse_get_proof::<LmdbStorage>),
+        )
+        .with_state(lmdb_sparse_state);
 
     let app = Router::new()
         .merge(heap_routes)
         .merge(lmdb_routes)
+        .merge(sparse_routes)
+        .merge(lmdb_sparse_routes)
         .layer(CorsLayer::permissive());
 
     let port = std::env::var("PORT").unwrap_or_else(|_| "8080".to_string());
@@ -323,11 +748,19 @@ async fn main() {
     let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
 
     println!("Server running on http://{}", addr);
-    println!("Heap routes: /add-leaf, /add-leaves, /get-num-leaves, /get-root, /get-proof");
     println!(
-        "LMDB routes: /lmdb/add-leaf, /lmdb/add-leaves, /lmdb/get-num-leaves, /lmdb/get-root, /lmdb/get-proof"
+        "Heap routes: /add-leaf, /add-leaves, /update-leaf, /get-num-leaves, /get-root, /root, /get-proof, /leaf-index, /get-proof-by-leaf, /get-range-proof, /get-consistency-proof"
+    );
+    println!(
+        "LMDB routes: /lmdb/add-leaf, /lmdb/add-leaves, /lmdb/update-leaf, /lmdb/get-num-leaves, /lmdb/get-root, /lmdb/root, /lmdb/get-proof, /lmdb/leaf-index, /lmdb/get-proof-by-leaf, /lmdb/get-range-proof, /lmdb/get-consistency-proof"
+    );
+    println!(
+        "Sparse routes: /sparse/insert, /sparse/remove, /sparse/get, /sparse/get-root, /sparse/get-proof (and /lmdb/sparse/...)"
     );
     println!("Storage path: {}", storage_path);
+    println!("Sparse storage path: {}", sparse_storage_path);
+    println!("Heap tree: {}", describe_config(&config));
+    println!("LMDB tree: {}", describe_config(&lmdb_config));
 
     axum::serve(listener, app).await.unwrap();
 }
@@ -1,48 +1,84 @@
-use super::storage::{LmdbStorage, TreeMetadata};
-use serde::Serialize;
-use sha3::{Digest, Keccak256};
-
-// tree limit
-const MAX_LEVELS: usize = 32;
-const MAX_LEAVES: usize = 1 << MAX_LEVELS;
-
-#[derive(Debug, Clone, Serialize)]
-pub struct MerkleProof {
-    pub siblings: Vec<String>,
-}
+use super::chunked::ChunkedVec;
+use super::config::{MAX_LEVELS, TreeConfig};
+use super::consistency::{self, ConsistencyProof};
+use super::error::MerkleError;
+use super::history::LevelView;
+use super::multiproof::{self, MerkleMultiProof};
+use super::nodes::{self, Hash, LeafUpdate, Level};
+use super::range_proof::{self, RangeProof};
+use super::snapshot::TreeSnapshot;
+use super::storage::{LmdbStorage, StorageBatch, TreeMetadata};
+use super::tree::MerkleTree;
+use super::verify::{self, InclusionProof};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+pub use super::verify::MerkleProof;
 
 #[derive(Debug)]
 pub struct IncrementalMerkleTree {
-    leaves: Vec<Vec<u8>>,
+    leaves: ChunkedVec<Vec<u8>>,
     max_leaves: usize,
-    // cache: level -> index -> hash
-    pub cached_hashes: Vec<Vec<Vec<u8>>>,
-    cached_root: Option<Vec<u8>>,
-    cache_valid: bool,
+    // cache: level -> index -> hash, see `nodes` for what is left out
+    cached_hashes: Vec<Level>,
+    // every leaf update, oldest first, to rebuild roots of earlier sizes;
+    // never pruned, so it grows by one leaf per update
+    updates: ChunkedVec<LeafUpdate>,
+    // leaf digest -> indices holding it, shared with snapshots
+    leaf_indices: Arc<LeafIndex>,
     storage: Option<LmdbStorage>,
+    config: TreeConfig,
+    zero_hashes: Arc<[Vec<u8>]>,
 }
 
 impl IncrementalMerkleTree {
     pub fn new() -> Self {
-        Self {
-            leaves: Vec::new(),
-            max_leaves: MAX_LEAVES,
+        Self::with_config(TreeConfig::default()).expect("the default config is valid")
+    }
+
+    pub fn with_config(config: TreeConfig) -> Result<Self, MerkleError> {
+        config.validate()?;
+
+        Ok(Self {
+            leaves: ChunkedVec::new(),
+            max_leaves: config.max_leaves(),
             cached_hashes: Vec::new(),
-            cached_root: None,
-            cache_valid: true,
+            updates: ChunkedVec::new(),
+            leaf_indices: Arc::default(),
             storage: None,
-        }
+            zero_hashes: config.zero_hashes(MAX_LEVELS).into(),
+            config,
+        })
     }
 
-    pub fn new_with_storage(storage_path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+    /// Opens a persisted tree, keeping the config it was created with.
+    pub fn new_with_storage(storage_path: &str) -> Result<Self, MerkleError> {
         let storage = LmdbStorage::new(storage_path)?;
+        let config = match storage.get_metadata()? {
+            Some(metadata) => metadata.config()?,
+            None => TreeConfig::default(),
+        };
+        Self::open_storage(storage, config)
+    }
+
+    /// Opens a persisted tree, failing if it was created with a different config.
+    pub fn new_with_storage_and_config(
+        storage_path: &str,
+        config: TreeConfig,
+    ) -> Result<Self, MerkleError> {
+        let storage = LmdbStorage::new(storage_path)?;
+        if let Some(metadata) = storage.get_metadata()? {
+            metadata.check_config(&config)?;
+        }
+        Self::open_storage(storage, config)
+    }
+
+    fn open_storage(storage: LmdbStorage, config: TreeConfig) -> Result<Self, MerkleError> {
         let mut tree = Self {
-            leaves: Vec::new(),
-            max_leaves: MAX_LEAVES,
-            cached_hashes: Vec::new(),
-            cached_root: None,
-            cache_valid: true,
             storage: Some(storage),
+            ..Self::with_config(config)?
         };
         tree.load_from_storage()?;
         Ok(tree)
@@ -50,111 +86,164 @@ impl IncrementalMerkleTree {
 
     pub fn _new_with_max(max_leaves: usize) -> Self {
         Self {
-            leaves: Vec::new(),
             max_leaves,
-            cached_hashes: Vec::new(),
-            cached_root: None,
-            cache_valid: true,
-            storage: None,
+            ..Self::new()
         }
     }
 
-    pub fn add_leaf(&mut self, leaf: Vec<u8>) -> Result<(), &'static str> {
-        if self.leaves.len() >= self.max_leaves {
-            return Err("Exceeded max number of leaves in merkle tree");
-        }
-        self.leaves.push(leaf.clone());
-        self.compute_tree();
+    pub fn config(&self) -> &TreeConfig {
+        &self.config
+    }
 
-        if let Some(ref storage) = self.storage {
-            let _ = storage.store_leaf(self.leaves.len() - 1, &leaf);
-            self.save_to_storage();
-        }
+    /// Cached levels above the leaves, level 0 first; see `nodes` for the
+    /// nodes that are not stored.
+    pub fn cached_levels(&self) -> &[Level] {
+        &self.cached_hashes
+    }
 
-        Ok(())
+    pub fn add_leaf(&mut self, leaf: Vec<u8>) -> Result<(), MerkleError> {
+        self.add_leaves(vec![leaf])
     }
 
-    pub fn add_leaves(&mut self, mut leaves: Vec<Vec<u8>>) -> Result<(), &'static str> {
+    pub fn add_leaves(&mut self, leaves: Vec<Vec<u8>>) -> Result<(), MerkleError> {
         if self.leaves.len() + leaves.len() > self.max_leaves {
-            return Err("Exceeded max number of leaves in merkle tree");
+            return Err(MerkleError::CapacityExceeded {
+                max_leaves: self.max_leaves,
+            });
         }
         let start_index = self.leaves.len();
-        self.leaves.append(&mut leaves);
-        self.compute_tree();
-
-        if let Some(ref storage) = self.storage {
-            let leaves_to_store = &self.leaves[start_index..];
-            let _ = storage.append_leaves(start_index, leaves_to_store);
-            self.save_to_storage();
+        self.leaves.extend(leaves);
+        self.index_leaves(start_index);
+
+        let result = self
+            .extend_tree(start_index)
+            .and_then(|()| match self.storage {
+                Some(_) => self.save_to_storage(start_index..self.leaves.len(), |batch| {
+                    batch.append_leaves(start_index, self.leaves.iter_from(start_index))
+                }),
+                None => Ok(()),
+            });
+
+        // Drop the leaves again so the tree matches what is stored; their
+        // index entries no longer match a leaf and are skipped
+        if result.is_err() {
+            self.leaves.truncate(start_index);
+            self.compute_tree();
         }
 
-        Ok(())
+        result
     }
 
-    pub fn num_leaves(&self) -> usize {
-        self.leaves.len()
+    /// Appends the leaf for `preimage`, as given by
+    /// `TreeConfig::preimage_leaf`, and returns it.
+    pub fn add_leaf_preimage(&mut self, preimage: &[u8]) -> Result<Vec<u8>, MerkleError> {
+        let leaf = self.config.preimage_leaf(preimage);
+        self.add_leaf(leaf.clone())?;
+        Ok(leaf)
     }
 
-    pub fn root(&mut self) -> Option<Vec<u8>> {
-        if self.leaves.is_empty() {
-            return None;
-        }
-
-        if self.cache_valid && self.cached_root.is_some() {
-            return self.cached_root.clone();
-        }
-
-        self.compute_tree();
-        self.cached_root.clone()
+    pub fn add_leaf_preimages(
+        &mut self,
+        preimages: &[Vec<u8>],
+    ) -> Result<Vec<Vec<u8>>, MerkleError> {
+        let leaves: Vec<Vec<u8>> = preimages
+            .iter()
+            .map(|preimage| self.config.preimage_leaf(preimage))
+            .collect();
+        self.add_leaves(leaves.clone())?;
+        Ok(leaves)
     }
 
-    pub fn get_proof(&self, index: usize) -> Option<MerkleProof> {
+    /// Replaces the leaf at `index`, rehashing only its path to the root.
+    pub fn update_leaf(&mut self, index: usize, leaf: Vec<u8>) -> Result<(), MerkleError> {
         if index >= self.leaves.len() {
-            return None;
+            return Err(MerkleError::IndexOutOfRange {
+                index,
+                size: self.leaves.len(),
+            });
         }
-
-        if self.cached_hashes.is_empty() {
-            return None;
+        let previous = std::mem::replace(&mut self.leaves[index], leaf);
+        self.index_leaf(index);
+        self.updates.push(LeafUpdate {
+            index,
+            size: self.leaves.len(),
+            previous,
+        });
+
+        let result = self.update_path(index).and_then(|()| match self.storage {
+            Some(_) => self.save_to_storage(index..index + 1, |batch| {
+                let position = self.updates.len() - 1;
+                batch.store_leaf(index, &self.leaves[index])?;
+                batch.store_leaf_update(position, &self.updates[position])
+            }),
+            None => Ok(()),
+        });
+
+        if result.is_err() {
+            let position = self.updates.len() - 1;
+            let previous = self.updates[position].previous.clone();
+            self.updates.truncate(position);
+            self.leaves[index] = previous;
+            self.compute_tree();
         }
 
-        let mut siblings = Vec::new();
-        let mut current_index = index;
-        let mut current_level = 0;
+        result
+    }
 
-        for _ in 0..MAX_LEVELS {
-            let level_hashes = self.cached_hashes.get(current_level)?;
-            let level_size = if current_level == 0 {
-                self.leaves.len()
-            } else {
-                level_hashes.len()
-            };
+    pub fn num_leaves(&self) -> usize {
+        self.leaves.len()
+    }
 
-            if level_size <= 1 {
-                break;
-            }
+    /// Indices of the leaves equal to `leaf`, in ascending order.
+    pub fn get_leaf_indices(&self, leaf: &[u8]) -> Vec<usize> {
+        self.leaf_indices
+            .get(leaf)
+            .into_iter()
+            .filter(|&index| self.leaves.get(index).is_some_and(|stored| stored == leaf))
+            .collect()
+    }
 
-            let sibling_index = if current_index % 2 == 0 {
-                current_index + 1
-            } else {
-                current_index - 1
-            };
+    pub fn root(&self) -> Option<Vec<u8>> {
+        self.level_view().cached_root()
+    }
 
-            if sibling_index < level_size {
-                if let Some(sibling_hash) = level_hashes.get(sibling_index) {
-                    siblings.push(hex::encode(sibling_hash));
-                }
-            } else {
-                // No sibling, use self
-                if let Some(self_hash) = level_hashes.get(current_index) {
-                    siblings.push(hex::encode(self_hash));
-                }
-            }
+    /// Root of the tree as it was when it last had `size` leaves, rebuilt
+    /// from the cached levels. Leaves changed by `update_leaf` once the tree
+    /// had grown past `size` are taken with the value they had then.
+    pub fn root_at(&self, size: usize) -> Result<Vec<u8>, MerkleError> {
+        self.level_view().root_at(size)
+    }
 
-            current_index /= 2;
-            current_level += 1;
-        }
+    pub fn get_proof(&self, index: usize) -> Result<MerkleProof, MerkleError> {
+        self.get_proof_at(index, self.leaves.len())
+    }
 
-        Some(MerkleProof { siblings })
+    /// Proof for leaf `index` against the root the tree had at `tree_size`.
+    pub fn get_proof_at(&self, index: usize, tree_size: usize) -> Result<MerkleProof, MerkleError> {
+        self.level_view().proof_at(index, tree_size)
+    }
+
+    /// Self-describing proof for leaf `index` against the current root.
+    pub fn get_inclusion_proof(&self, index: usize) -> Result<InclusionProof, MerkleError> {
+        self.get_inclusion_proof_at(index, self.leaves.len())
+    }
+
+    /// Self-describing proof for leaf `index` against the root at `tree_size`.
+    pub fn get_inclusion_proof_at(
+        &self,
+        index: usize,
+        tree_size: usize,
+    ) -> Result<InclusionProof, MerkleError> {
+        let proof = self.get_proof_at(index, tree_size)?;
+        let root = self.root_at(tree_size)?;
+        Ok(InclusionProof::new(
+            &self.config,
+            index,
+            &self.leaves[index],
+            tree_size,
+            &root,
+            proof,
+        ))
     }
 
     pub fn verify_proof(
@@ -164,141 +253,325 @@ impl IncrementalMerkleTree {
         root: &[u8],
         leaf_index: usize,
     ) -> bool {
-        let mut current_hash = leaf.to_vec();
-        let mut current_index = leaf_index;
-
-        for sibling_hex in proof.siblings.iter() {
-            let sibling = match hex::decode(sibling_hex) {
-                Ok(bytes) => bytes,
-                Err(_) => return false,
-            };
-
-            current_hash = if current_index % 2 == 0 {
-                // Current node is left, sibling is right
-                self.hash_pair(&current_hash, &sibling)
-            } else {
-                // Current node is right, sibling is left
-                self.hash_pair(&sibling, &current_hash)
-            };
-
-            current_index /= 2;
-        }
+        self.verify_proof_at(leaf, proof, root, leaf_index, self.leaves.len())
+    }
 
-        current_hash == root
+    /// Checks a proof from `get_proof_at` against the root at `tree_size`.
+    pub fn verify_proof_at(
+        &self,
+        leaf: &[u8],
+        proof: &MerkleProof,
+        root: &[u8],
+        leaf_index: usize,
+        tree_size: usize,
+    ) -> bool {
+        verify::verify_proof(&self.config, leaf, proof, root, leaf_index, tree_size)
     }
 
-    fn compute_tree(&mut self) {
-        if self.leaves.is_empty() {
-            self.cached_hashes.clear();
-            self.cached_root = None;
-            self.cache_valid = true;
-            return;
-        }
+    /// Proof for all of `indices` at once, sharing their common siblings.
+    pub fn get_multiproof(&self, indices: &[usize]) -> Result<MerkleMultiProof, MerkleError> {
+        multiproof::build_multiproof(&self.level_view(), indices)
+    }
 
-        // Level 0: The leaves are the first level of hashes.
-        // We clone them to store them in the cache.
-        self.cached_hashes.clear();
-        self.cached_hashes.push(self.leaves.clone());
+    /// Checks `leaves`, in the order of `proof.indices`, against `root`.
+    pub fn verify_multiproof(
+        &self,
+        leaves: &[Vec<u8>],
+        proof: &MerkleMultiProof,
+        root: &[u8],
+    ) -> bool {
+        verify::verify_multiproof(&self.config, leaves, proof, root, self.leaves.len())
+    }
 
-        let mut current_level = 0;
-        let mut level_size = self.leaves.len();
+    /// Proof that `start..end` are the leaves at those positions.
+    pub fn get_range_proof(&self, start: usize, end: usize) -> Result<RangeProof, MerkleError> {
+        self.get_range_proof_at(start, end, self.leaves.len())
+    }
 
-        while level_size > 1 {
-            if current_level >= MAX_LEVELS {
-                println!("Exceeded max number of leaves in merkle tree");
-                return;
-            }
+    /// Range proof for `start..end` against the root the tree had at
+    /// `tree_size`.
+    pub fn get_range_proof_at(
+        &self,
+        start: usize,
+        end: usize,
+        tree_size: usize,
+    ) -> Result<RangeProof, MerkleError> {
+        range_proof::build_range_proof(&self.level_view(), start, end, tree_size)
+    }
 
-            let mut next_level_hashes = Vec::new();
-            let current_hashes = &self.cached_hashes[current_level];
+    /// Checks `leaves` against the positions in `proof` and `root`.
+    pub fn verify_range_proof(&self, leaves: &[Vec<u8>], proof: &RangeProof, root: &[u8]) -> bool {
+        verify::verify_range_proof(&self.config, leaves, proof, root)
+    }
 
-            for chunk in current_hashes.chunks(2) {
-                let left = chunk.get(0).unwrap();
-                // Get the right element, or if it doesn't exist (odd number of hashes),
-                // use the left element as the right.
-                let right = chunk.get(1).unwrap_or(left);
+    /// Proof that the tree at `old_size` is a prefix of the tree at `new_size`.
+    pub fn consistency_proof(
+        &self,
+        old_size: usize,
+        new_size: usize,
+    ) -> Result<ConsistencyProof, MerkleError> {
+        consistency::build_consistency_proof(&self.level_view(), old_size, new_size)
+    }
 
-                let hash = self.hash_pair(left, right);
-                next_level_hashes.push(hash);
-            }
+    pub fn verify_consistency(
+        &self,
+        old_root: &[u8],
+        new_root: &[u8],
+        proof: &ConsistencyProof,
+    ) -> bool {
+        verify::verify_consistency(&self.config, old_root, new_root, proof)
+    }
 
-            self.cached_hashes.push(next_level_hashes);
-            current_level += 1;
-            level_size = self.cached_hashes[current_level].len();
-        }
+    /// Immutable copy of the tree for readers. It shares the tree's chunks,
+    /// so writes after it copy only the chunks they change.
+    pub fn snapshot(&self) -> TreeSnapshot {
+        TreeSnapshot::from_parts(
+            self.config.clone(),
+            self.zero_hashes.clone(),
+            self.leaves.clone(),
+            self.cached_hashes.clone(),
+            self.updates.clone(),
+        )
+        .with_leaf_index(self.leaf_indices.clone())
+    }
 
-        // Cache root
-        if let Some(root_vec) = self.cached_hashes.last() {
-            if let Some(root) = root_vec.first() {
-                self.cached_root = Some(root.clone());
-            }
+    fn level_view(&self) -> LevelView<'_> {
+        LevelView {
+            config: &self.config,
+            zero_hashes: &self.zero_hashes,
+            leaves: &self.leaves,
+            levels: &self.cached_hashes,
+            updates: &self.updates,
+            num_leaves: self.leaves.len(),
         }
+    }
+
+    fn index_leaves(&self, start_index: usize) {
+        self.leaf_indices
+            .insert((start_index..).zip(self.leaves.iter_from(start_index)));
+    }
+
+    fn index_leaf(&self, index: usize) {
+        self.leaf_indices.insert([(index, &self.leaves[index])]);
+    }
+
+    fn compute_tree(&mut self) {
+        self.cached_hashes.clear();
+        nodes::extend_levels(
+            &self.config,
+            &self.zero_hashes,
+            &mut self.cached_hashes,
+            &self.leaves,
+            0,
+        )
+        .expect("a rebuild does not read cached nodes");
+    }
 
-        self.cache_valid = true;
+    // Appends leaves[start_index..] to the cached levels. Everything left of the
+    // first new leaf's parent is kept.
+    fn extend_tree(&mut self, start_index: usize) -> Result<(), MerkleError> {
+        nodes::extend_levels(
+            &self.config,
+            &self.zero_hashes,
+            &mut self.cached_hashes,
+            &self.leaves,
+            start_index,
+        )
     }
 
-    fn load_from_storage(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    // Rehashes the ancestors of leaves[index] after it changed.
+    fn update_path(&mut self, index: usize) -> Result<(), MerkleError> {
+        nodes::update_levels(
+            &self.config,
+            &self.zero_hashes,
+            &mut self.cached_hashes,
+            &self.leaves,
+            index,
+        )
+    }
+
+    fn load_from_storage(&mut self) -> Result<(), MerkleError> {
         if let Some(ref storage) = self.storage {
             if let Some(metadata) = storage.get_metadata()? {
                 self.max_leaves = metadata.max_leaves;
-                self.cache_valid = self.cache_valid;
             }
 
-            self.leaves = storage.get_all_leaves()?;
+            self.leaves = storage.get_all_leaves()?.into();
             self.cached_hashes = storage.get_all_cache_levels()?;
-            self.cached_root = storage.get_root()?;
+            self.updates = storage.get_leaf_updates()?.into();
+
+            // Caches written in an older layout are rebuilt and stored again,
+            // since later writes only store the nodes they change
+            if !self.level_view().is_complete() {
+                self.compute_tree();
+                if let Some(ref storage) = self.storage {
+                    let mut batch = storage.begin_batch()?;
+                    batch.store_cache_batch(&self.cached_hashes)?;
+                    batch.commit()?;
+                }
+            }
         }
+
+        self.leaf_indices.clear();
+        self.index_leaves(0);
         Ok(())
     }
 
-    fn save_to_storage(&self) {
+    // Writes the leaves through `write_leaves`, then the cache nodes above
+    // the `changed` leaves, the root and metadata, all in one transaction.
+    fn save_to_storage(
+        &self,
+        changed: Range<usize>,
+        write_leaves: impl FnOnce(&mut StorageBatch) -> Result<(), MerkleError>,
+    ) -> Result<(), MerkleError> {
         if let Some(ref storage) = self.storage {
-            let metadata = TreeMetadata {
-                num_leaves: self.leaves.len(),
-                max_leaves: self.max_leaves,
-            };
+            let metadata = TreeMetadata::new(self.leaves.len(), self.max_leaves, &self.config);
 
-            let _ = storage.store_metadata(&metadata);
-            let _ = storage.store_cache_batch(&self.cached_hashes);
+            let mut batch = storage.begin_batch()?;
+            write_leaves(&mut batch)?;
+            batch.store_metadata(&metadata)?;
+            batch.store_cache_nodes(&self.cached_hashes, changed, self.config.arity)?;
 
-            if let Some(ref root) = self.cached_root {
-                let _ = storage.store_root(root);
+            if let Some(root) = self.root() {
+                batch.store_root(&root)?;
             }
 
-            let _ = storage.sync();
+            batch.commit()?;
+            storage.sync()?;
         }
+        Ok(())
     }
 
-    pub fn persist(&self) -> Result<(), Box<dyn std::error::Error>> {
-        if let Some(ref storage) = self.storage {
-            let metadata = TreeMetadata {
-                num_leaves: self.leaves.len(),
-                max_leaves: self.max_leaves,
-            };
+    pub fn persist(&self) -> Result<(), MerkleError> {
+        self.save_to_storage(0..self.leaves.len(), |batch| {
+            batch.append_leaves(0, self.leaves.iter())?;
+            for (position, update) in self.updates.iter().enumerate() {
+                batch.store_leaf_update(position, update)?;
+            }
+            Ok(())
+        })
+    }
+}
 
-            storage.store_leaves_batch(&self.leaves)?;
-            storage.store_metadata(&metadata)?;
-            storage.store_cache_batch(&self.cached_hashes)?;
+/// Positions of the leaves of a heap tree, keyed by the SHA-256 digest of
+/// the leaf so that the index holds no copy of it. Snapshots share it with
+/// the tree and check what it returns against their own leaves.
+///
+/// Entries are only added: an updated leaf stays listed under its previous
+/// value, so snapshots taken before the update still find it there. Like
+/// the update log, the index grows by one entry per update.
+#[derive(Debug, Default)]
+pub(crate) struct LeafIndex(RwLock<HashMap<Hash, Vec<usize>>>);
+
+impl LeafIndex {
+    /// Indices holding `leaf`, or a leaf with the same digest, now or before
+    /// an update, ascending.
+    pub(crate) fn get(&self, leaf: &[u8]) -> Vec<usize> {
+        self.read()
+            .get(&leaf_digest(leaf))
+            .cloned()
+            .unwrap_or_default()
+    }
 
-            if let Some(ref root) = self.cached_root {
-                storage.store_root(root)?;
+    fn insert<'a>(&self, leaves: impl IntoIterator<Item = (usize, &'a Vec<u8>)>) {
+        let mut map = self.write();
+        for (index, leaf) in leaves {
+            let indices = map.entry(leaf_digest(leaf)).or_default();
+            let position = indices.partition_point(|&i| i < index);
+            if indices.get(position) != Some(&index) {
+                indices.insert(position, index);
             }
-
-            storage.sync()?;
         }
-        Ok(())
     }
 
-    fn hash_pair(&self, left: &[u8], right: &[u8]) -> Vec<u8> {
-        let mut hasher = Keccak256::new();
-        hasher.update(left);
-        hasher.update(right);
-        hasher.finalize().to_vec()
+    fn clear(&self) {
+        self.write().clear();
+    }
+
+    // Every write leaves the map whole, so a panic elsewhere does not
+    // poison it
+    fn read(&self) -> RwLockReadGuard<'_, HashMap<Hash, Vec<usize>>> {
+        self.0.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, HashMap<Hash, Vec<usize>>> {
+        self.0.write().unwrap_or_else(PoisonError::into_inner)
     }
 }
 
+fn leaf_digest(leaf: &[u8]) -> Hash {
+    Sha256::digest(leaf).into()
+}
+
 impl Default for IncrementalMerkleTree {
     fn default() -> Self {
         Self::new()
     }
 }
+
+impl MerkleTree for IncrementalMerkleTree {
+    fn config(&self) -> &TreeConfig {
+        &self.config
+    }
+
+    fn append_batch(&mut self, leaves: Vec<Vec<u8>>) -> Result<(), MerkleError> {
+        self.add_leaves(leaves)
+    }
+
+    fn update(&mut self, index: usize, leaf: Vec<u8>) -> Result<(), MerkleError> {
+        self.update_leaf(index, leaf)
+    }
+
+    fn leaf_indices(&self, leaf: &[u8]) -> Result<Vec<usize>, MerkleError> {
+        Ok(self.get_leaf_indices(leaf))
+    }
+
+    fn len(&self) -> Result<usize, MerkleError> {
+        Ok(self.leaves.len())
+    }
+
+    fn root(&self) -> Result<Option<Vec<u8>>, MerkleError> {
+        Ok(IncrementalMerkleTree::root(self))
+    }
+
+    fn root_at(&self, size: usize) -> Result<Vec<u8>, MerkleError> {
+        IncrementalMerkleTree::root_at(self, size)
+    }
+
+    fn proof_at(&self, index: usize, tree_size: usize) -> Result<MerkleProof, MerkleError> {
+        self.get_proof_at(index, tree_size)
+    }
+
+    fn inclusion_proof(
+        &self,
+        index: usize,
+        tree_size: usize,
+    ) -> Result<InclusionProof, MerkleError> {
+        self.get_inclusion_proof_at(index, tree_size)
+    }
+
+    fn multiproof(&self, indices: &[usize]) -> Result<MerkleMultiProof, MerkleError> {
+        self.get_multiproof(indices)
+    }
+
+    fn range_proof(
+        &self,
+        start: usize,
+        end: usize,
+        tree_size: usize,
+    ) -> Result<RangeProof, MerkleError> {
+        self.get_range_proof_at(start, end, tree_size)
+    }
+
+    fn consistency_proof(
+        &self,
+        old_size: usize,
+        new_size: usize,
+    ) -> Result<ConsistencyProof, MerkleError> {
+        IncrementalMerkleTree::consistency_proof(self, old_size, new_size)
+    }
+
+    fn snapshot(&self) -> Result<TreeSnapshot, MerkleError> {
+        Ok(IncrementalMerkleTree::snapshot(self))
+    }
+}
@@ -0,0 +1,338 @@
+use crate::config::TreeConfig;
+use crate::error::MerkleError;
+use crate::verify;
+use serde::{Deserialize, Serialize};
+
+/// Where an MMR keeps its nodes, addressed by position.
+///
+/// Nodes are numbered in the order they are created: each leaf, followed by
+/// the parents it completes. Existing positions are never rewritten.
+pub trait MmrStore {
+    /// Number of nodes stored.
+    fn size(&self) -> Result<usize, MerkleError>;
+
+    fn get_node(&self, pos: usize) -> Result<Option<Vec<u8>>, MerkleError>;
+
+    /// Stores `nodes` at positions from `first_pos` on, all or none of them.
+    fn append_nodes(&mut self, first_pos: usize, nodes: &[Vec<u8>]) -> Result<(), MerkleError>;
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryMmrStore {
+    nodes: Vec<Vec<u8>>,
+}
+
+impl MmrStore for MemoryMmrStore {
+    fn size(&self) -> Result<usize, MerkleError> {
+        Ok(self.nodes.len())
+    }
+
+    fn get_node(&self, pos: usize) -> Result<Option<Vec<u8>>, MerkleError> {
+        Ok(self.nodes.get(pos).cloned())
+    }
+
+    fn append_nodes(&mut self, first_pos: usize, nodes: &[Vec<u8>]) -> Result<(), MerkleError> {
+        self.nodes.truncate(first_pos);
+        self.nodes.extend_from_slice(nodes);
+        Ok(())
+    }
+}
+
+/// Proof that a leaf is in an MMR of `num_leaves` leaves.
+///
+/// `siblings` lead from the leaf up to the peak of its mountain, and `peaks`
+/// holds the other peaks from left to right, so the verifier can bag them
+/// with the one it computed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MmrProof {
+    pub leaf_index: usize,
+    pub num_leaves: usize,
+    pub siblings: Vec<String>,
+    pub peaks: Vec<String>,
+}
+
+/// Merkle Mountain Range: an append-only list of perfect binary trees
+/// ("mountains"), one for each set bit of the leaf count, largest first.
+///
+/// Appending a leaf only adds nodes: the leaf, then a parent for each pair of
+/// equal mountains it completes. The root bags the peaks from the right, the
+/// two rightmost hashed as `H(right || left)` until one hash remains, which
+/// is how `ckb-merkle-mountain-range` bags them.
+#[derive(Debug)]
+pub struct MerkleMountainRange<S: MmrStore = MemoryMmrStore> {
+    store: S,
+    config: TreeConfig,
+    num_leaves: usize,
+    // Hashes of the current peaks, left to right
+    peaks: Vec<Vec<u8>>,
+}
+
+impl MerkleMountainRange<MemoryMmrStore> {
+    pub fn new() -> Self {
+        Self::with_config(TreeConfig::default()).expect("the default config is valid")
+    }
+
+    pub fn with_config(config: TreeConfig) -> Result<Self, MerkleError> {
+        validate_config(&config)?;
+
+        Ok(Self {
+            store: MemoryMmrStore::default(),
+            config,
+            num_leaves: 0,
+            peaks: Vec::new(),
+        })
+    }
+}
+
+impl Default for MerkleMountainRange<MemoryMmrStore> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: MmrStore> MerkleMountainRange<S> {
+    /// Opens an MMR over the nodes already in `store`.
+    pub fn with_store(store: S, config: TreeConfig) -> Result<Self, MerkleError> {
+        validate_config(&config)?;
+
+        let size = store.size()?;
+        let num_leaves = leaves_for_size(size)
+            .ok_or_else(|| MerkleError::Corrupted(format!("Invalid MMR size {}", size)))?;
+        let peaks = peak_positions(num_leaves)
+            .into_iter()
+            .map(|(_, pos)| get_node(&store, pos))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            store,
+            config,
+            num_leaves,
+            peaks,
+        })
+    }
+
+    pub fn config(&self) -> &TreeConfig {
+        &self.config
+    }
+
+    pub fn num_leaves(&self) -> usize {
+        self.num_leaves
+    }
+
+    /// Number of nodes, leaves included.
+    pub fn size(&self) -> usize {
+        mmr_size(self.num_leaves)
+    }
+
+    pub fn add_leaf(&mut self, leaf: Vec<u8>) -> Result<(), MerkleError> {
+        self.add_leaves(vec![leaf])
+    }
+
+    /// Appends all of `leaves` in one write to the store.
+    pub fn add_leaves(&mut self, leaves: Vec<Vec<u8>>) -> Result<(), MerkleError> {
+        let mut peaks = self.peaks.clone();
+        let mut nodes = Vec::new();
+
+        for (offset, leaf) in leaves.iter().enumerate() {
+            let mut hash = self.config.hash_leaf(leaf);
+            nodes.push(hash.clone());
+
+            // Each trailing one bit of the old count is a mountain of the
+            // height just reached, which the new one merges with
+            for _ in 0..(self.num_leaves + offset).trailing_ones() {
+                let left = peaks.pop().expect("a mountain to merge with");
+                hash = self.config.hash_node(&left, &hash);
+                nodes.push(hash.clone());
+            }
+            peaks.push(hash);
+        }
+
+        self.store.append_nodes(self.size(), &nodes)?;
+        self.num_leaves += leaves.len();
+        self.peaks = peaks;
+        Ok(())
+    }
+
+    /// Peak hashes, left to right.
+    pub fn peaks(&self) -> &[Vec<u8>] {
+        &self.peaks
+    }
+
+    /// Bagged peaks, or `None` for an empty MMR.
+    pub fn root(&self) -> Option<Vec<u8>> {
+        bag_peaks(&self.config, &self.peaks)
+    }
+
+    /// Root of the MMR as it was with `num_leaves` leaves. Its peaks are all
+    /// still stored, since appends never touch existing nodes.
+    pub fn root_at(&self, num_leaves: usize) -> Result<Vec<u8>, MerkleError> {
+        self.check_size(num_leaves)?;
+        let peaks = self.peaks_at(num_leaves)?;
+        bag_peaks(&self.config, &peaks).ok_or_else(|| {
+            MerkleError::InvalidArgument("An MMR without leaves has no root".to_string())
+        })
+    }
+
+    pub fn get_proof(&self, index: usize) -> Result<MmrProof, MerkleError> {
+        self.get_proof_at(index, self.num_leaves)
+    }
+
+    /// Proof for leaf `index` against the root the MMR had with
+    /// `num_leaves` leaves.
+    pub fn get_proof_at(&self, index: usize, num_leaves: usize) -> Result<MmrProof, MerkleError> {
+        self.check_size(num_leaves)?;
+        let Some(mountain) = find_mountain(index, num_leaves) else {
+            return Err(MerkleError::IndexOutOfRange {
+                index,
+                size: num_leaves,
+            });
+        };
+
+        let mut pos = mountain.node_offset + mmr_size(mountain.local_index);
+        let mut siblings = Vec::with_capacity(mountain.height);
+        for height in 0..mountain.height {
+            let subtree_size = (2 << height) - 1;
+            let sibling = if mountain.local_index >> height & 1 == 0 {
+                pos += subtree_size + 1;
+                pos - 1
+            } else {
+                pos += 1;
+                pos - 1 - subtree_size
+            };
+            siblings.push(hex::encode(get_node(&self.store, sibling)?));
+        }
+
+        let mut peaks = self.peaks_at(num_leaves)?;
+        peaks.remove(mountain.position);
+
+        Ok(MmrProof {
+            leaf_index: index,
+            num_leaves,
+            siblings,
+            peaks: peaks.iter().map(hex::encode).collect(),
+        })
+    }
+
+    pub fn verify_proof(&self, leaf: &[u8], proof: &MmrProof, root: &[u8]) -> bool {
+        verify::verify_mmr_proof(&self.config, leaf, proof, root)
+    }
+
+    fn peaks_at(&self, num_leaves: usize) -> Result<Vec<Vec<u8>>, MerkleError> {
+        if num_leaves == self.num_leaves {
+            return Ok(self.peaks.clone());
+        }
+        peak_positions(num_leaves)
+            .into_iter()
+            .map(|(_, pos)| get_node(&self.store, pos))
+            .collect()
+    }
+
+    fn check_size(&self, num_leaves: usize) -> Result<(), MerkleError> {
+        if num_leaves > self.num_leaves {
+            return Err(MerkleError::InvalidArgument(format!(
+                "MMR size {} exceeds the {} leaves of the MMR",
+                num_leaves, self.num_leaves
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Bags peaks, given left to right, into a single root.
+pub fn bag_peaks(config: &TreeConfig, peaks: &[Vec<u8>]) -> Option<Vec<u8>> {
+    peaks
+        .iter()
+        .rev()
+        .cloned()
+        .reduce(|bagged, peak| config.hash_node(&bagged, &peak))
+}
+
+/// Number of nodes in an MMR of `num_leaves` leaves, which is also the
+/// position of leaf `num_leaves`.
+pub fn mmr_size(num_leaves: usize) -> usize {
+    2 * num_leaves - num_leaves.count_ones() as usize
+}
+
+/// Height and position of each peak of an MMR of `num_leaves` leaves, left
+/// to right.
+pub fn peak_positions(num_leaves: usize) -> Vec<(usize, usize)> {
+    let mut peaks = Vec::new();
+    let mut offset = 0;
+    for height in (0..usize::BITS as usize).rev() {
+        if num_leaves >> height & 1 == 1 {
+            let mountain_size = (2 << height) - 1;
+            peaks.push((height, offset + mountain_size - 1));
+            offset += mountain_size;
+        }
+    }
+    peaks
+}
+
+// Inverse of `mmr_size`, or `None` if no leaf count gives `size` nodes
+fn leaves_for_size(size: usize) -> Option<usize> {
+    let mut remaining = size;
+    let mut num_leaves = 0;
+    for height in (0..usize::BITS as usize - 1).rev() {
+        let mountain_size = (2 << height) - 1;
+        if remaining >= mountain_size {
+            remaining -= mountain_size;
+            num_leaves |= 1 << height;
+        }
+    }
+    (remaining == 0).then_some(num_leaves)
+}
+
+/// The mountain holding a leaf.
+pub(crate) struct Mountain {
+    /// Index among the mountains, left to right.
+    pub position: usize,
+    pub height: usize,
+    /// Position of the mountain's first node.
+    pub node_offset: usize,
+    /// Index of the leaf within the mountain.
+    pub local_index: usize,
+}
+
+pub(crate) fn find_mountain(index: usize, num_leaves: usize) -> Option<Mountain> {
+    if index >= num_leaves {
+        return None;
+    }
+
+    let mut leaf_offset = 0;
+    let mut node_offset = 0;
+    for (position, (height, _)) in peak_positions(num_leaves).into_iter().enumerate() {
+        let mountain_leaves = 1 << height;
+        if index < leaf_offset + mountain_leaves {
+            return Some(Mountain {
+                position,
+                height,
+                node_offset,
+                local_index: index - leaf_offset,
+            });
+        }
+        leaf_offset += mountain_leaves;
+        node_offset += (2 << height) - 1;
+    }
+    None
+}
+
+pub(crate) fn validate_config(config: &TreeConfig) -> Result<(), MerkleError> {
+    config.validate()?;
+    if config.arity != 2 {
+        return Err(MerkleError::InvalidArgument(
+            "An MMR is built from binary mountains and needs arity 2".to_string(),
+        ));
+    }
+    if config.depth.is_some() {
+        return Err(MerkleError::InvalidArgument(
+            "An MMR grows without bound and cannot have a fixed depth".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn get_node<S: MmrStore>(store: &S, pos: usize) -> Result<Vec<u8>, MerkleError> {
+    store
+        .get_node(pos)?
+        .ok_or_else(|| MerkleError::Corrupted(format!("Missing MMR node {}", pos)))
+}
@@ -0,0 +1,79 @@
+#[cfg(feature = "server")]
+use crate::error::MerkleError;
+#[cfg(feature = "server")]
+use crate::history::{LevelView, missing_nodes};
+use serde::{Deserialize, Serialize};
+
+/// Proof for several leaves against one root.
+///
+/// `indices` are the proven leaf positions in ascending order. `siblings`
+/// holds, level by level from the leaves up, the nodes the verifier cannot
+/// compute from the proven leaves themselves, in ascending position. A node
+/// shared by several paths appears once, and the filler of a short group is
+/// left out since the verifier derives it from the odd node strategy.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleMultiProof {
+    pub indices: Vec<usize>,
+    pub siblings: Vec<String>,
+}
+
+/// Builds a multiproof from the cached levels of a tree.
+#[cfg(feature = "server")]
+pub(crate) fn build_multiproof(
+    view: &LevelView,
+    indices: &[usize],
+) -> Result<MerkleMultiProof, MerkleError> {
+    let (config, num_leaves) = (view.config, view.num_leaves);
+    let mut known = indices.to_vec();
+    known.sort_unstable();
+    known.dedup();
+
+    match known.last() {
+        None => {
+            return Err(MerkleError::InvalidArgument(
+                "No leaf indices to prove".to_string(),
+            ));
+        }
+        Some(&index) if index >= num_leaves => {
+            return Err(MerkleError::IndexOutOfRange {
+                index,
+                size: num_leaves,
+            });
+        }
+        Some(_) => {}
+    }
+
+    let arity = config.arity;
+    let proven = known.clone();
+    let mut siblings = Vec::new();
+    let mut level_size = num_leaves;
+    let mut current_level = 0;
+
+    while !config.is_root_level(current_level, level_size) {
+        let parents: Vec<usize> = known
+            .chunk_by(|a, b| a / arity == b / arity)
+            .map(|group| group[0] / arity)
+            .collect();
+
+        for &parent in &parents {
+            let group_end = level_size.min((parent + 1) * arity);
+            for sibling_index in parent * arity..group_end {
+                if known.binary_search(&sibling_index).is_err() {
+                    let sibling = view
+                        .cached_node(current_level, sibling_index)
+                        .ok_or_else(missing_nodes)?;
+                    siblings.push(hex::encode(sibling));
+                }
+            }
+        }
+
+        known = parents;
+        level_size = level_size.div_ceil(arity);
+        current_level += 1;
+    }
+
+    Ok(MerkleMultiProof {
+        indices: proven,
+        siblings,
+    })
+}
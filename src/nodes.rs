@@ -0,0 +1,180 @@
+//! Compact cache of the levels above the leaves.
+//!
+//! Every cached node is a hash, so each level is a `Level` of contiguous
+//! chunks instead of a heap allocation per node. Two kinds of node are not stored:
+//!
+//! - level 0 of a raw-scheme tree, whose leaf hashes are the leaves
+//!   themselves, so the leaves are read instead;
+//! - a node moved up unchanged by the promote strategy, which is the last
+//!   node of the level below.
+
+use crate::chunked::ChunkedVec;
+#[cfg(feature = "server")]
+use crate::config::{HashScheme, OddNodeStrategy, TreeConfig};
+use crate::error::MerkleError;
+#[cfg(feature = "server")]
+use crate::history::{LevelView, missing_nodes};
+use serde::{Deserialize, Serialize};
+
+/// Size of every node hash. Hashers producing other sizes are rejected by
+/// `TreeConfig::validate`.
+pub const HASH_SIZE: usize = 32;
+
+pub type Hash = [u8; HASH_SIZE];
+
+/// One cached level, shared chunk by chunk with snapshots of the tree.
+pub type Level = ChunkedVec<Hash>;
+
+/// A leaf replaced by an update, kept so that roots of the tree at earlier
+/// sizes can still be rebuilt as they were.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LeafUpdate {
+    /// Position of the leaf.
+    pub index: usize,
+    /// Number of leaves in the tree when it was updated.
+    pub size: usize,
+    /// The leaf before the update.
+    pub previous: Vec<u8>,
+}
+
+/// Converts the output of a hasher into a `Hash`, failing for a hasher of
+/// another size.
+pub fn to_hash(hash: Vec<u8>) -> Result<Hash, MerkleError> {
+    hash.try_into().map_err(|hash: Vec<u8>| {
+        MerkleError::InvalidArgument(format!(
+            "Expected a {}-byte hash, got {} bytes",
+            HASH_SIZE,
+            hash.len()
+        ))
+    })
+}
+
+/// Whether level 0 is cached, which it is unless the leaves are level 0.
+#[cfg(feature = "server")]
+pub(crate) fn caches_leaves(config: &TreeConfig) -> bool {
+    config.scheme != HashScheme::Raw
+}
+
+/// Brings `levels` up to date after `leaves[start_index..]` were appended.
+/// Nodes left of the first new leaf's parent are kept; the rest, including
+/// the previous right-most parent, are hashed again.
+#[cfg(feature = "server")]
+pub(crate) fn extend_levels(
+    config: &TreeConfig,
+    zero_hashes: &[Vec<u8>],
+    levels: &mut Vec<Level>,
+    leaves: &ChunkedVec<Vec<u8>>,
+    start_index: usize,
+) -> Result<(), MerkleError> {
+    if leaves.is_empty() {
+        levels.clear();
+        return Ok(());
+    }
+
+    let start_index = if levels.is_empty() { 0 } else { start_index };
+    if levels.is_empty() {
+        levels.push(Level::new());
+    }
+    if caches_leaves(config) {
+        let new_leaves: Vec<&Vec<u8>> = leaves.iter_from(start_index).collect();
+        levels[0].truncate(start_index);
+        levels[0].extend(config.hash_leaves(&new_leaves)?);
+    }
+
+    let arity = config.arity;
+    let mut level = 0;
+    let mut dirty_index = start_index;
+
+    loop {
+        let view = level_view(config, zero_hashes, leaves, levels);
+        let level_size = view.level_size(level);
+        if config.is_root_level(level, level_size) {
+            break;
+        }
+
+        let parent_index = dirty_index / arity;
+        let children = (parent_index * arity..level_size)
+            .map(|index| view.cached_node(level, index))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(missing_nodes)?;
+        let parents = config.hash_level(&children, &zero_hashes[level])?;
+
+        if levels.len() == level + 1 {
+            levels.push(Level::new());
+        }
+        levels[level + 1].truncate(parent_index);
+        levels[level + 1].extend(parents);
+
+        dirty_index = parent_index;
+        level += 1;
+    }
+
+    levels.truncate(level + 1);
+    Ok(())
+}
+
+/// Rehashes the ancestors of `leaves[index]` after it changed.
+#[cfg(feature = "server")]
+pub(crate) fn update_levels(
+    config: &TreeConfig,
+    zero_hashes: &[Vec<u8>],
+    levels: &mut [Level],
+    leaves: &ChunkedVec<Vec<u8>>,
+    index: usize,
+) -> Result<(), MerkleError> {
+    if caches_leaves(config) {
+        let leaf_hash = config.hash_leaf(&leaves[index]);
+        *levels
+            .first_mut()
+            .and_then(|level| level.get_mut(index))
+            .ok_or_else(missing_nodes)? = to_hash(leaf_hash)?;
+    }
+
+    let arity = config.arity;
+    let mut current_index = index;
+
+    for level in 0..levels.len().saturating_sub(1) {
+        let parent_index = current_index / arity;
+        let view = level_view(config, zero_hashes, leaves, levels);
+        let group_end = view.level_size(level).min((parent_index + 1) * arity);
+        let children = (parent_index * arity..group_end)
+            .map(|index| view.cached_node(level, index))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(missing_nodes)?;
+
+        // A promoted node has no entry of its own to update
+        let promoted =
+            children.len() == 1 && config.odd_node_strategy() == OddNodeStrategy::Promote;
+        if !promoted {
+            let parent = to_hash(config.hash_children(&children, &zero_hashes[level]))?;
+            *levels[level + 1]
+                .get_mut(parent_index)
+                .ok_or_else(missing_nodes)? = parent;
+        }
+
+        current_index = parent_index;
+    }
+
+    Ok(())
+}
+
+// Levels are only read node by node here, never for earlier roots
+#[cfg(feature = "server")]
+static NO_UPDATES: ChunkedVec<LeafUpdate> = ChunkedVec::new();
+
+#[cfg(feature = "server")]
+fn level_view<'a>(
+    config: &'a TreeConfig,
+    zero_hashes: &'a [Vec<u8>],
+    leaves: &'a ChunkedVec<Vec<u8>>,
+    levels: &'a [Level],
+) -> LevelView<'a> {
+    LevelView {
+        config,
+        zero_hashes,
+        leaves,
+        levels,
+        updates: &NO_UPDATES,
+        num_leaves: leaves.len(),
+    }
+}
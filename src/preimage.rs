@@ -0,0 +1,31 @@
+use crate::error::MerkleError;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::{Deserialize, Serialize};
+
+/// How leaf data sent to the server is turned into the bytes that are hashed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LeafEncoding {
+    /// The UTF-8 bytes of the string.
+    #[default]
+    Utf8,
+    /// Standard base64 with padding.
+    Base64,
+    /// A JSON document, serialized compactly with object keys sorted so that
+    /// formatting and key order do not change the leaf.
+    Json,
+}
+
+/// Bytes of the preimage described by `data`.
+pub fn decode_preimage(data: &str, encoding: LeafEncoding) -> Result<Vec<u8>, MerkleError> {
+    match encoding {
+        LeafEncoding::Utf8 => Ok(data.as_bytes().to_vec()),
+        LeafEncoding::Base64 => STANDARD
+            .decode(data)
+            .map_err(|_| MerkleError::InvalidArgument("Invalid base64 data".to_string())),
+        LeafEncoding::Json => serde_json::from_str::<serde_json::Value>(data)
+            .and_then(|value| serde_json::to_vec(&value))
+            .map_err(|_| MerkleError::InvalidArgument("Invalid JSON data".to_string())),
+    }
+}
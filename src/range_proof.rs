@@ -0,0 +1,76 @@
+#[cfg(feature = "server")]
+use crate::error::MerkleError;
+#[cfg(feature = "server")]
+use crate::history::{LevelView, missing_nodes};
+use serde::{Deserialize, Serialize};
+
+/// Proof that a run of leaves sits at exactly the positions `[start, end)`
+/// of the tree with `tree_size` leaves.
+///
+/// Every node above the interior of the range follows from the leaves
+/// themselves, so `siblings` only holds, level by level from the leaves up,
+/// the nodes left of the range in its first group followed by the nodes
+/// right of it in its last group. As in a multiproof, the filler of a short
+/// group is left out.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RangeProof {
+    pub start: usize,
+    pub end: usize,
+    pub tree_size: usize,
+    pub siblings: Vec<String>,
+}
+
+/// Builds the range proof for `[start, end)` in the tree as it was with
+/// `tree_size` leaves.
+#[cfg(feature = "server")]
+pub(crate) fn build_range_proof(
+    view: &LevelView,
+    start: usize,
+    end: usize,
+    tree_size: usize,
+) -> Result<RangeProof, MerkleError> {
+    view.check_size(tree_size)?;
+    if start >= end {
+        return Err(MerkleError::InvalidArgument(
+            "Leaf range is empty".to_string(),
+        ));
+    }
+    if end > tree_size {
+        return Err(MerkleError::IndexOutOfRange {
+            index: end - 1,
+            size: tree_size,
+        });
+    }
+    view.check_provable(tree_size)?;
+
+    let config = view.config;
+    let arity = config.arity;
+    let mut siblings = Vec::new();
+    let (mut low, mut high) = (start, end);
+    let mut level_size = tree_size;
+    let mut current_level = 0;
+
+    while !config.is_root_level(current_level, level_size) {
+        let group_start = low - low % arity;
+        let group_end = level_size.min(high.div_ceil(arity) * arity);
+
+        for sibling_index in (group_start..low).chain(high..group_end) {
+            let sibling = view
+                .node_at(current_level, sibling_index, tree_size)
+                .ok_or_else(missing_nodes)?;
+            siblings.push(hex::encode(sibling));
+        }
+
+        low /= arity;
+        high = high.div_ceil(arity);
+        level_size = level_size.div_ceil(arity);
+        current_level += 1;
+    }
+
+    Ok(RangeProof {
+        start,
+        end,
+        tree_size,
+        siblings,
+    })
+}
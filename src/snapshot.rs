@@ -0,0 +1,206 @@
+//! Immutable snapshots of a tree for readers.
+//!
+//! A snapshot shares the chunks of the tree's leaves and cached levels, so
+//! one can be taken after every write. Roots, sizes and proofs can then be
+//! served from the snapshot while the tree itself is locked for the next
+//! write.
+
+use crate::chunked::ChunkedVec;
+use crate::config::{MAX_LEVELS, TreeConfig};
+use crate::consistency::{self, ConsistencyProof};
+use crate::error::MerkleError;
+use crate::history::LevelView;
+use crate::merkle_tree::LeafIndex;
+use crate::multiproof::{self, MerkleMultiProof};
+use crate::nodes::{LeafUpdate, Level};
+use crate::range_proof::{self, RangeProof};
+use crate::storage::LmdbStorage;
+use crate::verify::{InclusionProof, MerkleProof};
+use std::sync::Arc;
+
+#[derive(Debug, Clone)]
+pub struct TreeSnapshot {
+    config: TreeConfig,
+    zero_hashes: Arc<[Vec<u8>]>,
+    leaves: ChunkedVec<Vec<u8>>,
+    levels: Vec<Level>,
+    updates: ChunkedVec<LeafUpdate>,
+    root: Option<Vec<u8>>,
+    // Preimages are looked up when asked for rather than copied
+    storage: Option<Arc<LmdbStorage>>,
+    // Positions of leaves, looked up in the heap tree's index
+    leaf_index: Option<Arc<LeafIndex>>,
+}
+
+impl TreeSnapshot {
+    /// Snapshot of a tree with `leaves`, cached `levels` as laid out in
+    /// `nodes`, and every leaf update so far, oldest first. The leaves may
+    /// be shared with the tree that is snapshotted. Fails if `config` is
+    /// invalid.
+    pub fn new(
+        config: TreeConfig,
+        leaves: ChunkedVec<Vec<u8>>,
+        levels: Vec<Level>,
+        updates: ChunkedVec<LeafUpdate>,
+    ) -> Result<Self, MerkleError> {
+        config.validate()?;
+        let zero_hashes = config.zero_hashes(MAX_LEVELS).into();
+        Ok(Self::from_parts(
+            config,
+            zero_hashes,
+            leaves,
+            levels,
+            updates,
+        ))
+    }
+
+    /// Like `new`, for a tree that already validated `config` and holds its
+    /// `zero_hashes`.
+    pub(crate) fn from_parts(
+        config: TreeConfig,
+        zero_hashes: Arc<[Vec<u8>]>,
+        leaves: ChunkedVec<Vec<u8>>,
+        levels: Vec<Level>,
+        updates: ChunkedVec<LeafUpdate>,
+    ) -> Self {
+        let mut snapshot = Self {
+            config,
+            zero_hashes,
+            leaves,
+            levels,
+            updates,
+            root: None,
+            storage: None,
+            leaf_index: None,
+        };
+        snapshot.root = snapshot.level_view().cached_root();
+        snapshot
+    }
+
+    /// Reads preimages, and positions of leaves, from `storage`.
+    pub fn with_storage(self, storage: Arc<LmdbStorage>) -> Self {
+        Self {
+            storage: Some(storage),
+            ..self
+        }
+    }
+
+    pub(crate) fn with_leaf_index(self, leaf_index: Arc<LeafIndex>) -> Self {
+        Self {
+            leaf_index: Some(leaf_index),
+            ..self
+        }
+    }
+
+    pub fn config(&self) -> &TreeConfig {
+        &self.config
+    }
+
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    /// Root of the tree when the snapshot was taken, or `None` if it was
+    /// empty and has no fixed depth.
+    pub fn root(&self) -> Option<&[u8]> {
+        self.root.as_deref()
+    }
+
+    /// Root of the tree as it was when it last had `size` leaves.
+    pub fn root_at(&self, size: usize) -> Result<Vec<u8>, MerkleError> {
+        self.level_view().root_at(size)
+    }
+
+    /// Indices of the leaves equal to `leaf` when the snapshot was taken, in
+    /// ascending order. They are looked up in the tree's index, or its
+    /// storage, and checked against the snapshot's leaves; a snapshot with
+    /// neither scans its leaves. Neither index drops the entries of updated
+    /// leaves, so those are found as the snapshot still holds them.
+    pub fn get_leaf_indices(&self, leaf: &[u8]) -> Result<Vec<usize>, MerkleError> {
+        let candidates = match (&self.leaf_index, &self.storage) {
+            (Some(leaf_index), _) => leaf_index.get(leaf),
+            (None, Some(storage)) => storage.get_leaf_index_entries(leaf, self.len())?,
+            (None, None) => (0..self.len()).collect(),
+        };
+        Ok(candidates
+            .into_iter()
+            .filter(|&index| self.leaves.get(index).is_some_and(|stored| stored == leaf))
+            .collect())
+    }
+
+    pub fn get_proof(&self, index: usize) -> Result<MerkleProof, MerkleError> {
+        self.get_proof_at(index, self.len())
+    }
+
+    /// Proof for leaf `index` against the root the tree had at `tree_size`.
+    pub fn get_proof_at(&self, index: usize, tree_size: usize) -> Result<MerkleProof, MerkleError> {
+        self.level_view().proof_at(index, tree_size)
+    }
+
+    /// Self-describing proof for leaf `index` against the root at `tree_size`.
+    pub fn get_inclusion_proof_at(
+        &self,
+        index: usize,
+        tree_size: usize,
+    ) -> Result<InclusionProof, MerkleError> {
+        let proof = self.get_proof_at(index, tree_size)?;
+        let root = self.root_at(tree_size)?;
+        Ok(InclusionProof::new(
+            &self.config,
+            index,
+            &self.leaves[index],
+            tree_size,
+            &root,
+            proof,
+        ))
+    }
+
+    /// Proof for all of `indices` at once, sharing their common siblings.
+    pub fn get_multiproof(&self, indices: &[usize]) -> Result<MerkleMultiProof, MerkleError> {
+        multiproof::build_multiproof(&self.level_view(), indices)
+    }
+
+    /// Range proof for `start..end` against the root the tree had at
+    /// `tree_size`.
+    pub fn get_range_proof_at(
+        &self,
+        start: usize,
+        end: usize,
+        tree_size: usize,
+    ) -> Result<RangeProof, MerkleError> {
+        range_proof::build_range_proof(&self.level_view(), start, end, tree_size)
+    }
+
+    /// Proof that the tree at `old_size` is a prefix of the tree at `new_size`.
+    pub fn consistency_proof(
+        &self,
+        old_size: usize,
+        new_size: usize,
+    ) -> Result<ConsistencyProof, MerkleError> {
+        consistency::build_consistency_proof(&self.level_view(), old_size, new_size)
+    }
+
+    /// Preimage stored for leaf `index` as it was when the snapshot was
+    /// taken, for trees that keep them.
+    pub fn get_preimage(&self, index: usize) -> Result<Option<Vec<u8>>, MerkleError> {
+        match (&self.storage, self.leaves.get(index)) {
+            (Some(storage), Some(leaf)) => storage.get_preimage(leaf),
+            _ => Ok(None),
+        }
+    }
+
+    fn level_view(&self) -> LevelView<'_> {
+        LevelView {
+            config: &self.config,
+            zero_hashes: &self.zero_hashes,
+            leaves: &self.leaves,
+            levels: &self.levels,
+            updates: &self.updates,
+            num_leaves: self.leaves.len(),
+        }
+    }
+}
@@ -0,0 +1,495 @@
+use crate::error::MerkleError;
+use crate::hasher::{Keccak256Hasher, MerkleHasher};
+#[cfg(feature = "server")]
+use crate::storage::LmdbStorage;
+use crate::verify::verify_sparse_proof;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Height of the tree: one level per key bit.
+pub const SPARSE_DEPTH: usize = 256;
+
+pub type SparseKey = [u8; 32];
+
+// Domain separation between leaf and branch hashes
+const LEAF_PREFIX: u8 = 0x00;
+const BRANCH_PREFIX: u8 = 0x01;
+
+/// Stored node of a sparse tree. Empty subtrees are never stored.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SparseNode {
+    /// The only key of its subtree, stored at the root of that subtree
+    /// instead of at the bottom of the tree.
+    Leaf { key: SparseKey, value_hash: Vec<u8> },
+    /// Subtree holding two or more keys.
+    Branch { hash: Vec<u8> },
+}
+
+/// Node writes produced by one update: `(height, prefix, node)`, where a
+/// `None` node removes the entry.
+pub type SparseNodeUpdate = (usize, SparseKey, Option<SparseNode>);
+
+/// Nodes along the path of a key, from the root down to the first node that
+/// is not a branch.
+#[derive(Debug, Clone, Default)]
+pub struct SparsePath {
+    /// Sibling of each node below the root on the path; entry `i` is at
+    /// height `SPARSE_DEPTH - 1 - i`.
+    pub siblings: Vec<Option<SparseNode>>,
+    /// The node where the path ends, at height
+    /// `SPARSE_DEPTH - siblings.len()`: empty or a leaf.
+    pub end: Option<SparseNode>,
+}
+
+/// Backing store for the non-empty nodes and values of a sparse tree.
+pub trait SparseStore {
+    fn get_node(
+        &self,
+        height: usize,
+        prefix: &SparseKey,
+    ) -> Result<Option<SparseNode>, MerkleError>;
+
+    /// Nodes on the path of `key`. Stores that can should read them in one
+    /// pass, see `read_path`.
+    fn get_path(&self, key: &SparseKey) -> Result<SparsePath, MerkleError> {
+        read_path(key, |height, prefix| self.get_node(height, prefix))
+    }
+
+    fn get_value(&self, key: &SparseKey) -> Result<Option<Vec<u8>>, MerkleError>;
+
+    /// Applies the value change and all node writes of one update
+    /// atomically, in order.
+    fn commit(
+        &mut self,
+        key: &SparseKey,
+        value: Option<&[u8]>,
+        nodes: &[SparseNodeUpdate],
+    ) -> Result<(), MerkleError>;
+}
+
+/// Walks the path of `key` from the root, reading nodes with `get_node`.
+pub fn read_path(
+    key: &SparseKey,
+    mut get_node: impl FnMut(usize, &SparseKey) -> Result<Option<SparseNode>, MerkleError>,
+) -> Result<SparsePath, MerkleError> {
+    let mut path = SparsePath {
+        siblings: Vec::new(),
+        end: get_node(SPARSE_DEPTH, &[0u8; 32])?,
+    };
+    let mut height = SPARSE_DEPTH;
+
+    while let Some(SparseNode::Branch { .. }) = path.end {
+        // A subtree at height 0 holds a single key
+        height = height.checked_sub(1).ok_or_else(|| {
+            MerkleError::Corrupted("Sparse tree has a branch below its last level".to_string())
+        })?;
+        path.siblings
+            .push(get_node(height, &sibling_prefix(key, height))?);
+        path.end = get_node(height, &prefix(key, height))?;
+    }
+    Ok(path)
+}
+
+#[derive(Debug, Default)]
+pub struct MemorySparseStore {
+    nodes: HashMap<(usize, SparseKey), SparseNode>,
+    values: HashMap<SparseKey, Vec<u8>>,
+}
+
+impl SparseStore for MemorySparseStore {
+    fn get_node(
+        &self,
+        height: usize,
+        prefix: &SparseKey,
+    ) -> Result<Option<SparseNode>, MerkleError> {
+        Ok(self.nodes.get(&(height, *prefix)).cloned())
+    }
+
+    fn get_value(&self, key: &SparseKey) -> Result<Option<Vec<u8>>, MerkleError> {
+        Ok(self.values.get(key).cloned())
+    }
+
+    fn commit(
+        &mut self,
+        key: &SparseKey,
+        value: Option<&[u8]>,
+        nodes: &[SparseNodeUpdate],
+    ) -> Result<(), MerkleError> {
+        match value {
+            Some(value) => self.values.insert(*key, value.to_vec()),
+            None => self.values.remove(key),
+        };
+
+        for (height, prefix, node) in nodes {
+            match node {
+                Some(node) => self.nodes.insert((*height, *prefix), node.clone()),
+                None => self.nodes.remove(&(*height, *prefix)),
+            };
+        }
+        Ok(())
+    }
+}
+
+/// Leaf of another key where the path of an absent key ends.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SparseProofLeaf {
+    pub key: String,
+    pub value_hash: String,
+}
+
+/// Proof that `key` maps to `value` (membership) or to nothing
+/// (non-membership, `value` is `None`).
+///
+/// The path of `key` is followed for `depth` levels from the root, down to
+/// the subtree holding `key`. For an absent key that subtree is empty or
+/// holds the single key of `other_leaf`. Siblings that are empty subtrees
+/// are left out: bit `h` of `bitmap` is set when the sibling at height `h`
+/// is included, and `siblings` lists the included ones from the bottom of
+/// the path upwards.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SparseMerkleProof {
+    pub key: String,
+    pub value: Option<String>,
+    pub other_leaf: Option<SparseProofLeaf>,
+    pub depth: usize,
+    pub bitmap: String,
+    pub siblings: Vec<String>,
+}
+
+/// Sparse Merkle tree mapping 256-bit keys to values.
+///
+/// Every key has a fixed path given by its bits (most significant bit first
+/// from the root). A subtree holding a single key is stored as that key's
+/// leaf, hashed as `H(0x00 || key || H(value))`, and an empty subtree hashes
+/// to zero bytes, so only the branches above two or more keys are stored and
+/// hashed, as `H(0x01 || left || right)`. A path is about `log2(n)` levels
+/// deep for `n` keys spread over the key space.
+#[derive(Debug)]
+pub struct SparseMerkleTree<S: SparseStore = MemorySparseStore> {
+    store: S,
+    hasher: Arc<dyn MerkleHasher>,
+}
+
+impl SparseMerkleTree<MemorySparseStore> {
+    pub fn new() -> Self {
+        Self::with_hasher(Arc::new(Keccak256Hasher))
+    }
+
+    pub fn with_hasher(hasher: Arc<dyn MerkleHasher>) -> Self {
+        Self::with_store(MemorySparseStore::default(), hasher)
+    }
+}
+
+impl Default for SparseMerkleTree<MemorySparseStore> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "server")]
+impl SparseMerkleTree<LmdbStorage> {
+    /// Opens a persisted sparse tree, keeping the hasher it was created with.
+    pub fn new_with_storage(storage_path: &str) -> Result<Self, MerkleError> {
+        let storage = LmdbStorage::new(storage_path)?;
+        let hasher = match storage.get_sparse_hash_algorithm()? {
+            Some(id) => crate::hasher::hasher_from_id(&id)
+                .ok_or_else(|| MerkleError::Corrupted(format!("Unknown hash algorithm: {}", id)))?,
+            None => Arc::new(Keccak256Hasher),
+        };
+        storage.store_sparse_hash_algorithm(hasher.id())?;
+        Ok(Self::with_store(storage, hasher))
+    }
+
+    /// Opens a persisted sparse tree, failing if it uses a different hasher.
+    pub fn new_with_storage_and_hasher(
+        storage_path: &str,
+        hasher: Arc<dyn MerkleHasher>,
+    ) -> Result<Self, MerkleError> {
+        let storage = LmdbStorage::new(storage_path)?;
+        match storage.get_sparse_hash_algorithm()? {
+            Some(id) if id != hasher.id() => {
+                return Err(MerkleError::ConfigMismatch(format!(
+                    "Tree was created with {}, not {}",
+                    id,
+                    hasher.id()
+                )));
+            }
+            Some(_) => {}
+            None => storage.store_sparse_hash_algorithm(hasher.id())?,
+        }
+        Ok(Self::with_store(storage, hasher))
+    }
+}
+
+impl<S: SparseStore> SparseMerkleTree<S> {
+    pub fn with_store(store: S, hasher: Arc<dyn MerkleHasher>) -> Self {
+        Self { store, hasher }
+    }
+
+    pub fn insert(&mut self, key: SparseKey, value: Vec<u8>) -> Result<(), MerkleError> {
+        let leaf = SparseNode::Leaf {
+            key,
+            value_hash: self.hasher.hash(&value),
+        };
+        self.update(key, Some(&value), Some(leaf))
+    }
+
+    /// Removes `key`, returning its previous value.
+    pub fn remove(&mut self, key: SparseKey) -> Result<Option<Vec<u8>>, MerkleError> {
+        let previous = self.store.get_value(&key)?;
+        if previous.is_some() {
+            self.update(key, None, None)?;
+        }
+        Ok(previous)
+    }
+
+    pub fn get(&self, key: &SparseKey) -> Result<Option<Vec<u8>>, MerkleError> {
+        self.store.get_value(key)
+    }
+
+    pub fn root(&self) -> Result<Vec<u8>, MerkleError> {
+        let root = self.store.get_node(SPARSE_DEPTH, &[0u8; 32])?;
+        Ok(self.node_hash(root.as_ref()))
+    }
+
+    pub fn get_proof(&self, key: SparseKey) -> Result<SparseMerkleProof, MerkleError> {
+        let path = self.store.get_path(&key)?;
+        let mut bitmap = [0u8; 32];
+        let mut siblings = Vec::new();
+
+        for (depth, sibling) in path.siblings.iter().enumerate().rev() {
+            if let Some(sibling) = sibling {
+                set_bit(&mut bitmap, SPARSE_DEPTH - 1 - depth);
+                siblings.push(hex::encode(self.node_hash(Some(sibling))));
+            }
+        }
+
+        let (value, other_leaf) = match path.end {
+            Some(SparseNode::Leaf { key: leaf_key, .. }) if leaf_key == key => {
+                let value = self.store.get_value(&key)?.ok_or_else(|| {
+                    MerkleError::Corrupted("Sparse tree leaf has no value".to_string())
+                })?;
+                (Some(hex::encode(value)), None)
+            }
+            Some(SparseNode::Leaf {
+                key: leaf_key,
+                value_hash,
+            }) => (
+                None,
+                Some(SparseProofLeaf {
+                    key: hex::encode(leaf_key),
+                    value_hash: hex::encode(value_hash),
+                }),
+            ),
+            _ => (None, None),
+        };
+
+        Ok(SparseMerkleProof {
+            key: hex::encode(key),
+            value,
+            other_leaf,
+            depth: path.siblings.len(),
+            bitmap: hex::encode(bitmap),
+            siblings,
+        })
+    }
+
+    /// Checks a membership or non-membership proof against `root`.
+    pub fn verify_proof(&self, proof: &SparseMerkleProof, root: &[u8]) -> bool {
+        verify_sparse_proof(&*self.hasher, proof, root)
+    }
+
+    // Replaces the subtree at the end of the path of `key` with `leaf`, or
+    // empties it, and rewrites every node up to the root. A leaf left alone
+    // in its parent's subtree moves up to the parent.
+    fn update(
+        &mut self,
+        key: SparseKey,
+        value: Option<&[u8]>,
+        leaf: Option<SparseNode>,
+    ) -> Result<(), MerkleError> {
+        let mut path = self.store.get_path(&key)?;
+        let mut height = SPARSE_DEPTH - path.siblings.len();
+        let mut nodes = Vec::new();
+
+        let mut current = match (leaf, path.end) {
+            (None, Some(SparseNode::Leaf { key: leaf_key, .. })) if leaf_key == key => None,
+            (None, _) => {
+                return Err(MerkleError::Corrupted(
+                    "Sparse tree has no leaf for a stored key".to_string(),
+                ));
+            }
+            // Another key's leaf: both move down to where their paths split
+            (Some(leaf), Some(other @ SparseNode::Leaf { key: other_key, .. }))
+                if other_key != key =>
+            {
+                let split_height = SPARSE_DEPTH - 1 - first_different_bit(&key, &other_key);
+                let mut hash = self.hash_children(
+                    &key,
+                    split_height,
+                    &self.node_hash(Some(&leaf)),
+                    &self.node_hash(Some(&other)),
+                );
+                nodes.push((split_height, prefix(&other_key, split_height), Some(other)));
+                nodes.push((split_height, prefix(&key, split_height), Some(leaf)));
+
+                for below in split_height + 1..height {
+                    nodes.push((
+                        below,
+                        prefix(&key, below),
+                        Some(SparseNode::Branch { hash: hash.clone() }),
+                    ));
+                    hash = self.hash_children(&key, below, &hash, &self.empty_hash());
+                }
+                Some(SparseNode::Branch { hash })
+            }
+            (leaf, _) => leaf,
+        };
+
+        while height < SPARSE_DEPTH {
+            let sibling = path.siblings.pop().ok_or_else(|| {
+                MerkleError::Corrupted("Sparse tree path is missing nodes".to_string())
+            })?;
+            let (parent, moved_up) = match (&current, sibling) {
+                (None, None) => (None, false),
+                (Some(leaf @ SparseNode::Leaf { .. }), None) => (Some(leaf.clone()), true),
+                (None, Some(leaf @ SparseNode::Leaf { .. })) => {
+                    nodes.push((height, sibling_prefix(&key, height), None));
+                    (Some(leaf), false)
+                }
+                (current, sibling) => {
+                    let hash = self.hash_children(
+                        &key,
+                        height,
+                        &self.node_hash(current.as_ref()),
+                        &self.node_hash(sibling.as_ref()),
+                    );
+                    (Some(SparseNode::Branch { hash }), false)
+                }
+            };
+
+            let stored = if moved_up { None } else { current };
+            nodes.push((height, prefix(&key, height), stored));
+            current = parent;
+            height += 1;
+        }
+        nodes.push((SPARSE_DEPTH, [0u8; 32], current));
+
+        self.store.commit(&key, value, &nodes)
+    }
+
+    fn empty_hash(&self) -> Vec<u8> {
+        sparse_empty_hash(&*self.hasher)
+    }
+
+    fn node_hash(&self, node: Option<&SparseNode>) -> Vec<u8> {
+        match node {
+            None => self.empty_hash(),
+            Some(SparseNode::Leaf { key, value_hash }) => {
+                sparse_leaf_hash(&*self.hasher, key, value_hash)
+            }
+            Some(SparseNode::Branch { hash }) => hash.clone(),
+        }
+    }
+
+    fn hash_children(
+        &self,
+        key: &SparseKey,
+        height: usize,
+        node: &[u8],
+        sibling: &[u8],
+    ) -> Vec<u8> {
+        sparse_hash_children(&*self.hasher, key, height, node, sibling)
+    }
+}
+
+/// Hash of an empty subtree at any height: zero bytes.
+pub(crate) fn sparse_empty_hash(hasher: &dyn MerkleHasher) -> Vec<u8> {
+    vec![0u8; hasher.hash(&[]).len()]
+}
+
+pub(crate) fn sparse_leaf_hash(
+    hasher: &dyn MerkleHasher,
+    key: &SparseKey,
+    value_hash: &[u8],
+) -> Vec<u8> {
+    hasher.hash_parts(&[&[LEAF_PREFIX], key, value_hash])
+}
+
+// Parent of the node at `height` on the path of `key`
+pub(crate) fn sparse_hash_children(
+    hasher: &dyn MerkleHasher,
+    key: &SparseKey,
+    height: usize,
+    node: &[u8],
+    sibling: &[u8],
+) -> Vec<u8> {
+    let (left, right) = if get_bit(key, SPARSE_DEPTH - 1 - height) {
+        (sibling, node)
+    } else {
+        (node, sibling)
+    };
+    hasher.hash_parts(&[&[BRANCH_PREFIX], left, right])
+}
+
+// Bit `index` counted from the most significant bit of the first byte
+pub(crate) fn get_bit(bytes: &[u8; 32], index: usize) -> bool {
+    bytes[index / 8] & (0x80 >> (index % 8)) != 0
+}
+
+fn set_bit(bytes: &mut [u8; 32], index: usize) {
+    bytes[index / 8] |= 0x80 >> (index % 8);
+}
+
+// Index of the first bit, from the most significant, where two different
+// keys differ
+fn first_different_bit(key: &SparseKey, other: &SparseKey) -> usize {
+    (0..SPARSE_DEPTH)
+        .find(|&index| get_bit(key, index) != get_bit(other, index))
+        .expect("the keys differ")
+}
+
+// Key of the node at `height` on the path of `key`: its lowest `height` bits cleared
+pub(crate) fn prefix(key: &SparseKey, height: usize) -> SparseKey {
+    let mut prefix = *key;
+    for index in SPARSE_DEPTH - height..SPARSE_DEPTH {
+        prefix[index / 8] &= !(0x80 >> (index % 8));
+    }
+    prefix
+}
+
+fn sibling_prefix(key: &SparseKey, height: usize) -> SparseKey {
+    let mut sibling = prefix(key, height);
+    let index = SPARSE_DEPTH - 1 - height;
+    sibling[index / 8] ^= 0x80 >> (index % 8);
+    sibling
+}
+
+#[cfg(feature = "server")]
+impl SparseStore for LmdbStorage {
+    fn get_node(
+        &self,
+        height: usize,
+        prefix: &SparseKey,
+    ) -> Result<Option<SparseNode>, MerkleError> {
+        self.get_sparse_node(height, prefix)
+    }
+
+    fn get_path(&self, key: &SparseKey) -> Result<SparsePath, MerkleError> {
+        self.get_sparse_path(key)
+    }
+
+    fn get_value(&self, key: &SparseKey) -> Result<Option<Vec<u8>>, MerkleError> {
+        self.get_sparse_value(key)
+    }
+
+    fn commit(
+        &mut self,
+        key: &SparseKey,
+        value: Option<&[u8]>,
+        nodes: &[SparseNodeUpdate],
+    ) -> Result<(), MerkleError> {
+        self.commit_sparse_update(key, value, nodes)
+    }
+}
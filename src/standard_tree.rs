@@ -0,0 +1,121 @@
+use crate::config::{HashScheme, TreeConfig};
+use crate::error::MerkleError;
+use crate::verify::{self, MerkleProof};
+
+/// Tree laid out like OpenZeppelin's `StandardMerkleTree`, so its root can
+/// be used with the `MerkleProof` library in Solidity contracts.
+///
+/// Leaves are `keccak256(keccak256(data))`, where `data` is usually
+/// `abi.encode(values)`, and nodes hash sorted pairs. Unlike the other trees
+/// in this crate it is built once from all of its leaves: the nodes are
+/// stored as a complete binary tree in one array, so the shape of a tree
+/// whose size is not a power of two differs from `IncrementalMerkleTree`.
+#[derive(Debug, Clone)]
+pub struct StandardMerkleTree {
+    config: TreeConfig,
+    // Node `i` has children `2i + 1` and `2i + 2`; the leaves fill the end
+    nodes: Vec<Vec<u8>>,
+    // Position in `nodes` of each leaf, in the order the leaves were given
+    leaf_positions: Vec<usize>,
+}
+
+impl StandardMerkleTree {
+    /// Builds the tree with the leaves sorted by hash, which is what
+    /// `StandardMerkleTree.of` does by default. Proofs are still requested
+    /// by the position of the leaf in `leaves`.
+    pub fn of(leaves: &[Vec<u8>]) -> Result<Self, MerkleError> {
+        Self::build(leaves, true)
+    }
+
+    /// Builds the tree with the leaves in the given order, like
+    /// `StandardMerkleTree.of` with `{ sortLeaves: false }`.
+    pub fn of_unsorted(leaves: &[Vec<u8>]) -> Result<Self, MerkleError> {
+        Self::build(leaves, false)
+    }
+
+    fn build(leaves: &[Vec<u8>], sort_leaves: bool) -> Result<Self, MerkleError> {
+        if leaves.is_empty() {
+            return Err(MerkleError::InvalidArgument(
+                "Expected a non-zero number of leaves".to_string(),
+            ));
+        }
+
+        let config = TreeConfig {
+            scheme: HashScheme::OpenZeppelin,
+            ..TreeConfig::default()
+        };
+
+        let mut hashed: Vec<(usize, Vec<u8>)> = leaves
+            .iter()
+            .map(|leaf| config.hash_leaf(leaf))
+            .enumerate()
+            .collect();
+        if sort_leaves {
+            hashed.sort_by(|a, b| a.1.cmp(&b.1));
+        }
+
+        let len = 2 * leaves.len() - 1;
+        let mut nodes = vec![Vec::new(); len];
+        let mut leaf_positions = vec![0; leaves.len()];
+        for (tree_order, (leaf_index, hash)) in hashed.into_iter().enumerate() {
+            let position = len - 1 - tree_order;
+            nodes[position] = hash;
+            leaf_positions[leaf_index] = position;
+        }
+
+        for i in (0..len - leaves.len()).rev() {
+            nodes[i] = config.hash_node(&nodes[2 * i + 1], &nodes[2 * i + 2]);
+        }
+
+        Ok(Self {
+            config,
+            nodes,
+            leaf_positions,
+        })
+    }
+
+    pub fn config(&self) -> &TreeConfig {
+        &self.config
+    }
+
+    pub fn root(&self) -> Vec<u8> {
+        self.nodes[0].clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.leaf_positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaf_positions.is_empty()
+    }
+
+    /// Hash of the leaf at `index`, as the contract computes it.
+    pub fn leaf_hash(&self, index: usize) -> Option<Vec<u8>> {
+        let position = *self.leaf_positions.get(index)?;
+        Some(self.nodes[position].clone())
+    }
+
+    /// Proof for the leaf at `index`, with the siblings from the leaf up.
+    pub fn get_proof(&self, index: usize) -> Option<MerkleProof> {
+        let mut position = *self.leaf_positions.get(index)?;
+        let mut siblings = Vec::new();
+
+        while position > 0 {
+            let sibling = if position.is_multiple_of(2) {
+                position - 1
+            } else {
+                position + 1
+            };
+            siblings.push(hex::encode(&self.nodes[sibling]));
+            position = (position - 1) / 2;
+        }
+
+        Some(MerkleProof { siblings })
+    }
+
+    /// Checks that `leaf` is in this tree; the proof carries no index.
+    pub fn verify(&self, leaf: &[u8], proof: &MerkleProof) -> bool {
+        verify::verify_sorted_proof(&self.config, leaf, proof, &self.root())
+    }
+}
@@ -1,13 +1,164 @@
+use crate::config::{HashScheme, OddNodeStrategy, TreeConfig};
+use crate::error::MerkleError;
+use crate::hasher::{Keccak256Hasher, MerkleHasher, hasher_from_id};
+use crate::nodes::{Hash, LeafUpdate, Level};
+use crate::sparse_tree::{SparseKey, SparseNode, SparseNodeUpdate, SparsePath, read_path};
 use lmdb::{
     Cursor, Database, DatabaseFlags, Environment, EnvironmentFlags, Transaction, WriteFlags,
 };
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::ops::Range;
 use std::path::Path;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TreeMetadata {
     pub num_leaves: usize,
     pub max_leaves: usize,
+    pub hash_algorithm: String,
+    pub hash_scheme: HashScheme,
+    pub odd_node: OddNodeStrategy,
+    pub depth: Option<usize>,
+    pub arity: usize,
+}
+
+// Metadata layout written before the hashing config was recorded
+#[derive(Deserialize)]
+struct LegacyTreeMetadata {
+    num_leaves: usize,
+    max_leaves: usize,
+}
+
+impl TreeMetadata {
+    pub fn new(num_leaves: usize, max_leaves: usize, config: &TreeConfig) -> Self {
+        Self {
+            num_leaves,
+            max_leaves,
+            hash_algorithm: config.hasher.id().to_string(),
+            hash_scheme: config.scheme,
+            odd_node: config.odd_node,
+            depth: config.depth,
+            arity: config.arity,
+        }
+    }
+
+    /// Rebuilds the config a tree was created with.
+    pub fn config(&self) -> Result<TreeConfig, MerkleError> {
+        let hasher = hasher_from_id(&self.hash_algorithm).ok_or_else(|| {
+            MerkleError::Corrupted(format!("Unknown hash algorithm: {}", self.hash_algorithm))
+        })?;
+        Ok(TreeConfig {
+            hasher,
+            scheme: self.hash_scheme,
+            odd_node: self.odd_node,
+            depth: self.depth,
+            arity: self.arity,
+        })
+    }
+
+    /// Fails if `config` would hash differently from the stored tree.
+    pub fn check_config(&self, config: &TreeConfig) -> Result<(), MerkleError> {
+        if config.hasher.id() != self.hash_algorithm {
+            return Err(MerkleError::ConfigMismatch(format!(
+                "Tree was created with {}, not {}",
+                self.hash_algorithm,
+                config.hasher.id()
+            )));
+        }
+        if config.scheme != self.hash_scheme {
+            return Err(MerkleError::ConfigMismatch(format!(
+                "Tree was created with the {:?} hash scheme, not {:?}",
+                self.hash_scheme, config.scheme
+            )));
+        }
+        if config.odd_node != self.odd_node {
+            return Err(MerkleError::ConfigMismatch(format!(
+                "Tree was created with the {:?} odd node strategy, not {:?}",
+                self.odd_node, config.odd_node
+            )));
+        }
+        if config.depth != self.depth {
+            return Err(MerkleError::ConfigMismatch(format!(
+                "Tree was created with depth {:?}, not {:?}",
+                self.depth, config.depth
+            )));
+        }
+        if config.arity != self.arity {
+            return Err(MerkleError::ConfigMismatch(format!(
+                "Tree was created with arity {}, not {}",
+                self.arity, config.arity
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Default for TreeMetadata {
+    fn default() -> Self {
+        Self {
+            num_leaves: 0,
+            max_leaves: 0,
+            hash_algorithm: Keccak256Hasher.id().to_string(),
+            hash_scheme: HashScheme::default(),
+            odd_node: OddNodeStrategy::default(),
+            depth: None,
+            arity: 2,
+        }
+    }
+}
+
+impl From<LegacyTreeMetadata> for TreeMetadata {
+    fn from(legacy: LegacyTreeMetadata) -> Self {
+        Self {
+            num_leaves: legacy.num_leaves,
+            max_leaves: legacy.max_leaves,
+            ..Self::default()
+        }
+    }
+}
+
+/// Hashing parameters of a persisted MMR. Its mountains are always binary
+/// and unpadded, so only the hasher and scheme can differ.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MmrMetadata {
+    pub hash_algorithm: String,
+    pub hash_scheme: HashScheme,
+}
+
+impl MmrMetadata {
+    pub fn new(config: &TreeConfig) -> Self {
+        Self {
+            hash_algorithm: config.hasher.id().to_string(),
+            hash_scheme: config.scheme,
+        }
+    }
+
+    pub fn config(&self) -> Result<TreeConfig, MerkleError> {
+        TreeMetadata {
+            hash_algorithm: self.hash_algorithm.clone(),
+            hash_scheme: self.hash_scheme,
+            ..TreeMetadata::default()
+        }
+        .config()
+    }
+
+    /// Fails if `config` would hash differently from the stored MMR.
+    pub fn check_config(&self, config: &TreeConfig) -> Result<(), MerkleError> {
+        if config.hasher.id() != self.hash_algorithm {
+            return Err(MerkleError::ConfigMismatch(format!(
+                "MMR was created with {}, not {}",
+                self.hash_algorithm,
+                config.hasher.id()
+            )));
+        }
+        if config.scheme != self.hash_scheme {
+            return Err(MerkleError::ConfigMismatch(format!(
+                "MMR was created with the {:?} hash scheme, not {:?}",
+                self.hash_scheme, config.scheme
+            )));
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -16,48 +167,76 @@ pub struct LmdbStorage {
     leaves_db: Database,
     cache_db: Database,
     metadata_db: Database,
+    sparse_nodes_db: Database,
+    sparse_values_db: Database,
+    preimages_db: Database,
+    mmr_nodes_db: Database,
+    // Leaf digest -> indices holding that leaf or that held it before an
+    // update, see `leaf_index_key`
+    leaf_index_db: Database,
+    // Leaf updates in the order they were made, keyed by their position
+    leaf_updates_db: Database,
 }
 
 impl LmdbStorage {
-    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, MerkleError> {
         let env = Environment::new()
             .set_flags(EnvironmentFlags::NO_SUB_DIR)
-            .set_max_dbs(3)
+            .set_max_dbs(10)
             .set_map_size(1024 * 1024 * 1024) // 1GB
             .open(path.as_ref())?;
 
         let leaves_db = env.create_db(Some("leaves"), DatabaseFlags::empty())?;
         let cache_db = env.create_db(Some("cache"), DatabaseFlags::empty())?;
         let metadata_db = env.create_db(Some("metadata"), DatabaseFlags::empty())?;
+        let sparse_nodes_db = env.create_db(Some("sparse_nodes"), DatabaseFlags::empty())?;
+        let sparse_values_db = env.create_db(Some("sparse_values"), DatabaseFlags::empty())?;
+        let preimages_db = env.create_db(Some("preimages"), DatabaseFlags::empty())?;
+        let mmr_nodes_db = env.create_db(Some("mmr_nodes"), DatabaseFlags::empty())?;
+        let leaf_index_db = env.create_db(Some("leaf_index"), DatabaseFlags::DUP_SORT)?;
+        let leaf_updates_db = env.create_db(Some("leaf_updates"), DatabaseFlags::empty())?;
 
         Ok(Self {
             env,
             leaves_db,
             cache_db,
             metadata_db,
+            sparse_nodes_db,
+            sparse_values_db,
+            preimages_db,
+            mmr_nodes_db,
+            leaf_index_db,
+            leaf_updates_db,
         })
     }
 
-    // Leaf operations
-    pub fn store_leaf(&self, index: usize, leaf: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
-        let mut txn = self.env.begin_rw_txn()?;
-        let key = index.to_be_bytes();
-        txn.put(self.leaves_db, &key, &leaf, WriteFlags::empty())?;
-        txn.commit()?;
-        Ok(())
+    /// Starts a write transaction. Nothing written through the batch is
+    /// visible until `commit`, and dropping it discards every write.
+    pub fn begin_batch(&self) -> Result<StorageBatch<'_>, MerkleError> {
+        Ok(StorageBatch {
+            storage: self,
+            txn: self.env.begin_rw_txn()?,
+        })
+    }
+
+    // Leaf operations. Every write also keeps the leaf index up to date.
+    pub fn store_leaf(&self, index: usize, leaf: &[u8]) -> Result<(), MerkleError> {
+        let mut batch = self.begin_batch()?;
+        batch.store_leaf(index, leaf)?;
+        batch.commit()
     }
 
-    pub fn get_leaf(&self, index: usize) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+    pub fn get_leaf(&self, index: usize) -> Result<Option<Vec<u8>>, MerkleError> {
         let txn = self.env.begin_ro_txn()?;
         let key = index.to_be_bytes();
         match txn.get(self.leaves_db, &key) {
             Ok(data) => Ok(Some(data.to_vec())),
             Err(lmdb::Error::NotFound) => Ok(None),
-            Err(e) => Err(Box::new(e)),
+            Err(e) => Err(e.into()),
         }
     }
 
-    pub fn get_all_leaves(&self) -> Result<Vec<Vec<u8>>, Box<dyn std::error::Error>> {
+    pub fn get_all_leaves(&self) -> Result<Vec<Vec<u8>>, MerkleError> {
         let txn = self.env.begin_ro_txn()?;
         let mut cursor = txn.open_ro_cursor(self.leaves_db)?;
         let mut leaves = Vec::new();
@@ -69,157 +248,708 @@ impl LmdbStorage {
         Ok(leaves)
     }
 
-    pub fn store_leaves_batch(&self, leaves: &[Vec<u8>]) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn store_leaves_batch<I>(&self, leaves: I) -> Result<(), MerkleError>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
         let mut txn = self.env.begin_rw_txn()?;
 
-        for (index, leaf) in leaves.iter().enumerate() {
+        for (index, leaf) in leaves.into_iter().enumerate() {
             let key = index.to_be_bytes();
             txn.put(self.leaves_db, &key, &leaf, WriteFlags::empty())?;
+            txn.put(
+                self.leaf_index_db,
+                &leaf_index_key(leaf.as_ref()),
+                &key,
+                WriteFlags::empty(),
+            )?;
         }
 
         txn.commit()?;
         Ok(())
     }
 
-    pub fn append_leaves(
+    pub fn append_leaves<I>(&self, start_index: usize, leaves: I) -> Result<(), MerkleError>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let mut batch = self.begin_batch()?;
+        batch.append_leaves(start_index, leaves)?;
+        batch.commit()
+    }
+
+    /// Indices below `num_leaves` of the leaves equal to `leaf`, in
+    /// ascending order. Leaves stored past the tree's size are not part of
+    /// it.
+    pub fn get_leaf_indices(
         &self,
-        start_index: usize,
-        leaves: &[Vec<u8>],
-    ) -> Result<(), Box<dyn std::error::Error>> {
+        leaf: &[u8],
+        num_leaves: usize,
+    ) -> Result<Vec<usize>, MerkleError> {
+        let txn = self.env.begin_ro_txn()?;
+        let mut indices = Vec::new();
+        for index in read_leaf_index(&txn, self.leaf_index_db, leaf, num_leaves)? {
+            // Entries of updated leaves, or left by an overwritten batch, no
+            // longer match their leaf
+            match txn.get(self.leaves_db, &index.to_be_bytes()) {
+                Ok(stored) if stored == leaf => indices.push(index),
+                Ok(_) | Err(lmdb::Error::NotFound) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(indices)
+    }
+
+    /// Indices below `num_leaves` that hold `leaf` or held it before an
+    /// update, or a leaf with the same digest, in ascending order. Entries
+    /// are never removed, so a snapshot checks them against its own leaves.
+    pub fn get_leaf_index_entries(
+        &self,
+        leaf: &[u8],
+        num_leaves: usize,
+    ) -> Result<Vec<usize>, MerkleError> {
+        let txn = self.env.begin_ro_txn()?;
+        read_leaf_index(&txn, self.leaf_index_db, leaf, num_leaves)
+    }
+
+    /// Rebuilds the leaf index from the stored leaves, for databases written
+    /// before it existed.
+    pub fn rebuild_leaf_index(&self) -> Result<(), MerkleError> {
         let mut txn = self.env.begin_rw_txn()?;
+        txn.clear_db(self.leaf_index_db)?;
 
-        for (offset, leaf) in leaves.iter().enumerate() {
-            let key = (start_index + offset).to_be_bytes();
-            txn.put(self.leaves_db, &key, &leaf, WriteFlags::empty())?;
+        let entries: Vec<([u8; 32], Vec<u8>)> = {
+            let mut cursor = txn.open_ro_cursor(self.leaves_db)?;
+            cursor
+                .iter()
+                .map(|(key, leaf)| (leaf_index_key(leaf), key.to_vec()))
+                .collect()
+        };
+        for (digest, key) in entries {
+            txn.put(self.leaf_index_db, &digest, &key, WriteFlags::empty())?;
         }
 
         txn.commit()?;
         Ok(())
     }
 
-    // Cache operations
-    pub fn store_cache_level(
-        &self,
-        level: usize,
-        hashes: &[Vec<u8>],
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    // Cache operations. Every node is its own entry keyed by level and
+    // index, so a write touches only the nodes that changed.
+    pub fn store_cache_level(&self, level: usize, hashes: &[Hash]) -> Result<(), MerkleError> {
+        let mut batch = self.begin_batch()?;
+        let num_levels = batch.num_cache_levels()?.max(level + 1);
+        batch.store_cache_nodes_from(level, 0, hashes)?;
+        batch.store_num_cache_levels(num_levels)?;
+        batch.commit()
+    }
+
+    pub fn get_cache_level(&self, level: usize) -> Result<Option<Vec<Hash>>, MerkleError> {
+        let txn = self.env.begin_ro_txn()?;
+        if level >= read_num_cache_levels(&txn, self.cache_db)?.unwrap_or(0) {
+            return Ok(None);
+        }
+
+        let mut hashes = Vec::new();
+        loop {
+            match txn.get(self.cache_db, &cache_node_key(level, hashes.len())) {
+                Ok(data) => hashes.push(decode_cache_node(data)?),
+                Err(lmdb::Error::NotFound) => return Ok(Some(hashes)),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// All cached levels in order. A cache written in the layout used before
+    /// nodes were stored one by one is skipped, so trees rebuild it.
+    pub fn get_all_cache_levels(&self) -> Result<Vec<Level>, MerkleError> {
+        let txn = self.env.begin_ro_txn()?;
+        let Some(num_levels) = read_num_cache_levels(&txn, self.cache_db)? else {
+            return Ok(Vec::new());
+        };
+
+        let mut levels = vec![Level::new(); num_levels];
+        let mut cursor = txn.open_ro_cursor(self.cache_db)?;
+        // Keys sort by level, then by index, so each level is read in order
+        for (key, value) in cursor.iter() {
+            let Some((level, index)) = parse_cache_node_key(key) else {
+                continue;
+            };
+            let level = levels
+                .get_mut(level)
+                .filter(|level| level.len() == index)
+                .ok_or_else(|| MerkleError::Corrupted("Cache has a missing node".to_string()))?;
+            level.push(decode_cache_node(value)?);
+        }
+        Ok(levels)
+    }
+
+    /// Replaces the whole cache with `cache_levels`.
+    pub fn store_cache_batch(&self, cache_levels: &[Level]) -> Result<(), MerkleError> {
+        let mut batch = self.begin_batch()?;
+        batch.store_cache_batch(cache_levels)?;
+        batch.commit()
+    }
+
+    pub fn clear_cache(&self) -> Result<(), MerkleError> {
         let mut txn = self.env.begin_rw_txn()?;
-        let key = format!("level_{}", level);
-        let serialized = bincode::serialize(hashes)?;
-        txn.put(self.cache_db, &key, &serialized, WriteFlags::empty())?;
+        txn.clear_db(self.cache_db)?;
         txn.commit()?;
         Ok(())
     }
 
-    pub fn get_cache_level(
-        &self,
-        level: usize,
-    ) -> Result<Option<Vec<Vec<u8>>>, Box<dyn std::error::Error>> {
+    // Metadata operations
+    pub fn store_metadata(&self, metadata: &TreeMetadata) -> Result<(), MerkleError> {
+        let mut batch = self.begin_batch()?;
+        batch.store_metadata(metadata)?;
+        batch.commit()
+    }
+
+    pub fn get_metadata(&self) -> Result<Option<TreeMetadata>, MerkleError> {
         let txn = self.env.begin_ro_txn()?;
-        let key = format!("level_{}", level);
-        match txn.get(self.cache_db, &key) {
+        match txn.get(self.metadata_db, &"tree_metadata") {
             Ok(data) => {
-                let hashes: Vec<Vec<u8>> = bincode::deserialize(data)?;
-                Ok(Some(hashes))
+                let metadata = bincode::deserialize::<TreeMetadata>(data).or_else(|_| {
+                    bincode::deserialize::<LegacyTreeMetadata>(data).map(TreeMetadata::from)
+                })?;
+                Ok(Some(metadata))
             }
             Err(lmdb::Error::NotFound) => Ok(None),
-            Err(e) => Err(Box::new(e)),
+            Err(e) => Err(e.into()),
         }
     }
 
-    pub fn get_all_cache_levels(&self) -> Result<Vec<Vec<Vec<u8>>>, Box<dyn std::error::Error>> {
-        let txn = self.env.begin_ro_txn()?;
-        let mut cursor = txn.open_ro_cursor(self.cache_db)?;
-        let mut levels = Vec::new();
+    pub fn store_root(&self, root: &[u8]) -> Result<(), MerkleError> {
+        let mut batch = self.begin_batch()?;
+        batch.store_root(root)?;
+        batch.commit()
+    }
 
-        for (_key, value) in cursor.iter() {
-            let hashes: Vec<Vec<u8>> = bincode::deserialize(value)?;
-            levels.push(hashes);
+    pub fn get_root(&self) -> Result<Option<Vec<u8>>, MerkleError> {
+        let txn = self.env.begin_ro_txn()?;
+        match txn.get(self.metadata_db, &"cached_root") {
+            Ok(data) => Ok(Some(data.to_vec())),
+            Err(lmdb::Error::NotFound) => Ok(None),
+            Err(e) => Err(e.into()),
         }
+    }
 
-        Ok(levels)
+    /// Every leaf update, oldest first.
+    pub fn get_leaf_updates(&self) -> Result<Vec<LeafUpdate>, MerkleError> {
+        let txn = self.env.begin_ro_txn()?;
+        let mut cursor = txn.open_ro_cursor(self.leaf_updates_db)?;
+        let mut updates = Vec::new();
+
+        for (key, value) in cursor.iter() {
+            if key != updates.len().to_be_bytes() {
+                return Err(MerkleError::Corrupted(
+                    "Leaf update log has a gap".to_string(),
+                ));
+            }
+            updates.push(bincode::deserialize(value)?);
+        }
+        Ok(updates)
     }
 
-    pub fn store_cache_batch(
+    // Leaf preimages, keyed by the digest of the leaf they hash to, so that
+    // an update leaves them in place for snapshots taken before it
+    pub fn store_preimages(
         &self,
-        cache_levels: &[Vec<Vec<u8>>],
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let mut txn = self.env.begin_rw_txn()?;
+        leaves: &[Vec<u8>],
+        preimages: &[Vec<u8>],
+    ) -> Result<(), MerkleError> {
+        let mut batch = self.begin_batch()?;
+        batch.store_preimages(leaves, preimages)?;
+        batch.commit()
+    }
 
-        for (level, hashes) in cache_levels.iter().enumerate() {
-            let key = format!("level_{}", level);
-            let serialized = bincode::serialize(hashes)?;
-            txn.put(self.cache_db, &key, &serialized, WriteFlags::empty())?;
+    /// Preimage stored for `leaf`, whichever indices hold it.
+    pub fn get_preimage(&self, leaf: &[u8]) -> Result<Option<Vec<u8>>, MerkleError> {
+        let txn = self.env.begin_ro_txn()?;
+        match txn.get(self.preimages_db, &leaf_index_key(leaf)) {
+            Ok(data) => Ok(Some(data.to_vec())),
+            Err(lmdb::Error::NotFound) => Ok(None),
+            Err(e) => Err(e.into()),
         }
+    }
 
-        txn.commit()?;
-        Ok(())
+    // Sparse tree operations
+    pub fn get_sparse_node(
+        &self,
+        height: usize,
+        prefix: &SparseKey,
+    ) -> Result<Option<SparseNode>, MerkleError> {
+        let txn = self.env.begin_ro_txn()?;
+        read_sparse_node(&txn, self.sparse_nodes_db, height, prefix)
+    }
+
+    /// Nodes on the path of `key`, read in one transaction.
+    pub fn get_sparse_path(&self, key: &SparseKey) -> Result<SparsePath, MerkleError> {
+        let txn = self.env.begin_ro_txn()?;
+        read_path(key, |height, prefix| {
+            read_sparse_node(&txn, self.sparse_nodes_db, height, prefix)
+        })
+    }
+
+    pub fn get_sparse_value(&self, key: &SparseKey) -> Result<Option<Vec<u8>>, MerkleError> {
+        let txn = self.env.begin_ro_txn()?;
+        match txn.get(self.sparse_values_db, key) {
+            Ok(data) => Ok(Some(data.to_vec())),
+            Err(lmdb::Error::NotFound) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
     }
 
-    pub fn clear_cache(&self) -> Result<(), Box<dyn std::error::Error>> {
+    /// Writes a sparse tree update in one transaction, in order. `None`
+    /// values and nodes delete the entry.
+    pub fn commit_sparse_update(
+        &self,
+        key: &[u8; 32],
+        value: Option<&[u8]>,
+        nodes: &[SparseNodeUpdate],
+    ) -> Result<(), MerkleError> {
         let mut txn = self.env.begin_rw_txn()?;
-        txn.clear_db(self.cache_db)?;
+
+        match value {
+            Some(value) => txn.put(self.sparse_values_db, key, &value, WriteFlags::empty())?,
+            None => delete_if_present(&mut txn, self.sparse_values_db, key)?,
+        }
+
+        for (height, prefix, node) in nodes {
+            let node_key = sparse_node_key(*height, prefix);
+            match node {
+                Some(node) => txn.put(
+                    self.sparse_nodes_db,
+                    &node_key,
+                    &bincode::serialize(node)?,
+                    WriteFlags::empty(),
+                )?,
+                None => delete_if_present(&mut txn, self.sparse_nodes_db, &node_key)?,
+            }
+        }
+
         txn.commit()?;
         Ok(())
     }
 
-    // Metadata operations
-    pub fn store_metadata(
-        &self,
-        metadata: &TreeMetadata,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn store_sparse_hash_algorithm(&self, id: &str) -> Result<(), MerkleError> {
         let mut txn = self.env.begin_rw_txn()?;
-        let serialized = bincode::serialize(metadata)?;
         txn.put(
             self.metadata_db,
-            &"tree_metadata",
-            &serialized,
+            &"sparse_hash_algorithm",
+            &id,
             WriteFlags::empty(),
         )?;
         txn.commit()?;
         Ok(())
     }
 
-    pub fn get_metadata(&self) -> Result<Option<TreeMetadata>, Box<dyn std::error::Error>> {
+    pub fn get_sparse_hash_algorithm(&self) -> Result<Option<String>, MerkleError> {
         let txn = self.env.begin_ro_txn()?;
-        match txn.get(self.metadata_db, &"tree_metadata") {
-            Ok(data) => {
-                let metadata: TreeMetadata = bincode::deserialize(data)?;
-                Ok(Some(metadata))
-            }
+        match txn.get(self.metadata_db, &"sparse_hash_algorithm") {
+            Ok(data) => String::from_utf8(data.to_vec())
+                .map(Some)
+                .map_err(|_| MerkleError::Corrupted("Invalid sparse hash algorithm".to_string())),
+            Err(lmdb::Error::NotFound) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // MMR operations, nodes keyed by position
+    pub fn get_mmr_node(&self, pos: usize) -> Result<Option<Vec<u8>>, MerkleError> {
+        let txn = self.env.begin_ro_txn()?;
+        let key = pos.to_be_bytes();
+        match txn.get(self.mmr_nodes_db, &key) {
+            Ok(data) => Ok(Some(data.to_vec())),
             Err(lmdb::Error::NotFound) => Ok(None),
-            Err(e) => Err(Box::new(e)),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Number of MMR nodes, written with the nodes so the two always agree.
+    pub fn get_mmr_size(&self) -> Result<usize, MerkleError> {
+        let txn = self.env.begin_ro_txn()?;
+        match txn.get(self.metadata_db, &"mmr_size") {
+            Ok(data) => data
+                .try_into()
+                .map(usize::from_be_bytes)
+                .map_err(|_| MerkleError::Corrupted("Invalid MMR size".to_string())),
+            Err(lmdb::Error::NotFound) => Ok(0),
+            Err(e) => Err(e.into()),
         }
     }
 
-    pub fn store_root(&self, root: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn append_mmr_nodes(&self, first_pos: usize, nodes: &[Vec<u8>]) -> Result<(), MerkleError> {
         let mut txn = self.env.begin_rw_txn()?;
-        txn.put(self.metadata_db, &"cached_root", &root, WriteFlags::empty())?;
+
+        for (offset, node) in nodes.iter().enumerate() {
+            let key = (first_pos + offset).to_be_bytes();
+            txn.put(self.mmr_nodes_db, &key, &node, WriteFlags::empty())?;
+        }
+        let size = (first_pos + nodes.len()).to_be_bytes();
+        txn.put(self.metadata_db, &"mmr_size", &size, WriteFlags::empty())?;
+
         txn.commit()?;
         Ok(())
     }
 
-    pub fn get_root(&self) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+    pub fn store_mmr_metadata(&self, metadata: &MmrMetadata) -> Result<(), MerkleError> {
+        let mut txn = self.env.begin_rw_txn()?;
+        let serialized = bincode::serialize(metadata)?;
+        txn.put(
+            self.metadata_db,
+            &"mmr_metadata",
+            &serialized,
+            WriteFlags::empty(),
+        )?;
+        txn.commit()?;
+        Ok(())
+    }
+
+    pub fn get_mmr_metadata(&self) -> Result<Option<MmrMetadata>, MerkleError> {
         let txn = self.env.begin_ro_txn()?;
-        match txn.get(self.metadata_db, &"cached_root") {
-            Ok(data) => Ok(Some(data.to_vec())),
+        match txn.get(self.metadata_db, &"mmr_metadata") {
+            Ok(data) => Ok(Some(bincode::deserialize(data)?)),
             Err(lmdb::Error::NotFound) => Ok(None),
-            Err(e) => Err(Box::new(e)),
+            Err(e) => Err(e.into()),
         }
     }
 
     // Utility operations
-    pub fn clear_all(&self) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn clear_all(&self) -> Result<(), MerkleError> {
         let mut txn = self.env.begin_rw_txn()?;
         txn.clear_db(self.leaves_db)?;
         txn.clear_db(self.cache_db)?;
         txn.clear_db(self.metadata_db)?;
+        txn.clear_db(self.sparse_nodes_db)?;
+        txn.clear_db(self.sparse_values_db)?;
+        txn.clear_db(self.preimages_db)?;
+        txn.clear_db(self.mmr_nodes_db)?;
+        txn.clear_db(self.leaf_index_db)?;
+        txn.clear_db(self.leaf_updates_db)?;
         txn.commit()?;
         Ok(())
     }
 
-    pub fn sync(&self) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn sync(&self) -> Result<(), MerkleError> {
         self.env.sync(true)?;
         Ok(())
     }
 }
+
+/// Writes to the tree databases that land in one transaction, so an
+/// operation is stored entirely or not at all.
+pub struct StorageBatch<'env> {
+    storage: &'env LmdbStorage,
+    txn: lmdb::RwTransaction<'env>,
+}
+
+impl StorageBatch<'_> {
+    /// Stores `leaf` at `index`. The index entry of the leaf it replaces is
+    /// kept for snapshots taken before.
+    pub fn store_leaf(&mut self, index: usize, leaf: &[u8]) -> Result<(), MerkleError> {
+        let storage = self.storage;
+        let key = index.to_be_bytes();
+        self.txn
+            .put(storage.leaves_db, &key, &leaf, WriteFlags::empty())?;
+        self.txn.put(
+            storage.leaf_index_db,
+            &leaf_index_key(leaf),
+            &key,
+            WriteFlags::empty(),
+        )?;
+        Ok(())
+    }
+
+    pub fn append_leaves<I>(&mut self, start_index: usize, leaves: I) -> Result<(), MerkleError>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let storage = self.storage;
+        for (offset, leaf) in leaves.into_iter().enumerate() {
+            let key = (start_index + offset).to_be_bytes();
+            self.txn
+                .put(storage.leaves_db, &key, &leaf, WriteFlags::empty())?;
+            self.txn.put(
+                storage.leaf_index_db,
+                &leaf_index_key(leaf.as_ref()),
+                &key,
+                WriteFlags::empty(),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the whole cache with `cache_levels`.
+    pub fn store_cache_batch(&mut self, cache_levels: &[Level]) -> Result<(), MerkleError> {
+        self.txn.clear_db(self.storage.cache_db)?;
+        for (level, hashes) in cache_levels.iter().enumerate() {
+            for (index, hash) in hashes.iter().enumerate() {
+                self.put_cache_node(level, index, hash)?;
+            }
+        }
+        self.store_num_cache_levels(cache_levels.len())
+    }
+
+    /// Writes the cached nodes above the leaves in `changed`, the ones an
+    /// append or update rehashed, and drops stored nodes past the end of
+    /// `cache_levels`. The other nodes must be stored already.
+    pub fn store_cache_nodes(
+        &mut self,
+        cache_levels: &[Level],
+        changed: Range<usize>,
+        arity: usize,
+    ) -> Result<(), MerkleError> {
+        let Range { mut start, mut end } = changed;
+        for (level, hashes) in cache_levels.iter().enumerate() {
+            let end_index = end.min(hashes.len());
+            if start < end_index {
+                for (offset, hash) in hashes.iter_from(start).take(end_index - start).enumerate() {
+                    self.put_cache_node(level, start + offset, hash)?;
+                }
+            }
+            self.delete_cache_nodes_from(level, hashes.len())?;
+
+            start /= arity;
+            end = end.div_ceil(arity);
+        }
+
+        for level in cache_levels.len()..self.num_cache_levels()? {
+            self.delete_cache_nodes_from(level, 0)?;
+        }
+        self.store_num_cache_levels(cache_levels.len())
+    }
+
+    fn store_cache_nodes_from(
+        &mut self,
+        level: usize,
+        start_index: usize,
+        hashes: &[Hash],
+    ) -> Result<(), MerkleError> {
+        for (offset, hash) in hashes.iter().enumerate() {
+            self.put_cache_node(level, start_index + offset, hash)?;
+        }
+        self.delete_cache_nodes_from(level, start_index + hashes.len())
+    }
+
+    fn put_cache_node(
+        &mut self,
+        level: usize,
+        index: usize,
+        hash: &Hash,
+    ) -> Result<(), MerkleError> {
+        self.txn.put(
+            self.storage.cache_db,
+            &cache_node_key(level, index),
+            hash,
+            WriteFlags::empty(),
+        )?;
+        Ok(())
+    }
+
+    fn delete_cache_nodes_from(
+        &mut self,
+        level: usize,
+        start_index: usize,
+    ) -> Result<(), MerkleError> {
+        for index in start_index.. {
+            match self
+                .txn
+                .del(self.storage.cache_db, &cache_node_key(level, index), None)
+            {
+                Ok(()) => {}
+                Err(lmdb::Error::NotFound) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+
+    fn num_cache_levels(&self) -> Result<usize, MerkleError> {
+        Ok(read_num_cache_levels(&self.txn, self.storage.cache_db)?.unwrap_or(0))
+    }
+
+    fn store_num_cache_levels(&mut self, num_levels: usize) -> Result<(), MerkleError> {
+        self.txn.put(
+            self.storage.cache_db,
+            &CACHE_LEVELS_KEY,
+            &num_levels.to_be_bytes(),
+            WriteFlags::empty(),
+        )?;
+        Ok(())
+    }
+
+    pub fn clear_cache(&mut self) -> Result<(), MerkleError> {
+        self.txn.clear_db(self.storage.cache_db)?;
+        Ok(())
+    }
+
+    pub fn store_metadata(&mut self, metadata: &TreeMetadata) -> Result<(), MerkleError> {
+        let serialized = bincode::serialize(metadata)?;
+        self.txn.put(
+            self.storage.metadata_db,
+            &"tree_metadata",
+            &serialized,
+            WriteFlags::empty(),
+        )?;
+        Ok(())
+    }
+
+    pub fn store_root(&mut self, root: &[u8]) -> Result<(), MerkleError> {
+        self.txn.put(
+            self.storage.metadata_db,
+            &"cached_root",
+            &root,
+            WriteFlags::empty(),
+        )?;
+        Ok(())
+    }
+
+    /// Logs the update at `position` in the update log.
+    pub fn store_leaf_update(
+        &mut self,
+        position: usize,
+        update: &LeafUpdate,
+    ) -> Result<(), MerkleError> {
+        let serialized = bincode::serialize(update)?;
+        self.txn.put(
+            self.storage.leaf_updates_db,
+            &position.to_be_bytes(),
+            &serialized,
+            WriteFlags::empty(),
+        )?;
+        Ok(())
+    }
+
+    /// Stores each of `preimages` under the leaf at the same position of
+    /// `leaves`, which it gives.
+    pub fn store_preimages(
+        &mut self,
+        leaves: &[Vec<u8>],
+        preimages: &[Vec<u8>],
+    ) -> Result<(), MerkleError> {
+        for (leaf, preimage) in leaves.iter().zip(preimages) {
+            self.txn.put(
+                self.storage.preimages_db,
+                &leaf_index_key(leaf),
+                &preimage,
+                WriteFlags::empty(),
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn commit(self) -> Result<(), MerkleError> {
+        self.txn.commit()?;
+        Ok(())
+    }
+}
+
+// Leaves can be longer than an LMDB key, so the index and the preimages are
+// keyed by their SHA-256 digest and lookups compare the stored leaf
+fn leaf_index_key(leaf: &[u8]) -> [u8; 32] {
+    Sha256::digest(leaf).into()
+}
+
+// Entries of the leaf index for `leaf` below `num_leaves`, ascending
+fn read_leaf_index(
+    txn: &impl Transaction,
+    leaf_index_db: Database,
+    leaf: &[u8],
+    num_leaves: usize,
+) -> Result<Vec<usize>, MerkleError> {
+    let mut cursor = txn.open_ro_cursor(leaf_index_db)?;
+    let entries = match cursor.iter_dup_of(&leaf_index_key(leaf)) {
+        Ok(entries) => entries,
+        Err(lmdb::Error::NotFound) => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut indices = Vec::new();
+    for (_key, value) in entries {
+        let index = value
+            .try_into()
+            .map(usize::from_be_bytes)
+            .map_err(|_| MerkleError::Corrupted("Invalid leaf index entry".to_string()))?;
+        // Entries are sorted, so the rest are past the tree as well
+        if index >= num_leaves {
+            break;
+        }
+        indices.push(index);
+    }
+    Ok(indices)
+}
+
+// Records how many levels the cache has, since a level can have no stored
+// nodes. A cache without it was written in the per-level layout.
+const CACHE_LEVELS_KEY: &[u8] = b"levels";
+
+// Level first so the nodes of one level are stored together, in order
+fn cache_node_key(level: usize, index: usize) -> [u8; 16] {
+    let mut key = [0u8; 16];
+    key[..8].copy_from_slice(&(level as u64).to_be_bytes());
+    key[8..].copy_from_slice(&(index as u64).to_be_bytes());
+    key
+}
+
+fn parse_cache_node_key(key: &[u8]) -> Option<(usize, usize)> {
+    let (level, index) = key.split_first_chunk::<8>()?;
+    let index: [u8; 8] = index.try_into().ok()?;
+    Some((
+        u64::from_be_bytes(*level) as usize,
+        u64::from_be_bytes(index) as usize,
+    ))
+}
+
+fn read_num_cache_levels(
+    txn: &impl Transaction,
+    cache_db: Database,
+) -> Result<Option<usize>, MerkleError> {
+    match txn.get(cache_db, &CACHE_LEVELS_KEY) {
+        Ok(data) => data
+            .try_into()
+            .map(|data| Some(usize::from_be_bytes(data)))
+            .map_err(|_| MerkleError::Corrupted("Invalid cache level count".to_string())),
+        Err(lmdb::Error::NotFound) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn decode_cache_node(data: &[u8]) -> Result<Hash, MerkleError> {
+    data.try_into()
+        .map_err(|_| MerkleError::Corrupted("Cached node is not a hash".to_string()))
+}
+
+// Height first so the nodes of one level are stored together
+fn sparse_node_key(height: usize, prefix: &[u8; 32]) -> [u8; 34] {
+    let mut key = [0u8; 34];
+    key[..2].copy_from_slice(&(height as u16).to_be_bytes());
+    key[2..].copy_from_slice(prefix);
+    key
+}
+
+fn read_sparse_node(
+    txn: &impl Transaction,
+    sparse_nodes_db: Database,
+    height: usize,
+    prefix: &SparseKey,
+) -> Result<Option<SparseNode>, MerkleError> {
+    match txn.get(sparse_nodes_db, &sparse_node_key(height, prefix)) {
+        Ok(data) => Ok(Some(bincode::deserialize(data)?)),
+        Err(lmdb::Error::NotFound) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn delete_if_present(
+    txn: &mut lmdb::RwTransaction,
+    db: Database,
+    key: &[u8],
+) -> Result<(), lmdb::Error> {
+    match txn.del(db, &key, None) {
+        Ok(()) | Err(lmdb::Error::NotFound) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,115 @@
+use crate::config::TreeConfig;
+use crate::consistency::ConsistencyProof;
+use crate::error::MerkleError;
+use crate::multiproof::MerkleMultiProof;
+use crate::range_proof::RangeProof;
+use crate::snapshot::TreeSnapshot;
+use crate::verify::{self, InclusionProof, MerkleProof};
+
+/// Append-only Merkle tree with positional leaves.
+///
+/// `IncrementalMerkleTree` and `LmdbMerkleTree` both implement it, and the
+/// HTTP routes are written against it, so another backend only has to
+/// implement this trait to be served.
+pub trait MerkleTree {
+    fn config(&self) -> &TreeConfig;
+
+    fn append(&mut self, leaf: Vec<u8>) -> Result<(), MerkleError> {
+        self.append_batch(vec![leaf])
+    }
+
+    /// Appends all of `leaves`, or none of them if the tree cannot take them.
+    fn append_batch(&mut self, leaves: Vec<Vec<u8>>) -> Result<(), MerkleError>;
+
+    /// Appends the leaf for each preimage, as given by
+    /// `TreeConfig::preimage_leaf`, and returns them. With `keep` the preimages are stored so that
+    /// `preimage` can return them; trees without a place to keep them refuse.
+    fn append_preimages(
+        &mut self,
+        preimages: Vec<Vec<u8>>,
+        keep: bool,
+    ) -> Result<Vec<Vec<u8>>, MerkleError> {
+        if keep {
+            return Err(MerkleError::InvalidArgument(
+                "This tree does not store preimages".to_string(),
+            ));
+        }
+
+        let leaves: Vec<Vec<u8>> = preimages
+            .iter()
+            .map(|preimage| self.config().preimage_leaf(preimage))
+            .collect();
+        self.append_batch(leaves.clone())?;
+        Ok(leaves)
+    }
+
+    /// Preimage kept by `append_preimages` for the leaf at `index`.
+    fn preimage(&self, _index: usize) -> Result<Option<Vec<u8>>, MerkleError> {
+        Ok(None)
+    }
+
+    /// Replaces the leaf at `index`.
+    fn update(&mut self, index: usize, leaf: Vec<u8>) -> Result<(), MerkleError>;
+
+    /// Indices of the leaves equal to `leaf`, in ascending order.
+    fn leaf_indices(&self, leaf: &[u8]) -> Result<Vec<usize>, MerkleError>;
+
+    /// Number of leaves. Backends that read it from storage can fail.
+    fn len(&self) -> Result<usize, MerkleError>;
+
+    fn is_empty(&self) -> Result<bool, MerkleError> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Current root, or `None` for an empty tree without a fixed depth.
+    fn root(&self) -> Result<Option<Vec<u8>>, MerkleError>;
+
+    /// Root of the tree as it was when it last had `size` leaves. An update
+    /// made at a larger size does not change it; only the current root
+    /// does.
+    fn root_at(&self, size: usize) -> Result<Vec<u8>, MerkleError>;
+
+    fn proof(&self, index: usize) -> Result<MerkleProof, MerkleError> {
+        self.proof_at(index, self.len()?)
+    }
+
+    /// Proof for leaf `index` against `root_at(tree_size)`. Proofs are built
+    /// from the current leaves, so this fails with `InvalidArgument` once a
+    /// leaf below `tree_size` was updated at a larger size.
+    fn proof_at(&self, index: usize, tree_size: usize) -> Result<MerkleProof, MerkleError>;
+
+    /// Self-describing proof for leaf `index` against the root at `tree_size`.
+    fn inclusion_proof(
+        &self,
+        index: usize,
+        tree_size: usize,
+    ) -> Result<InclusionProof, MerkleError>;
+
+    fn multiproof(&self, indices: &[usize]) -> Result<MerkleMultiProof, MerkleError>;
+
+    /// Proof that the leaves at `start..end` are exactly those positions of
+    /// the tree at `tree_size`.
+    fn range_proof(
+        &self,
+        start: usize,
+        end: usize,
+        tree_size: usize,
+    ) -> Result<RangeProof, MerkleError>;
+
+    fn consistency_proof(
+        &self,
+        old_size: usize,
+        new_size: usize,
+    ) -> Result<ConsistencyProof, MerkleError>;
+
+    /// Immutable copy of the tree as it is now, for readers that should not
+    /// wait for writes.
+    fn snapshot(&self) -> Result<TreeSnapshot, MerkleError>;
+
+    /// Checks a proof from `proof` against `root`.
+    fn verify(&self, leaf: &[u8], proof: &MerkleProof, root: &[u8], leaf_index: usize) -> bool {
+        self.len().is_ok_and(|size| {
+            verify::verify_proof(self.config(), leaf, proof, root, leaf_index, size)
+        })
+    }
+}
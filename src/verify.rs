@@ -0,0 +1,484 @@
+//! Proof verification from the hashing parameters alone.
+//!
+//! Nothing here depends on `storage`, `lmdb` or `tokio`: a light client
+//! holding a `TreeConfig` can check proofs without building a tree or
+//! opening an LMDB environment.
+
+use crate::config::{HashScheme, OddNodeStrategy, TreeConfig};
+use crate::consistency::ConsistencyProof;
+use crate::error::MerkleError;
+use crate::hasher::{MerkleHasher, hasher_from_id};
+use crate::mmr::{MmrProof, bag_peaks, find_mountain};
+use crate::multiproof::MerkleMultiProof;
+use crate::range_proof::RangeProof;
+use crate::sparse_tree::{
+    SPARSE_DEPTH, SparseKey, SparseMerkleProof, get_bit, prefix, sparse_empty_hash,
+    sparse_hash_children, sparse_leaf_hash,
+};
+use serde::{Deserialize, Serialize};
+
+/// Current version of the `InclusionProof` format.
+pub const INCLUSION_PROOF_VERSION: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleProof {
+    pub siblings: Vec<String>,
+}
+
+/// Inclusion proof that carries everything needed to verify it: the leaf,
+/// its position, the tree size and root it was issued against, and the
+/// hashing parameters of the tree.
+///
+/// `verify` only shows that the leaf is in the tree with `root`; callers
+/// must still check `root` against a root they trust.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InclusionProof {
+    pub version: u8,
+    pub hash_algorithm: String,
+    pub hash_scheme: HashScheme,
+    pub odd_node: OddNodeStrategy,
+    pub depth: Option<usize>,
+    /// Absent from proofs of binary trees.
+    #[serde(default = "binary_arity", skip_serializing_if = "is_binary")]
+    pub arity: usize,
+    pub tree_size: usize,
+    pub leaf_index: usize,
+    pub leaf: String,
+    pub root: String,
+    pub siblings: Vec<String>,
+}
+
+impl InclusionProof {
+    pub fn new(
+        config: &TreeConfig,
+        leaf_index: usize,
+        leaf: &[u8],
+        tree_size: usize,
+        root: &[u8],
+        proof: MerkleProof,
+    ) -> Self {
+        Self {
+            version: INCLUSION_PROOF_VERSION,
+            hash_algorithm: config.hasher.id().to_string(),
+            hash_scheme: config.scheme,
+            odd_node: config.odd_node_strategy(),
+            depth: config.depth,
+            arity: config.arity,
+            tree_size,
+            leaf_index,
+            leaf: hex::encode(leaf),
+            root: hex::encode(root),
+            siblings: proof.siblings,
+        }
+    }
+
+    /// Hashing parameters of the tree the proof was issued by.
+    pub fn config(&self) -> Result<TreeConfig, MerkleError> {
+        let hasher = hasher_from_id(&self.hash_algorithm).ok_or_else(|| {
+            MerkleError::InvalidArgument(format!("Unknown hash algorithm: {}", self.hash_algorithm))
+        })?;
+        let config = TreeConfig {
+            hasher,
+            scheme: self.hash_scheme,
+            odd_node: self.odd_node,
+            depth: self.depth,
+            arity: self.arity,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn verify(&self) -> bool {
+        if self.version != INCLUSION_PROOF_VERSION {
+            return false;
+        }
+
+        let (Ok(config), Ok(leaf), Ok(root)) = (
+            self.config(),
+            hex::decode(&self.leaf),
+            hex::decode(&self.root),
+        ) else {
+            return false;
+        };
+        let proof = MerkleProof {
+            siblings: self.siblings.clone(),
+        };
+
+        verify_proof(
+            &config,
+            &leaf,
+            &proof,
+            &root,
+            self.leaf_index,
+            self.tree_size,
+        )
+    }
+}
+
+fn binary_arity() -> usize {
+    2
+}
+
+fn is_binary(arity: &usize) -> bool {
+    *arity == 2
+}
+
+/// Checks that `leaf` sits at `leaf_index` of the tree of `tree_size` leaves
+/// whose root is `root`. Each level of the proof holds the `arity - 1`
+/// other children of the group, in order, and levels where the node is
+/// promoted have none; any other number of siblings is rejected.
+pub fn verify_proof(
+    config: &TreeConfig,
+    leaf: &[u8],
+    proof: &MerkleProof,
+    root: &[u8],
+    leaf_index: usize,
+    tree_size: usize,
+) -> bool {
+    let arity = config.arity;
+    let Ok(siblings) = proof
+        .siblings
+        .iter()
+        .map(hex::decode)
+        .collect::<Result<Vec<_>, _>>()
+    else {
+        return false;
+    };
+    if arity < 2 || leaf_index >= tree_size || tree_size > config.max_leaves() {
+        return false;
+    }
+
+    let mut current_hash = config.hash_leaf(leaf);
+    let mut current_index = leaf_index;
+    let mut level_size = tree_size;
+    let mut current_level = 0;
+    let mut remaining = siblings.as_slice();
+    let promote = config.odd_node_strategy() == OddNodeStrategy::Promote;
+
+    while !config.is_root_level(current_level, level_size) {
+        // Promoted nodes have no sibling in the proof
+        let promoted =
+            promote && current_index == level_size - 1 && current_index.is_multiple_of(2);
+        if !promoted {
+            let Some((level_siblings, rest)) = remaining.split_at_checked(arity - 1) else {
+                return false;
+            };
+
+            // The current node goes between the siblings left and right of it
+            let position = current_index % arity;
+            let mut children: Vec<&[u8]> = level_siblings.iter().map(Vec::as_slice).collect();
+            children.insert(position, &current_hash);
+            current_hash = config.hash_nodes(&children);
+            remaining = rest;
+        }
+
+        current_index /= arity;
+        level_size = level_size.div_ceil(arity);
+        current_level += 1;
+    }
+
+    remaining.is_empty() && current_hash == root
+}
+
+/// Checks a proof of an OpenZeppelin-scheme tree the way OpenZeppelin's
+/// `MerkleProof.verify` does. Pairs are hashed in sorted order, so neither
+/// the leaf index nor the tree size is needed.
+pub fn verify_sorted_proof(
+    config: &TreeConfig,
+    leaf: &[u8],
+    proof: &MerkleProof,
+    root: &[u8],
+) -> bool {
+    if config.scheme != HashScheme::OpenZeppelin {
+        return false;
+    }
+
+    let mut current_hash = config.hash_leaf(leaf);
+    for sibling_hex in proof.siblings.iter() {
+        match hex::decode(sibling_hex) {
+            Ok(sibling) => current_hash = config.hash_node(&current_hash, &sibling),
+            Err(_) => return false,
+        }
+    }
+
+    current_hash == root
+}
+
+/// Recomputes the root of a tree of `tree_size` leaves from `leaves`, given
+/// in the order of `proof.indices`, and compares it with `root`.
+pub fn verify_multiproof(
+    config: &TreeConfig,
+    leaves: &[Vec<u8>],
+    proof: &MerkleMultiProof,
+    root: &[u8],
+    tree_size: usize,
+) -> bool {
+    if leaves.len() != proof.indices.len() || leaves.is_empty() {
+        return false;
+    }
+
+    // (index, hash) of the known nodes on the current level, ascending
+    let mut nodes: Vec<(usize, Vec<u8>)> = proof
+        .indices
+        .iter()
+        .zip(leaves)
+        .map(|(&index, leaf)| (index, config.hash_leaf(leaf)))
+        .collect();
+    if nodes.windows(2).any(|pair| pair[0].0 >= pair[1].0)
+        || nodes.last().is_some_and(|(index, _)| *index >= tree_size)
+    {
+        return false;
+    }
+
+    let arity = config.arity;
+    let mut siblings = proof.siblings.iter();
+    let mut level_size = tree_size;
+    let mut current_level = 0;
+    let mut zero = config.zero_hashes(0).swap_remove(0);
+
+    while !config.is_root_level(current_level, level_size) {
+        let mut parents = Vec::with_capacity(nodes.len());
+        let mut remaining = nodes.into_iter().peekable();
+
+        while let Some(&(first, _)) = remaining.peek() {
+            let parent = first / arity;
+            let group_end = level_size.min((parent + 1) * arity);
+
+            // Children of the group come from the known nodes or, in order,
+            // from the proof; the odd node strategy fills a short group
+            let mut children = Vec::with_capacity(arity);
+            for index in parent * arity..group_end {
+                if remaining.peek().is_some_and(|(next, _)| *next == index) {
+                    children.push(remaining.next().unwrap().1);
+                } else {
+                    match siblings.next().map(hex::decode) {
+                        Some(Ok(sibling)) => children.push(sibling),
+                        _ => return false,
+                    }
+                }
+            }
+            parents.push((parent, config.hash_children(&children, &zero)));
+        }
+
+        nodes = parents;
+        level_size = level_size.div_ceil(arity);
+        current_level += 1;
+        zero = config.hash_nodes(&vec![zero.as_slice(); arity]);
+    }
+
+    siblings.next().is_none() && nodes.len() == 1 && nodes[0].1 == root
+}
+
+/// Checks that `leaves` are exactly the leaves at `[proof.start, proof.end)`
+/// of the tree of `proof.tree_size` leaves with `root`.
+pub fn verify_range_proof(
+    config: &TreeConfig,
+    leaves: &[Vec<u8>],
+    proof: &RangeProof,
+    root: &[u8],
+) -> bool {
+    let (start, end, tree_size) = (proof.start, proof.end, proof.tree_size);
+    let arity = config.arity;
+    if arity < 2 || start >= end || end > tree_size || tree_size > config.max_leaves() {
+        return false;
+    }
+    if leaves.len() != end - start {
+        return false;
+    }
+
+    let mut nodes: Vec<Vec<u8>> = leaves.iter().map(|leaf| config.hash_leaf(leaf)).collect();
+    let mut siblings = proof.siblings.iter().map(hex::decode);
+    let (mut low, mut high) = (start, end);
+    let mut level_size = tree_size;
+    let mut current_level = 0;
+    let mut zero = config.zero_hashes(0).swap_remove(0);
+
+    while !config.is_root_level(current_level, level_size) {
+        let group_start = low - low % arity;
+        let group_end = level_size.min(high.div_ceil(arity) * arity);
+
+        // The known nodes framed by the boundary siblings cover whole groups
+        let mut row = Vec::with_capacity(group_end - group_start);
+        for _ in group_start..low {
+            match siblings.next() {
+                Some(Ok(sibling)) => row.push(sibling),
+                _ => return false,
+            }
+        }
+        row.append(&mut nodes);
+        for _ in high..group_end {
+            match siblings.next() {
+                Some(Ok(sibling)) => row.push(sibling),
+                _ => return false,
+            }
+        }
+
+        nodes = row
+            .chunks(arity)
+            .map(|children| config.hash_children(children, &zero))
+            .collect();
+        low /= arity;
+        high = high.div_ceil(arity);
+        level_size = level_size.div_ceil(arity);
+        current_level += 1;
+        zero = config.hash_nodes(&vec![zero.as_slice(); arity]);
+    }
+
+    siblings.next().is_none() && nodes.len() == 1 && nodes[0] == root
+}
+
+/// Checks that `old_root` and `new_root` are the roots of the two sizes in
+/// `proof`, following RFC 9162, section 2.1.4.2.
+pub fn verify_consistency(
+    config: &TreeConfig,
+    old_root: &[u8],
+    new_root: &[u8],
+    proof: &ConsistencyProof,
+) -> bool {
+    if !config.supports_consistency_proofs() {
+        return false;
+    }
+
+    let (old_size, new_size) = (proof.old_size, proof.new_size);
+    let Ok(mut path) = proof
+        .hashes
+        .iter()
+        .map(hex::decode)
+        .collect::<Result<Vec<_>, _>>()
+    else {
+        return false;
+    };
+
+    if old_size == 0 || old_size > new_size {
+        return false;
+    }
+    if old_size == new_size {
+        return path.is_empty() && old_root == new_root;
+    }
+
+    // A complete old tree is itself a subtree of the new one
+    if old_size.is_power_of_two() {
+        path.insert(0, old_root.to_vec());
+    }
+    let Some((first, rest)) = path.split_first() else {
+        return false;
+    };
+
+    let mut old_index = old_size - 1;
+    let mut new_index = new_size - 1;
+    while old_index & 1 == 1 {
+        old_index >>= 1;
+        new_index >>= 1;
+    }
+
+    let mut old_hash = first.clone();
+    let mut new_hash = first.clone();
+
+    for node in rest {
+        if new_index == 0 {
+            return false;
+        }
+
+        if old_index & 1 == 1 || old_index == new_index {
+            old_hash = config.hash_node(node, &old_hash);
+            new_hash = config.hash_node(node, &new_hash);
+            while old_index & 1 == 0 && old_index != 0 {
+                old_index >>= 1;
+                new_index >>= 1;
+            }
+        } else {
+            new_hash = config.hash_node(&new_hash, node);
+        }
+
+        old_index >>= 1;
+        new_index >>= 1;
+    }
+
+    old_hash == old_root && new_hash == new_root && new_index == 0
+}
+
+/// Checks that `leaf` is leaf `proof.leaf_index` of the MMR whose bagged
+/// root is `root`.
+pub fn verify_mmr_proof(config: &TreeConfig, leaf: &[u8], proof: &MmrProof, root: &[u8]) -> bool {
+    let Some(mountain) = find_mountain(proof.leaf_index, proof.num_leaves) else {
+        return false;
+    };
+    let decode = |hashes: &[String]| {
+        hashes
+            .iter()
+            .map(hex::decode)
+            .collect::<Result<Vec<_>, _>>()
+    };
+    let (Ok(siblings), Ok(mut peaks)) = (decode(&proof.siblings), decode(&proof.peaks)) else {
+        return false;
+    };
+    let mountains = proof.num_leaves.count_ones() as usize;
+    if siblings.len() != mountain.height || peaks.len() != mountains - 1 {
+        return false;
+    }
+
+    // Climb to the peak of the leaf's mountain, then bag it with the others
+    let mut current_hash = config.hash_leaf(leaf);
+    for (height, sibling) in siblings.iter().enumerate() {
+        current_hash = if mountain.local_index >> height & 1 == 0 {
+            config.hash_node(&current_hash, sibling)
+        } else {
+            config.hash_node(sibling, &current_hash)
+        };
+    }
+    peaks.insert(mountain.position, current_hash);
+
+    bag_peaks(config, &peaks).is_some_and(|bagged| bagged == root)
+}
+
+/// Checks a sparse tree membership or non-membership proof against `root`,
+/// for a tree hashed with `hasher`.
+pub fn verify_sparse_proof(
+    hasher: &dyn MerkleHasher,
+    proof: &SparseMerkleProof,
+    root: &[u8],
+) -> bool {
+    let computed = || -> Option<Vec<u8>> {
+        let key: SparseKey = hex::decode(&proof.key).ok()?.try_into().ok()?;
+        let bitmap: [u8; 32] = hex::decode(&proof.bitmap).ok()?.try_into().ok()?;
+        let end_height = SPARSE_DEPTH.checked_sub(proof.depth)?;
+        // No sibling can sit below the end of the path
+        if (0..end_height).any(|height| get_bit(&bitmap, height)) {
+            return None;
+        }
+
+        let empty = sparse_empty_hash(hasher);
+        let mut current_hash = match (&proof.value, &proof.other_leaf) {
+            (Some(value), None) => {
+                sparse_leaf_hash(hasher, &key, &hasher.hash(&hex::decode(value).ok()?))
+            }
+            // The other key must be alone in the subtree where `key` would be
+            (None, Some(leaf)) => {
+                let other_key: SparseKey = hex::decode(&leaf.key).ok()?.try_into().ok()?;
+                if other_key == key || prefix(&other_key, end_height) != prefix(&key, end_height) {
+                    return None;
+                }
+                sparse_leaf_hash(hasher, &other_key, &hex::decode(&leaf.value_hash).ok()?)
+            }
+            (None, None) => empty.clone(),
+            (Some(_), Some(_)) => return None,
+        };
+        let mut siblings = proof.siblings.iter();
+
+        for height in end_height..SPARSE_DEPTH {
+            let sibling = if get_bit(&bitmap, height) {
+                hex::decode(siblings.next()?).ok()?
+            } else {
+                empty.clone()
+            };
+            if sibling.len() != empty.len() {
+                return None;
+            }
+            current_hash = sparse_hash_children(hasher, &key, height, &current_hash, &sibling);
+        }
+
+        siblings.next().is_none().then_some(current_hash)
+    };
+
+    computed().is_some_and(|hash| hash == root)
+}
@@ -0,0 +1,74 @@
+//! Fixtures shared by the integration tests. Each test crate uses only some
+//! of them.
+#![allow(dead_code)]
+
+use merkle_tree_api::config::{HashScheme, OddNodeStrategy, TreeConfig};
+use merkle_tree_api::hasher::Sha256Hasher;
+use merkle_tree_api::merkle_tree::IncrementalMerkleTree;
+
+pub fn hex(s: &str) -> Vec<u8> {
+    s.as_bytes().to_vec()
+}
+
+/// `count` distinct leaves: `leaf0`, `leaf1`, ...
+pub fn leaves(count: usize) -> Vec<Vec<u8>> {
+    (0..count)
+        .map(|i| format!("leaf{}", i).into_bytes())
+        .collect()
+}
+
+pub fn tree(config: &TreeConfig, leaves: &[Vec<u8>]) -> IncrementalMerkleTree {
+    let mut tree = IncrementalMerkleTree::with_config(config.clone()).unwrap();
+    tree.add_leaves(leaves.to_vec()).unwrap();
+    tree
+}
+
+pub fn tree_with_strategy(odd_node: OddNodeStrategy, leaves: &[Vec<u8>]) -> IncrementalMerkleTree {
+    let config = TreeConfig {
+        odd_node,
+        ..TreeConfig::default()
+    };
+    tree(&config, leaves)
+}
+
+/// Empty tree with `arity` children per node.
+pub fn kary_tree(
+    arity: usize,
+    odd_node: OddNodeStrategy,
+    depth: Option<usize>,
+) -> IncrementalMerkleTree {
+    IncrementalMerkleTree::with_config(TreeConfig {
+        odd_node,
+        depth,
+        arity,
+        ..TreeConfig::default()
+    })
+    .unwrap()
+}
+
+/// Tree hashed as in RFC 6962, as Certificate Transparency logs are.
+pub fn rfc6962_tree(leaves: &[Vec<u8>]) -> IncrementalMerkleTree {
+    let config = TreeConfig {
+        scheme: HashScheme::Rfc6962,
+        odd_node: OddNodeStrategy::Promote,
+        ..TreeConfig::new(Sha256Hasher)
+    };
+    tree(&config, leaves)
+}
+
+/// Leaves of the Certificate Transparency reference test vectors.
+pub fn rfc6962_test_leaves() -> Vec<Vec<u8>> {
+    [
+        "",
+        "00",
+        "10",
+        "2021",
+        "3031",
+        "40414243",
+        "5051525354555657",
+        "606162636465666768696a6b6c6d6e6f",
+    ]
+    .iter()
+    .map(|leaf| ::hex::decode(leaf).unwrap())
+    .collect()
+}
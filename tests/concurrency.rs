@@ -33,7 +33,7 @@ async fn test_concurrent_add_leaves_and_get_root() {
     // Task 3: Repeatedly try to get root (may fail during updates)
     let task3 = tokio::spawn(async move {
         for _ in 0..10 {
-            let mut t = tree3.write().await;
+            let t = tree3.write().await;
             let _root = t.root();
             tokio::time::sleep(tokio::time::Duration::from_millis(1)).await;
         }
@@ -43,7 +43,7 @@ async fn test_concurrent_add_leaves_and_get_root() {
     let _ = tokio::try_join!(task1, task2, task3);
 
     // Verify final state
-    let mut final_tree = tree.write().await;
+    let final_tree = tree.write().await;
     assert_eq!(final_tree.num_leaves(), 200);
     assert!(final_tree.root().is_some());
 }
@@ -67,7 +67,7 @@ async fn test_concurrent_proof_generation() {
         let handle = tokio::spawn(async move {
             let t = tree_clone.read().await;
             let proof = t.get_proof(i % 50);
-            proof.is_some()
+            proof.is_ok()
         });
         handles.push(handle);
     }
@@ -78,3 +78,53 @@ async fn test_concurrent_proof_generation() {
         assert!(result);
     }
 }
+
+#[test]
+fn test_concurrent_lmdb_writes_keep_every_batch() {
+    use merkle_tree_api::lmdb_tree::LmdbMerkleTree;
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().unwrap();
+    let db_path = temp_dir.path().join("test.db");
+    let tree = Arc::new(LmdbMerkleTree::new(db_path.to_str().unwrap()).unwrap());
+    tree.add_leaf(b"first".to_vec()).unwrap();
+
+    // Writers take `&self`, so nothing outside the tree serializes them;
+    // each round starts them together
+    let round = Arc::new(std::sync::Barrier::new(8));
+    let handles: Vec<_> = (0..8)
+        .map(|writer| {
+            let tree = Arc::clone(&tree);
+            let round = Arc::clone(&round);
+            std::thread::spawn(move || {
+                for batch in 0..10 {
+                    round.wait();
+                    let leaves = (0..3)
+                        .map(|i| format!("leaf{}-{}-{}", writer, batch, i).into_bytes())
+                        .collect();
+                    tree.add_leaves(leaves).unwrap();
+                    tree.update_leaf(0, format!("first{}", writer).into_bytes())
+                        .unwrap();
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    assert_eq!(tree.num_leaves().unwrap(), 1 + 8 * 10 * 3);
+    for writer in 0..8 {
+        for batch in 0..10 {
+            let leaf = format!("leaf{}-{}-0", writer, batch).into_bytes();
+            assert_eq!(tree.get_leaf_indices(&leaf).unwrap().len(), 1);
+        }
+    }
+
+    // The tree in memory agrees with the one stored
+    let root = tree.root().unwrap();
+    assert_eq!(tree.snapshot().unwrap().root(), root.as_deref());
+    drop(tree);
+    let reopened = LmdbMerkleTree::new(db_path.to_str().unwrap()).unwrap();
+    assert_eq!(reopened.root().unwrap(), root);
+}
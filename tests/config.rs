@@ -0,0 +1,503 @@
+mod common;
+
+use common::{hex, kary_tree, leaves, rfc6962_test_leaves, tree_with_strategy};
+use merkle_tree_api::error::MerkleError;
+use merkle_tree_api::merkle_tree::IncrementalMerkleTree;
+
+#[test]
+fn test_hasher_output_size_validated() {
+    use merkle_tree_api::config::{HashScheme, TreeConfig};
+    use merkle_tree_api::hasher::{MerkleHasher, Sha256Hasher};
+    use merkle_tree_api::snapshot::TreeSnapshot;
+
+    #[derive(Debug)]
+    struct ShortHasher;
+
+    impl MerkleHasher for ShortHasher {
+        fn id(&self) -> &str {
+            "short"
+        }
+
+        fn hash_parts(&self, parts: &[&[u8]]) -> Vec<u8> {
+            Sha256Hasher.hash_parts(parts)[..20].to_vec()
+        }
+    }
+
+    let config = TreeConfig::new(ShortHasher);
+    assert!(matches!(
+        config.validate(),
+        Err(MerkleError::InvalidArgument(_))
+    ));
+    assert!(IncrementalMerkleTree::with_config(config.clone()).is_err());
+    assert!(
+        TreeSnapshot::new(
+            config.clone(),
+            vec![hex("a")].into(),
+            Vec::new(),
+            Default::default()
+        )
+        .is_err()
+    );
+    // Hashing leaves checks every hash rather than panicking
+    let hashed = TreeConfig {
+        scheme: HashScheme::Rfc6962,
+        ..config
+    };
+    assert!(hashed.hash_leaves(&[hex("a")]).is_err());
+}
+
+#[test]
+fn test_custom_hasher() {
+    use merkle_tree_api::config::TreeConfig;
+    use merkle_tree_api::hasher::{MerkleHasher, Sha256Hasher};
+
+    let mut tree = IncrementalMerkleTree::with_config(TreeConfig::new(Sha256Hasher)).unwrap();
+    let leaves = vec![hex("a"), hex("b"), hex("c")];
+    tree.add_leaves(leaves.clone()).unwrap();
+
+    let ab = Sha256Hasher.hash_pair(&leaves[0], &leaves[1]);
+    let cc = Sha256Hasher.hash_pair(&leaves[2], &leaves[2]);
+    let root = tree.root().unwrap();
+    assert_eq!(root, Sha256Hasher.hash_pair(&ab, &cc));
+    let mut keccak = IncrementalMerkleTree::new();
+    keccak.add_leaves(leaves.clone()).unwrap();
+    assert_ne!(keccak.root().unwrap(), root);
+
+    let proof = tree.get_proof(2).unwrap();
+    assert!(tree.verify_proof(&leaves[2], &proof, &root, 2));
+}
+
+#[test]
+fn test_rfc6962_scheme_matches_reference_roots() {
+    use merkle_tree_api::config::{HashScheme, TreeConfig};
+    use merkle_tree_api::hasher::Sha256Hasher;
+
+    let config = TreeConfig {
+        scheme: HashScheme::Rfc6962,
+        ..TreeConfig::new(Sha256Hasher)
+    };
+    let expected = [
+        (
+            1,
+            "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d",
+        ),
+        (
+            2,
+            "fac54203e7cc696cf0dfcb42c92a1d9dbaf70ad9e621f4bd8d98662f00e3c125",
+        ),
+        (
+            4,
+            "d37ee418976dd95753c1c73862b9398fa2a2cf9b4ff0fdfe8b30cd95209614b7",
+        ),
+        (
+            8,
+            "5dc9da79a70659a9ad559cb701ded9a2ab9d823aad2f4960cfe370eff4604328",
+        ),
+    ];
+
+    let leaves = rfc6962_test_leaves();
+    for (size, root) in expected {
+        let mut tree = IncrementalMerkleTree::with_config(config.clone()).unwrap();
+        tree.add_leaves(leaves[..size].to_vec()).unwrap();
+        let tree_root = tree.root().unwrap();
+        assert_eq!(::hex::encode(&tree_root), root);
+
+        for (i, leaf) in leaves[..size].iter().enumerate() {
+            let proof = tree.get_proof(i).unwrap();
+            assert!(tree.verify_proof(leaf, &proof, &tree_root, i));
+        }
+    }
+}
+
+#[test]
+fn test_rfc6962_scheme_rejects_internal_node_as_leaf() {
+    use merkle_tree_api::config::{HashScheme, TreeConfig};
+
+    let leaves = vec![hex("a"), hex("b"), hex("c"), hex("d")];
+
+    // Without domain separation, the level-1 nodes form a two-leaf tree with the same root
+    let mut raw = IncrementalMerkleTree::new();
+    raw.add_leaves(leaves.clone()).unwrap();
+    let raw_nodes = raw.cached_levels()[1]
+        .iter()
+        .map(|hash| hash.to_vec())
+        .collect();
+    let mut forged = IncrementalMerkleTree::new();
+    forged.add_leaves(raw_nodes).unwrap();
+    assert_eq!(raw.root(), forged.root());
+
+    let config = TreeConfig {
+        scheme: HashScheme::Rfc6962,
+        ..TreeConfig::default()
+    };
+    let mut tree = IncrementalMerkleTree::with_config(config.clone()).unwrap();
+    tree.add_leaves(leaves).unwrap();
+    let root = tree.root().unwrap();
+    let mut forged = IncrementalMerkleTree::with_config(config).unwrap();
+    forged
+        .add_leaves(
+            tree.cached_levels()[1]
+                .iter()
+                .map(|hash| hash.to_vec())
+                .collect(),
+        )
+        .unwrap();
+    assert_ne!(forged.root().unwrap(), root);
+
+    // An internal node with its sibling path does not verify as a leaf
+    let proof = tree.get_proof(0).unwrap();
+    let short_proof = merkle_tree_api::merkle_tree::MerkleProof {
+        siblings: proof.siblings[1..].to_vec(),
+    };
+    assert!(!tree.verify_proof(&tree.cached_levels()[1][0], &short_proof, &root, 0));
+}
+
+#[test]
+fn test_odd_node_strategies_distinguish_duplicated_leaf() {
+    use merkle_tree_api::config::OddNodeStrategy;
+
+    let three = vec![hex("a"), hex("b"), hex("c")];
+    let four = vec![hex("a"), hex("b"), hex("c"), hex("c")];
+
+    let dup3 = tree_with_strategy(OddNodeStrategy::Duplicate, &three);
+    let dup4 = tree_with_strategy(OddNodeStrategy::Duplicate, &four);
+    assert_eq!(dup3.root(), dup4.root());
+
+    for strategy in [OddNodeStrategy::Promote, OddNodeStrategy::ZeroPad] {
+        let tree3 = tree_with_strategy(strategy, &three);
+        let tree4 = tree_with_strategy(strategy, &four);
+        assert_ne!(tree3.root(), tree4.root());
+    }
+}
+
+#[test]
+fn test_zero_pad_and_promote_roots() {
+    use merkle_tree_api::config::OddNodeStrategy;
+    use merkle_tree_api::hasher::{Keccak256Hasher, MerkleHasher};
+
+    let leaves = vec![hex("a"), hex("b"), hex("c")];
+    let ab = Keccak256Hasher.hash_pair(&leaves[0], &leaves[1]);
+
+    let promote = tree_with_strategy(OddNodeStrategy::Promote, &leaves);
+    assert_eq!(
+        promote.root().unwrap(),
+        Keccak256Hasher.hash_pair(&ab, &leaves[2])
+    );
+
+    let zero_pad = tree_with_strategy(OddNodeStrategy::ZeroPad, &leaves);
+    let c0 = Keccak256Hasher.hash_pair(&leaves[2], &[0u8; 32]);
+    assert_eq!(
+        zero_pad.root().unwrap(),
+        Keccak256Hasher.hash_pair(&ab, &c0)
+    );
+}
+
+#[test]
+fn test_proofs_verify_for_every_odd_node_strategy() {
+    use merkle_tree_api::config::OddNodeStrategy;
+
+    let strategies = [
+        OddNodeStrategy::Duplicate,
+        OddNodeStrategy::Promote,
+        OddNodeStrategy::ZeroPad,
+    ];
+    for strategy in strategies {
+        for size in 1..=20 {
+            let leaves = leaves(size);
+            let tree = tree_with_strategy(strategy, &leaves);
+            let root = tree.root().unwrap();
+
+            for (i, leaf) in leaves.iter().enumerate() {
+                let proof = tree.get_proof(i).unwrap();
+                assert!(tree.verify_proof(leaf, &proof, &root, i));
+                assert!(!tree.verify_proof(&hex("wrong"), &proof, &root, i));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_rfc6962_promote_matches_all_reference_roots() {
+    use merkle_tree_api::config::{HashScheme, OddNodeStrategy, TreeConfig};
+    use merkle_tree_api::hasher::Sha256Hasher;
+
+    let config = TreeConfig {
+        scheme: HashScheme::Rfc6962,
+        odd_node: OddNodeStrategy::Promote,
+        ..TreeConfig::new(Sha256Hasher)
+    };
+    let expected = [
+        "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d",
+        "fac54203e7cc696cf0dfcb42c92a1d9dbaf70ad9e621f4bd8d98662f00e3c125",
+        "aeb6bcfe274b70a14fb067a5e5578264db0fa9b51af5e0ba159158f329e06e77",
+        "d37ee418976dd95753c1c73862b9398fa2a2cf9b4ff0fdfe8b30cd95209614b7",
+        "4e3bbb1f7b478dcfe71fb631631519a3bca12c9aefca1612bfce4c13a86264d4",
+        "76e67dadbcdf1e10e1b74ddc608abd2f98dfb16fbce75277b5232a127f2087ef",
+        "ddb89be403809e325750d3d263cd78929c2942b7942a34b77e122c9594a74c8c",
+        "5dc9da79a70659a9ad559cb701ded9a2ab9d823aad2f4960cfe370eff4604328",
+    ];
+
+    let mut tree = IncrementalMerkleTree::with_config(config).unwrap();
+    for (leaf, root) in rfc6962_test_leaves().into_iter().zip(expected) {
+        tree.add_leaf(leaf).unwrap();
+        assert_eq!(::hex::encode(tree.root().unwrap()), root);
+    }
+}
+
+// Incremental root algorithm of the Ethereum deposit contract, stopping
+// before `get_deposit_root()` mixes in the deposit count
+fn deposit_contract_root(leaves: &[Vec<u8>], depth: usize) -> Vec<u8> {
+    use merkle_tree_api::hasher::{MerkleHasher, Sha256Hasher};
+
+    let mut zero_hashes = vec![vec![0u8; 32]];
+    for level in 0..depth {
+        zero_hashes.push(Sha256Hasher.hash_pair(&zero_hashes[level], &zero_hashes[level]));
+    }
+
+    let mut branch = vec![vec![0u8; 32]; depth];
+    for (count, leaf) in leaves.iter().enumerate() {
+        let mut node = leaf.clone();
+        let mut size = count + 1;
+        for level_branch in branch.iter_mut() {
+            if size % 2 == 1 {
+                *level_branch = node;
+                break;
+            }
+            node = Sha256Hasher.hash_pair(level_branch, &node);
+            size /= 2;
+        }
+    }
+
+    let mut node = vec![0u8; 32];
+    let mut size = leaves.len();
+    for level in 0..depth {
+        node = if size % 2 == 1 {
+            Sha256Hasher.hash_pair(&branch[level], &node)
+        } else {
+            Sha256Hasher.hash_pair(&node, &zero_hashes[level])
+        };
+        size /= 2;
+    }
+    node
+}
+
+// What `get_deposit_root()` returns: the root hashed with the deposit count
+// as a little-endian u64 padded to 32 bytes. The tree itself has no mix-in.
+fn mix_in_deposit_count(root: &[u8], count: usize) -> Vec<u8> {
+    use merkle_tree_api::hasher::{MerkleHasher, Sha256Hasher};
+
+    let mut length = (count as u64).to_le_bytes().to_vec();
+    length.resize(32, 0);
+    Sha256Hasher.hash_pair(root, &length)
+}
+
+#[test]
+fn test_fixed_depth_matches_deposit_contract() {
+    use merkle_tree_api::config::{MAX_LEVELS, TreeConfig};
+    use merkle_tree_api::hasher::{MerkleHasher, Sha256Hasher};
+
+    let config = TreeConfig::fixed_depth(Sha256Hasher, MAX_LEVELS);
+
+    // Published values of the deployed contract: its first zero hashes and
+    // `get_deposit_root()` before any deposit
+    let zero_hashes = config.zero_hashes(MAX_LEVELS);
+    assert_eq!(
+        ::hex::encode(&zero_hashes[1]),
+        "f5a5fd42d16a20302798ef6ed309979b43003d2320d9f0e8ea9831a92759fb4b"
+    );
+    assert_eq!(
+        ::hex::encode(&zero_hashes[2]),
+        "db56114e00fdd4c1f85c892bf35ac9a89289aaecb1ebd0a96cde606a748b5d71"
+    );
+
+    let mut tree = IncrementalMerkleTree::with_config(config).unwrap();
+    let empty_root = tree.root().unwrap();
+    assert_eq!(
+        ::hex::encode(&empty_root),
+        "c6f67e02e6e4e1bdefb994c6098953f34636ba2b6ca20a4721d2b26a886722ff"
+    );
+    assert_eq!(
+        ::hex::encode(mix_in_deposit_count(&empty_root, 0)),
+        "d70a234731285c6804c2a4f56711ddb8c82c99740f207854891028af34e27e5e"
+    );
+    assert_eq!(empty_root, deposit_contract_root(&[], MAX_LEVELS));
+
+    let leaves: Vec<Vec<u8>> = (0..20u32)
+        .map(|i| Sha256Hasher.hash(&i.to_be_bytes()))
+        .collect();
+    for (i, leaf) in leaves.iter().enumerate() {
+        tree.add_leaf(leaf.clone()).unwrap();
+        let root = tree.root().unwrap();
+        assert_eq!(root, deposit_contract_root(&leaves[..=i], MAX_LEVELS));
+
+        let proof = tree.get_proof(i).unwrap();
+        assert_eq!(proof.siblings.len(), MAX_LEVELS);
+        assert!(tree.verify_proof(leaf, &proof, &root, i));
+    }
+}
+
+#[test]
+fn test_fixed_depth_capacity() {
+    use merkle_tree_api::config::TreeConfig;
+    use merkle_tree_api::hasher::Keccak256Hasher;
+
+    let mut tree =
+        IncrementalMerkleTree::with_config(TreeConfig::fixed_depth(Keccak256Hasher, 3)).unwrap();
+    let leaves = leaves(8);
+    assert!(tree.add_leaves(leaves.clone()).is_ok());
+    let overflow = tree.add_leaf(hex("overflow"));
+    assert!(matches!(
+        overflow,
+        Err(MerkleError::CapacityExceeded { max_leaves: 8 })
+    ));
+    assert_eq!(overflow.unwrap_err().code(), "capacity_exceeded");
+    assert_eq!(tree.num_leaves(), 8);
+
+    let root = tree.root().unwrap();
+    for (i, leaf) in leaves.iter().enumerate() {
+        let proof = tree.get_proof(i).unwrap();
+        assert_eq!(proof.siblings.len(), 3);
+        assert!(tree.verify_proof(leaf, &proof, &root, i));
+    }
+}
+
+#[test]
+fn test_kary_root_matches_manual() {
+    use merkle_tree_api::config::OddNodeStrategy;
+    use merkle_tree_api::hasher::{Keccak256Hasher, MerkleHasher};
+
+    let leaves: Vec<Vec<u8>> = ["a", "b", "c", "d", "e"].iter().map(|s| hex(s)).collect();
+    let h = |parts: &[&[u8]]| Keccak256Hasher.hash_parts(parts);
+
+    let zero = vec![0u8; 32];
+    let first = h(&[&leaves[0], &leaves[1], &leaves[2], &leaves[3]]);
+
+    let mut zero_pad = kary_tree(4, OddNodeStrategy::ZeroPad, None);
+    zero_pad.add_leaves(leaves.clone()).unwrap();
+    let second = h(&[&leaves[4], &zero, &zero, &zero]);
+    let zero1 = h(&[&zero, &zero, &zero, &zero]);
+    assert_eq!(
+        zero_pad.root().unwrap(),
+        h(&[&first, &second, &zero1, &zero1])
+    );
+
+    let mut duplicate = kary_tree(4, OddNodeStrategy::Duplicate, None);
+    duplicate.add_leaves(leaves.clone()).unwrap();
+    let second = h(&[&leaves[4], &leaves[4], &leaves[4], &leaves[4]]);
+    assert_eq!(
+        duplicate.root().unwrap(),
+        h(&[&first, &second, &second, &second])
+    );
+
+    // Each level of a proof holds the other three children of the group
+    let proof = duplicate.get_proof(4).unwrap();
+    assert_eq!(proof.siblings.len(), 6);
+}
+
+#[test]
+fn test_kary_proofs_verify() {
+    use merkle_tree_api::config::OddNodeStrategy;
+
+    let leaves = leaves(40);
+
+    for arity in [4, 8, 16] {
+        for (odd_node, depth) in [
+            (OddNodeStrategy::Duplicate, None),
+            (OddNodeStrategy::ZeroPad, None),
+            (OddNodeStrategy::ZeroPad, Some(3)),
+        ] {
+            let mut tree = kary_tree(arity, odd_node, depth);
+            let mut roots = Vec::new();
+            for leaf in &leaves {
+                tree.add_leaf(leaf.clone()).unwrap();
+                roots.push(tree.root().unwrap());
+            }
+
+            let mut batch = kary_tree(arity, odd_node, depth);
+            batch.add_leaves(leaves.clone()).unwrap();
+            let root = batch.root().unwrap();
+            assert_eq!(tree.root().unwrap(), root);
+
+            for (i, leaf) in leaves.iter().enumerate() {
+                let proof = tree.get_proof(i).unwrap();
+                assert_eq!(proof.siblings.len() % (arity - 1), 0);
+                assert!(tree.verify_proof(leaf, &proof, &root, i), "{} {}", arity, i);
+                assert!(!tree.verify_proof(&hex("wrong"), &proof, &root, i));
+            }
+
+            for size in [1, 5, arity, arity + 1, 33] {
+                assert_eq!(tree.root_at(size).unwrap(), roots[size - 1]);
+                let proof = tree.get_proof_at(size - 1, size).unwrap();
+                assert!(tree.verify_proof_at(
+                    &leaves[size - 1],
+                    &proof,
+                    &roots[size - 1],
+                    size - 1,
+                    size
+                ));
+            }
+
+            for indices in [&[0][..], &[39], &[1, 2, 3], &[0, 5, 17, 18, 39]] {
+                let proof = tree.get_multiproof(indices).unwrap();
+                let proven: Vec<Vec<u8>> = indices.iter().map(|&i| leaves[i].clone()).collect();
+                assert!(tree.verify_multiproof(&proven, &proof, &root));
+            }
+
+            for (start, end) in [(0, 40), (3, 21), (arity - 1, arity + 1)] {
+                let proof = tree.get_range_proof(start, end).unwrap();
+                assert!(tree.verify_range_proof(&leaves[start..end], &proof, &root));
+            }
+
+            tree.update_leaf(6, hex("updated")).unwrap();
+            let mut rebuilt_leaves = leaves.clone();
+            rebuilt_leaves[6] = hex("updated");
+            let mut rebuilt = kary_tree(arity, odd_node, depth);
+            rebuilt.add_leaves(rebuilt_leaves).unwrap();
+            assert_eq!(tree.root(), rebuilt.root());
+        }
+    }
+}
+
+#[test]
+fn test_kary_config_validation() {
+    use merkle_tree_api::config::{HashScheme, OddNodeStrategy, TreeConfig};
+
+    let config = |arity, scheme, odd_node, depth| TreeConfig {
+        scheme,
+        odd_node,
+        depth,
+        arity,
+        ..TreeConfig::default()
+    };
+
+    assert!(
+        config(4, HashScheme::Rfc6962, OddNodeStrategy::ZeroPad, None)
+            .validate()
+            .is_ok()
+    );
+    assert!(
+        config(3, HashScheme::Raw, OddNodeStrategy::ZeroPad, None)
+            .validate()
+            .is_err()
+    );
+    assert!(
+        config(4, HashScheme::OpenZeppelin, OddNodeStrategy::ZeroPad, None)
+            .validate()
+            .is_err()
+    );
+    assert!(
+        config(4, HashScheme::Raw, OddNodeStrategy::Promote, None)
+            .validate()
+            .is_err()
+    );
+
+    // 16^8 leaves is the most a fixed-depth tree may hold
+    let deepest = config(16, HashScheme::Raw, OddNodeStrategy::ZeroPad, Some(8));
+    assert!(deepest.validate().is_ok());
+    assert_eq!(deepest.max_leaves(), 1 << 32);
+    assert!(
+        config(16, HashScheme::Raw, OddNodeStrategy::ZeroPad, Some(9))
+            .validate()
+            .is_err()
+    );
+}
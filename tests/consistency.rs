@@ -0,0 +1,87 @@
+mod common;
+
+use common::{hex, leaves, rfc6962_test_leaves, rfc6962_tree};
+use merkle_tree_api::merkle_tree::IncrementalMerkleTree;
+
+#[test]
+fn test_consistency_proof_matches_reference_vectors() {
+    // Consistency proofs for the RFC 6962 test leaves, as published with the
+    // Certificate Transparency reference implementation
+    let tree = rfc6962_tree(&rfc6962_test_leaves());
+    let expected: [(usize, usize, &[&str]); 4] = [
+        (1, 1, &[]),
+        (
+            1,
+            8,
+            &[
+                "96a296d224f285c67bee93c30f8a309157f0daa35dc5b87e410b78630a09cfc7",
+                "5f083f0a1a33ca076a95279832580db3e0ef4584bdff1f54c8a360f50de3031e",
+                "6b47aaf29ee3c2af9af889bc1fb9254dabd31177f16232dd6aab035ca39bf6e4",
+            ],
+        ),
+        (
+            6,
+            8,
+            &[
+                "0ebc5d3437fbe2db158b9f126a1d118e308181031d0a949f8dededebc558ef6a",
+                "ca854ea128ed050b41b35ffc1b87b8eb2bde461e9e3b5596ece6b9d5975a0ae0",
+                "d37ee418976dd95753c1c73862b9398fa2a2cf9b4ff0fdfe8b30cd95209614b7",
+            ],
+        ),
+        (
+            2,
+            5,
+            &[
+                "5f083f0a1a33ca076a95279832580db3e0ef4584bdff1f54c8a360f50de3031e",
+                "bc1a0643b12e4d2d7c77918f44e0f4f79a838b6cf9ec5b5c283e1f4d88599e6b",
+            ],
+        ),
+    ];
+
+    for (old_size, new_size, hashes) in expected {
+        let proof = tree.consistency_proof(old_size, new_size).unwrap();
+        assert_eq!(proof.hashes, hashes, "{} -> {}", old_size, new_size);
+    }
+}
+
+#[test]
+fn test_consistency_proofs_verify_between_all_sizes() {
+    let leaves = leaves(20);
+    let tree = rfc6962_tree(&leaves);
+    let roots: Vec<Vec<u8>> = (1..=leaves.len())
+        .map(|size| rfc6962_tree(&leaves[..size]).root().unwrap())
+        .collect();
+
+    for old_size in 1..=leaves.len() {
+        for new_size in old_size..=leaves.len() {
+            let old_root = &roots[old_size - 1];
+            let new_root = &roots[new_size - 1];
+            let proof = tree.consistency_proof(old_size, new_size).unwrap();
+            assert!(
+                tree.verify_consistency(old_root, new_root, &proof),
+                "{} -> {}",
+                old_size,
+                new_size
+            );
+
+            if old_size < new_size {
+                // Swapped roots, or a proof with a tampered hash, must not verify
+                assert!(!tree.verify_consistency(new_root, old_root, &proof));
+                let mut tampered = proof.clone();
+                tampered.hashes[0] = ::hex::encode([0u8; 32]);
+                assert!(!tree.verify_consistency(old_root, new_root, &tampered));
+            }
+        }
+    }
+
+    assert!(tree.consistency_proof(0, 5).is_err());
+    assert!(tree.consistency_proof(6, 5).is_err());
+    assert!(tree.consistency_proof(5, 21).is_err());
+}
+
+#[test]
+fn test_consistency_proof_requires_promote() {
+    let mut tree = IncrementalMerkleTree::new();
+    tree.add_leaves(vec![hex("a"), hex("b"), hex("c")]).unwrap();
+    assert!(tree.consistency_proof(1, 3).is_err());
+}
@@ -0,0 +1,166 @@
+mod common;
+
+use common::tree;
+use merkle_tree_api::config::{HashScheme, OddNodeStrategy, TreeConfig};
+use merkle_tree_api::consistency::ConsistencyProof;
+use merkle_tree_api::encoding::{BinaryEncoding, prefers_binary};
+use merkle_tree_api::hasher::Sha256Hasher;
+use merkle_tree_api::multiproof::MerkleMultiProof;
+use merkle_tree_api::range_proof::RangeProof;
+use merkle_tree_api::verify::{InclusionProof, MerkleProof};
+
+// Leaves are hashes already, so raw trees have fixed-width nodes too
+fn hash_leaves(count: usize) -> Vec<Vec<u8>> {
+    (0..count).map(|i| vec![i as u8; 32]).collect()
+}
+
+#[test]
+fn test_inclusion_proof_round_trip() {
+    let configs = [
+        TreeConfig::default(),
+        TreeConfig {
+            scheme: HashScheme::Rfc6962,
+            odd_node: OddNodeStrategy::Promote,
+            ..TreeConfig::new(Sha256Hasher)
+        },
+        TreeConfig::fixed_depth(Sha256Hasher, 6),
+        TreeConfig {
+            arity: 8,
+            ..TreeConfig::default()
+        },
+    ];
+
+    for config in configs {
+        let tree = tree(&config, &hash_leaves(11));
+        let proof = tree.get_inclusion_proof(6).unwrap();
+
+        let bytes = proof.encode().unwrap();
+        let decoded = InclusionProof::decode(&bytes).unwrap();
+        assert_eq!(decoded, proof);
+        assert!(decoded.verify());
+
+        // Raw hashes take far less space than the JSON hex strings
+        let json = serde_json::to_vec(&proof).unwrap();
+        assert!(bytes.len() * 2 < json.len());
+    }
+
+    // The version is one byte in either form
+    let mut json = serde_json::to_value(
+        tree(&TreeConfig::default(), &hash_leaves(2))
+            .get_inclusion_proof(0)
+            .unwrap(),
+    )
+    .unwrap();
+    json["version"] = 256.into();
+    assert!(serde_json::from_value::<InclusionProof>(json).is_err());
+}
+
+#[test]
+fn test_other_proofs_round_trip() {
+    let config = TreeConfig {
+        scheme: HashScheme::Rfc6962,
+        odd_node: OddNodeStrategy::Promote,
+        ..TreeConfig::new(Sha256Hasher)
+    };
+    let tree = tree(&config, &hash_leaves(13));
+
+    let proof = tree.get_proof(4).unwrap();
+    assert_eq!(
+        MerkleProof::decode(&proof.encode().unwrap()).unwrap(),
+        proof
+    );
+
+    let multiproof = tree.get_multiproof(&[0, 5, 12]).unwrap();
+    assert_eq!(
+        MerkleMultiProof::decode(&multiproof.encode().unwrap()).unwrap(),
+        multiproof
+    );
+
+    let range = tree.get_range_proof(3, 11).unwrap();
+    assert_eq!(RangeProof::decode(&range.encode().unwrap()).unwrap(), range);
+
+    let consistency = tree.consistency_proof(7, 13).unwrap();
+    assert_eq!(
+        ConsistencyProof::decode(&consistency.encode().unwrap()).unwrap(),
+        consistency
+    );
+
+    // A single-leaf proof has no siblings
+    let empty = MerkleProof { siblings: vec![] };
+    assert_eq!(
+        MerkleProof::decode(&empty.encode().unwrap()).unwrap(),
+        empty
+    );
+
+    // JSON proofs can be read back as well
+    let json = serde_json::to_string(&proof).unwrap();
+    assert_eq!(serde_json::from_str::<MerkleProof>(&json).unwrap(), proof);
+}
+
+#[test]
+fn test_decode_rejects_malformed_input() {
+    let tree = tree(&TreeConfig::default(), &hash_leaves(5));
+    let bytes = tree.get_inclusion_proof(2).unwrap().encode().unwrap();
+
+    assert!(InclusionProof::decode(&bytes[..bytes.len() - 1]).is_err());
+    assert!(InclusionProof::decode(&[bytes.as_slice(), &[0]].concat()).is_err());
+    assert!(MerkleProof::decode(&bytes).is_err());
+    assert!(InclusionProof::decode(&[]).is_err());
+
+    // Siblings of different widths have no fixed-width encoding
+    let uneven = MerkleProof {
+        siblings: vec![hex::encode([1u8; 32]), hex::encode([2u8; 20])],
+    };
+    assert!(uneven.encode().is_err());
+}
+
+#[test]
+fn test_empty_hashes_have_no_encoding() {
+    // Five zero-width siblings would decode as none
+    assert!(MerkleProof::decode(&[0x01, 0, 0, 0, 0, 5]).is_err());
+    assert!(
+        MerkleProof::decode(&[0x01, 0, 0, 0, 0, 0])
+            .unwrap()
+            .siblings
+            .is_empty()
+    );
+
+    let empty_sibling = MerkleProof {
+        siblings: vec![String::new(), String::new()],
+    };
+    assert!(empty_sibling.encode().is_err());
+
+    for siblings in [
+        vec![],
+        vec![hex::encode([7u8; 1])],
+        vec![hex::encode([7u8; 32]); 3],
+    ] {
+        let proof = MerkleProof { siblings };
+        let bytes = proof.encode().unwrap();
+        assert_eq!(MerkleProof::decode(&bytes).unwrap(), proof);
+        assert_eq!(
+            MerkleProof::decode(&bytes).unwrap().encode().unwrap(),
+            bytes
+        );
+    }
+}
+
+#[test]
+fn test_accept_header_preference() {
+    assert!(prefers_binary("application/octet-stream"));
+    assert!(prefers_binary(
+        "application/json;q=0.5, application/octet-stream"
+    ));
+    assert!(prefers_binary("Application/Octet-Stream; q=0.9"));
+    assert!(prefers_binary("application/*, application/json;q=0.1"));
+
+    assert!(!prefers_binary("application/json"));
+    assert!(!prefers_binary("*/*"));
+    assert!(!prefers_binary("application/octet-stream;q=0"));
+    assert!(!prefers_binary("application/octet-stream;q=0.0, */*"));
+    assert!(!prefers_binary(
+        "application/octet-stream;q=0.5, application/json"
+    ));
+    assert!(!prefers_binary("application/octet-stream-like"));
+    assert!(!prefers_binary("text/application/octet-stream"));
+}
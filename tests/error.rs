@@ -0,0 +1,33 @@
+use merkle_tree_api::error::MerkleError;
+
+#[test]
+fn test_error_http_status() {
+    let cases = [
+        (MerkleError::CapacityExceeded { max_leaves: 8 }, 409),
+        (MerkleError::IndexOutOfRange { index: 3, size: 2 }, 404),
+        (MerkleError::LeafNotFound, 404),
+        (MerkleError::InvalidArgument("bad size".to_string()), 400),
+        (
+            MerkleError::ConfigMismatch("created with sha256".to_string()),
+            409,
+        ),
+        (MerkleError::InvalidProof("bad width".to_string()), 422),
+        (MerkleError::Storage("disk full".into()), 500),
+        (MerkleError::Corrupted("bad metadata".to_string()), 500),
+    ];
+
+    for (error, status) in cases {
+        assert_eq!(error.http_status(), status, "{}", error.code());
+    }
+}
+
+#[test]
+fn test_storage_error_keeps_source() {
+    use std::error::Error;
+
+    let error = MerkleError::Storage("disk full".into());
+    assert_eq!(error.code(), "storage_error");
+    assert_eq!(error.to_string(), "Storage error: disk full");
+    assert_eq!(error.source().unwrap().to_string(), "disk full");
+    assert!(MerkleError::LeafNotFound.source().is_none());
+}
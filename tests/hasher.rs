@@ -0,0 +1,47 @@
+use merkle_tree_api::hasher::{
+    Blake3Hasher, Keccak256Hasher, MerkleHasher, Sha3_256Hasher, Sha256Hasher, hasher_from_id,
+};
+
+#[test]
+fn test_known_empty_digests() {
+    assert_eq!(
+        hex::encode(Keccak256Hasher.hash(b"")),
+        "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+    );
+    assert_eq!(
+        hex::encode(Sha256Hasher.hash(b"")),
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+    assert_eq!(
+        hex::encode(Sha3_256Hasher.hash(b"")),
+        "a7ffc6f8bf1ed76651c14756a061d662f580ff4de43b49fa82d80a4b80f8434a"
+    );
+    assert_eq!(
+        hex::encode(Blake3Hasher.hash(b"")),
+        "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"
+    );
+}
+
+#[test]
+fn test_hash_pair_is_concatenation() {
+    let hashers: [&dyn MerkleHasher; 4] = [
+        &Keccak256Hasher,
+        &Sha256Hasher,
+        &Sha3_256Hasher,
+        &Blake3Hasher,
+    ];
+    for hasher in hashers {
+        assert_eq!(
+            hasher.hash_pair(b"left", b"right"),
+            hasher.hash(b"leftright")
+        );
+    }
+}
+
+#[test]
+fn test_hasher_from_id() {
+    for id in ["keccak256", "sha256", "sha3-256", "blake3"] {
+        assert_eq!(hasher_from_id(id).unwrap().id(), id);
+    }
+    assert!(hasher_from_id("md5").is_none());
+}
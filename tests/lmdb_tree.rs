@@ -1,3 +1,6 @@
+mod common;
+
+use common::{hex, leaves};
 use merkle_tree_api::lmdb_tree::LmdbMerkleTree;
 use tempfile::TempDir;
 
@@ -8,10 +11,6 @@ fn create_temp_tree() -> (LmdbMerkleTree, TempDir) {
     (tree, temp_dir)
 }
 
-fn hex(s: &str) -> Vec<u8> {
-    s.as_bytes().to_vec()
-}
-
 #[test]
 fn test_lmdb_tree_new() {
     let (_tree, _temp_dir) = create_temp_tree();
@@ -21,8 +20,8 @@ fn test_lmdb_tree_new() {
 #[test]
 fn test_empty_tree() {
     let (tree, _temp_dir) = create_temp_tree();
-    assert_eq!(tree.num_leaves(), 0);
-    assert!(tree.root().is_none());
+    assert_eq!(tree.num_leaves().unwrap(), 0);
+    assert!(tree.root().unwrap().is_none());
 }
 
 #[test]
@@ -30,8 +29,8 @@ fn test_single_leaf() {
     let (tree, _temp_dir) = create_temp_tree();
 
     assert!(tree.add_leaf(hex("leaf1")).is_ok());
-    assert_eq!(tree.num_leaves(), 1);
-    assert!(tree.root().is_some());
+    assert_eq!(tree.num_leaves().unwrap(), 1);
+    assert!(tree.root().unwrap().is_some());
 }
 
 #[test]
@@ -42,8 +41,8 @@ fn test_multiple_leaves() {
     assert!(tree.add_leaf(hex("leaf2")).is_ok());
     assert!(tree.add_leaf(hex("leaf3")).is_ok());
 
-    assert_eq!(tree.num_leaves(), 3);
-    assert!(tree.root().is_some());
+    assert_eq!(tree.num_leaves().unwrap(), 3);
+    assert!(tree.root().unwrap().is_some());
 }
 
 #[test]
@@ -52,8 +51,8 @@ fn test_add_leaves_batch() {
 
     let leaves = vec![hex("leaf1"), hex("leaf2"), hex("leaf3")];
     assert!(tree.add_leaves(leaves).is_ok());
-    assert_eq!(tree.num_leaves(), 3);
-    assert!(tree.root().is_some());
+    assert_eq!(tree.num_leaves().unwrap(), 3);
+    assert!(tree.root().unwrap().is_some());
 }
 
 #[test]
@@ -64,10 +63,10 @@ fn test_proof_generation_valid_and_invalid_index() {
     assert!(tree.add_leaves(leaves).is_ok());
 
     let valid_proof = tree.get_proof(0);
-    assert!(valid_proof.is_some());
+    assert!(valid_proof.is_ok());
 
     let invalid_proof = tree.get_proof(999);
-    assert!(invalid_proof.is_none());
+    assert!(invalid_proof.is_err());
 }
 
 #[test]
@@ -77,8 +76,8 @@ fn test_root_consistency() {
     let leaves = vec![hex("x"), hex("y"), hex("z")];
     assert!(tree.add_leaves(leaves).is_ok());
 
-    let root1 = tree.root();
-    let root2 = tree.root();
+    let root1 = tree.root().unwrap();
+    let root2 = tree.root().unwrap();
 
     assert_eq!(root1, root2);
     assert!(root1.is_some());
@@ -89,10 +88,10 @@ fn test_root_changes_after_addition() {
     let (tree, _temp_dir) = create_temp_tree();
 
     assert!(tree.add_leaf(hex("1")).is_ok());
-    let root1 = tree.root();
+    let root1 = tree.root().unwrap();
 
     assert!(tree.add_leaf(hex("2")).is_ok());
-    let root2 = tree.root();
+    let root2 = tree.root().unwrap();
 
     assert_ne!(root1, root2);
     assert!(root1.is_some());
@@ -107,10 +106,10 @@ fn test_proof_structure() {
     assert!(tree.add_leaves(leaves).is_ok());
 
     let proof = tree.get_proof(2);
-    assert!(proof.is_some());
+    assert!(proof.is_ok());
     let proof = proof.unwrap();
 
-    assert!(proof.siblings.len() > 0);
+    assert!(!proof.siblings.is_empty());
 }
 
 #[test]
@@ -120,7 +119,7 @@ fn test_proof_verification_valid() {
     let leaves = vec![hex("a"), hex("b"), hex("c"), hex("d")];
     assert!(tree.add_leaves(leaves.clone()).is_ok());
 
-    let root = tree.root().unwrap();
+    let root = tree.root().unwrap().unwrap();
     let proof = tree.get_proof(1).unwrap();
 
     assert!(tree.verify_proof(&leaves[1], &proof, &root, 1));
@@ -133,7 +132,7 @@ fn test_proof_verification_invalid_leaf() {
     let leaves = vec![hex("a"), hex("b"), hex("c"), hex("d")];
     assert!(tree.add_leaves(leaves).is_ok());
 
-    let root = tree.root().unwrap();
+    let root = tree.root().unwrap().unwrap();
     let proof = tree.get_proof(1).unwrap();
 
     // Wrong leaf data
@@ -160,7 +159,7 @@ fn test_proof_verification_single_leaf() {
     let leaf = hex("single");
     assert!(tree.add_leaf(leaf.clone()).is_ok());
 
-    let root = tree.root().unwrap();
+    let root = tree.root().unwrap().unwrap();
     let proof = tree.get_proof(0).unwrap();
 
     assert!(tree.verify_proof(&leaf, &proof, &root, 0));
@@ -177,29 +176,29 @@ fn test_persistence_across_instances() {
         let tree = LmdbMerkleTree::new(db_path_str).unwrap();
         let leaves = vec![hex("persist1"), hex("persist2"), hex("persist3")];
         assert!(tree.add_leaves(leaves).is_ok());
-        assert_eq!(tree.num_leaves(), 3);
+        assert_eq!(tree.num_leaves().unwrap(), 3);
     }
 
     // Second instance - should load existing data
     {
         let tree = LmdbMerkleTree::new(db_path_str).unwrap();
-        assert_eq!(tree.num_leaves(), 3);
-        assert!(tree.root().is_some());
+        assert_eq!(tree.num_leaves().unwrap(), 3);
+        assert!(tree.root().unwrap().is_some());
 
         // Add more data
         assert!(tree.add_leaf(hex("persist4")).is_ok());
-        assert_eq!(tree.num_leaves(), 4);
+        assert_eq!(tree.num_leaves().unwrap(), 4);
     }
 
     // Third instance - should have all data
     {
         let tree = LmdbMerkleTree::new(db_path_str).unwrap();
-        assert_eq!(tree.num_leaves(), 4);
-        assert!(tree.root().is_some());
+        assert_eq!(tree.num_leaves().unwrap(), 4);
+        assert!(tree.root().unwrap().is_some());
 
         // Should be able to get proofs for all indices
         for i in 0..4 {
-            assert!(tree.get_proof(i).is_some());
+            assert!(tree.get_proof(i).is_ok());
         }
     }
 }
@@ -218,14 +217,14 @@ fn test_large_batch_operations() {
         assert!(tree.add_leaves(batch_leaves).is_ok());
     }
 
-    assert_eq!(tree.num_leaves(), 100);
-    assert!(tree.root().is_some());
+    assert_eq!(tree.num_leaves().unwrap(), 100);
+    assert!(tree.root().unwrap().is_some());
 
     // Test proof generation for various indices
-    assert!(tree.get_proof(0).is_some());
-    assert!(tree.get_proof(50).is_some());
-    assert!(tree.get_proof(99).is_some());
-    assert!(tree.get_proof(100).is_none());
+    assert!(tree.get_proof(0).is_ok());
+    assert!(tree.get_proof(50).is_ok());
+    assert!(tree.get_proof(99).is_ok());
+    assert!(tree.get_proof(100).is_err());
 }
 
 #[test]
@@ -234,20 +233,670 @@ fn test_mixed_single_and_batch_operations() {
 
     // Add single leaf
     assert!(tree.add_leaf(hex("single1")).is_ok());
-    assert_eq!(tree.num_leaves(), 1);
+    assert_eq!(tree.num_leaves().unwrap(), 1);
 
     // Add batch
     let batch = vec![hex("batch1"), hex("batch2")];
     assert!(tree.add_leaves(batch).is_ok());
-    assert_eq!(tree.num_leaves(), 3);
+    assert_eq!(tree.num_leaves().unwrap(), 3);
 
     // Add another single leaf
     assert!(tree.add_leaf(hex("single2")).is_ok());
-    assert_eq!(tree.num_leaves(), 4);
+    assert_eq!(tree.num_leaves().unwrap(), 4);
 
     // Verify all operations worked
-    assert!(tree.root().is_some());
+    assert!(tree.root().unwrap().is_some());
     for i in 0..4 {
-        assert!(tree.get_proof(i).is_some());
+        assert!(tree.get_proof(i).is_ok());
+    }
+}
+
+#[test]
+fn test_hasher_persisted_across_instances() {
+    use merkle_tree_api::config::TreeConfig;
+    use merkle_tree_api::hasher::{Blake3Hasher, Keccak256Hasher};
+
+    let temp_dir = TempDir::new().unwrap();
+    let db_path = temp_dir.path().join("test.db");
+    let db_path_str = db_path.to_str().unwrap();
+
+    let root = {
+        let tree = LmdbMerkleTree::with_config(db_path_str, TreeConfig::new(Blake3Hasher)).unwrap();
+        assert!(tree.add_leaves(vec![hex("a"), hex("b")]).is_ok());
+        tree.root().unwrap().unwrap()
+    };
+
+    // Reopening without a config keeps the stored hasher
+    let tree = LmdbMerkleTree::new(db_path_str).unwrap();
+    assert_eq!(tree.config().hasher.id(), "blake3");
+    assert!(tree.add_leaf(hex("c")).is_ok());
+    let proof = tree.get_proof(0).unwrap();
+    assert!(!tree.verify_proof(&hex("a"), &proof, &root, 0));
+    assert!(tree.verify_proof(&hex("a"), &proof, &tree.root().unwrap().unwrap(), 0));
+    drop(tree);
+
+    // Reopening with a different hasher is rejected
+    assert!(LmdbMerkleTree::with_config(db_path_str, TreeConfig::new(Keccak256Hasher)).is_err());
+}
+
+#[test]
+fn test_hash_scheme_persisted_across_instances() {
+    use merkle_tree_api::config::{HashScheme, TreeConfig};
+    use merkle_tree_api::error::MerkleError;
+
+    let temp_dir = TempDir::new().unwrap();
+    let db_path = temp_dir.path().join("test.db");
+    let db_path_str = db_path.to_str().unwrap();
+    let config = TreeConfig {
+        scheme: HashScheme::Rfc6962,
+        ..TreeConfig::default()
+    };
+
+    {
+        let tree = LmdbMerkleTree::with_config(db_path_str, config.clone()).unwrap();
+        assert!(tree.add_leaves(vec![hex("a"), hex("b"), hex("c")]).is_ok());
+    }
+
+    let tree = LmdbMerkleTree::new(db_path_str).unwrap();
+    assert_eq!(tree.config().scheme, HashScheme::Rfc6962);
+    let root = tree.root().unwrap().unwrap();
+    let proof = tree.get_proof(2).unwrap();
+    assert!(tree.verify_proof(&hex("c"), &proof, &root, 2));
+    drop(tree);
+
+    assert!(matches!(
+        LmdbMerkleTree::with_config(db_path_str, TreeConfig::default()),
+        Err(MerkleError::ConfigMismatch(_))
+    ));
+    assert!(LmdbMerkleTree::with_config(db_path_str, config).is_ok());
+}
+
+#[test]
+fn test_odd_node_strategy_matches_heap_tree() {
+    use merkle_tree_api::config::{OddNodeStrategy, TreeConfig};
+    use merkle_tree_api::merkle_tree::IncrementalMerkleTree;
+
+    for odd_node in [OddNodeStrategy::Promote, OddNodeStrategy::ZeroPad] {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let db_path_str = db_path.to_str().unwrap();
+        let config = TreeConfig {
+            odd_node,
+            ..TreeConfig::default()
+        };
+        let leaves = leaves(11);
+
+        let mut heap = IncrementalMerkleTree::with_config(config.clone()).unwrap();
+        heap.add_leaves(leaves.clone()).unwrap();
+        {
+            let tree = LmdbMerkleTree::with_config(db_path_str, config).unwrap();
+            assert!(tree.add_leaves(leaves.clone()).is_ok());
+            assert_eq!(tree.root().unwrap(), heap.root());
+        }
+
+        let tree = LmdbMerkleTree::new(db_path_str).unwrap();
+        assert_eq!(tree.config().odd_node, odd_node);
+        let root = tree.root().unwrap().unwrap();
+        for (i, leaf) in leaves.iter().enumerate() {
+            let proof = tree.get_proof(i).unwrap();
+            assert_eq!(proof.siblings, heap.get_proof(i).unwrap().siblings);
+            assert!(tree.verify_proof(leaf, &proof, &root, i));
+        }
+    }
+}
+
+#[test]
+fn test_kary_tree_matches_heap_tree() {
+    use merkle_tree_api::config::{OddNodeStrategy, TreeConfig};
+    use merkle_tree_api::merkle_tree::IncrementalMerkleTree;
+
+    let temp_dir = TempDir::new().unwrap();
+    let db_path = temp_dir.path().join("test.db");
+    let db_path_str = db_path.to_str().unwrap();
+    let config = TreeConfig {
+        odd_node: OddNodeStrategy::ZeroPad,
+        arity: 4,
+        ..TreeConfig::default()
+    };
+    let leaves = leaves(19);
+
+    let mut heap = IncrementalMerkleTree::with_config(config.clone()).unwrap();
+    heap.add_leaves(leaves.clone()).unwrap();
+    {
+        let tree = LmdbMerkleTree::with_config(db_path_str, config.clone()).unwrap();
+        tree.add_leaf(leaves[0].clone()).unwrap();
+        tree.add_leaves(leaves[1..].to_vec()).unwrap();
+        assert_eq!(tree.root().unwrap(), heap.root());
+        // 19 leaves fit in three levels of a 4-ary tree instead of five
+        assert_eq!(tree.get_proof(0).unwrap().siblings.len(), 9);
+    }
+
+    let tree = LmdbMerkleTree::new(db_path_str).unwrap();
+    assert_eq!(tree.config().arity, 4);
+    let root = tree.root().unwrap().unwrap();
+    for (i, leaf) in leaves.iter().enumerate() {
+        let proof = tree.get_proof(i).unwrap();
+        assert_eq!(proof.siblings, heap.get_proof(i).unwrap().siblings);
+        assert!(tree.verify_proof(leaf, &proof, &root, i));
+    }
+    assert_eq!(tree.root_at(7).unwrap(), heap.root_at(7).unwrap());
+
+    tree.update_leaf(10, hex("updated")).unwrap();
+    heap.update_leaf(10, hex("updated")).unwrap();
+    assert_eq!(tree.root().unwrap(), heap.root());
+    drop(tree);
+
+    let binary = TreeConfig {
+        arity: 2,
+        ..config.clone()
+    };
+    assert!(LmdbMerkleTree::with_config(db_path_str, binary).is_err());
+    assert!(LmdbMerkleTree::with_config(db_path_str, config).is_ok());
+}
+
+#[test]
+fn test_fixed_depth_tree() {
+    use merkle_tree_api::config::{MAX_LEVELS, TreeConfig};
+    use merkle_tree_api::hasher::Keccak256Hasher;
+    use merkle_tree_api::merkle_tree::IncrementalMerkleTree;
+
+    let temp_dir = TempDir::new().unwrap();
+    let db_path = temp_dir.path().join("test.db");
+    let db_path_str = db_path.to_str().unwrap();
+    let config = TreeConfig::fixed_depth(Keccak256Hasher, 16);
+
+    let mut heap = IncrementalMerkleTree::with_config(config.clone()).unwrap();
+    {
+        let tree = LmdbMerkleTree::with_config(db_path_str, config).unwrap();
+        assert_eq!(tree.root().unwrap(), heap.root());
+
+        let leaves = leaves(5);
+        heap.add_leaves(leaves.clone()).unwrap();
+        assert!(tree.add_leaves(leaves).is_ok());
+        assert_eq!(tree.root().unwrap(), heap.root());
+    }
+
+    let tree = LmdbMerkleTree::new(db_path_str).unwrap();
+    assert_eq!(tree.config().depth, Some(16));
+    let proof = tree.get_proof(4).unwrap();
+    assert_eq!(proof.siblings.len(), 16);
+    assert!(tree.verify_proof(&hex("leaf4"), &proof, &tree.root().unwrap().unwrap(), 4));
+
+    let other_dir = TempDir::new().unwrap();
+    let other_path = other_dir.path().join("test.db");
+    let too_deep = TreeConfig::fixed_depth(Keccak256Hasher, MAX_LEVELS + 1);
+    assert!(LmdbMerkleTree::with_config(other_path.to_str().unwrap(), too_deep).is_err());
+}
+
+#[test]
+fn test_update_leaf_persisted() {
+    use merkle_tree_api::merkle_tree::IncrementalMerkleTree;
+
+    let temp_dir = TempDir::new().unwrap();
+    let db_path = temp_dir.path().join("test.db");
+    let db_path = db_path.to_str().unwrap();
+
+    let leaves = leaves(9);
+    {
+        let tree = LmdbMerkleTree::new(db_path).unwrap();
+        tree.add_leaves(leaves.clone()).unwrap();
+        let old_proof = tree.get_proof(8).unwrap();
+
+        tree.update_leaf(8, hex("updated")).unwrap();
+        let root = tree.root().unwrap().unwrap();
+        assert!(!tree.verify_proof(&leaves[8], &old_proof, &root, 8));
+        assert!(tree.update_leaf(9, hex("missing")).is_err());
+    }
+
+    let mut expected_leaves = leaves;
+    expected_leaves[8] = hex("updated");
+    let mut expected = IncrementalMerkleTree::new();
+    expected.add_leaves(expected_leaves).unwrap();
+
+    let tree = LmdbMerkleTree::new(db_path).unwrap();
+    let root = tree.root().unwrap().unwrap();
+    assert_eq!(Some(root.clone()), expected.root());
+
+    let proof = tree.get_proof(8).unwrap();
+    assert!(tree.verify_proof(&hex("updated"), &proof, &root, 8));
+
+    // The heap tree loads the updated leaf from the same storage
+    drop(tree);
+    let heap = IncrementalMerkleTree::new_with_storage(db_path).unwrap();
+    assert_eq!(heap.root(), Some(root));
+}
+
+#[test]
+fn test_heap_tree_stores_changed_nodes() {
+    use merkle_tree_api::config::{HashScheme, OddNodeStrategy, TreeConfig};
+    use merkle_tree_api::hasher::Sha256Hasher;
+    use merkle_tree_api::merkle_tree::IncrementalMerkleTree;
+
+    let configs = [
+        TreeConfig::default(),
+        TreeConfig {
+            scheme: HashScheme::Rfc6962,
+            odd_node: OddNodeStrategy::Promote,
+            ..TreeConfig::new(Sha256Hasher)
+        },
+        TreeConfig {
+            arity: 4,
+            ..TreeConfig::default()
+        },
+    ];
+
+    for config in configs {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let db_path = db_path.to_str().unwrap();
+
+        // Every write reopens the tree, so the next one starts from the stored cache
+        let mut expected = IncrementalMerkleTree::with_config(config.clone()).unwrap();
+        for step in 0..12 {
+            let mut heap =
+                IncrementalMerkleTree::new_with_storage_and_config(db_path, config.clone())
+                    .unwrap();
+            let leaves: Vec<Vec<u8>> = (0..step % 4 + 1)
+                .map(|i| format!("leaf{}-{}", step, i).into_bytes())
+                .collect();
+            heap.add_leaves(leaves.clone()).unwrap();
+            expected.add_leaves(leaves).unwrap();
+
+            let index = step * 7 % heap.num_leaves();
+            heap.update_leaf(index, format!("updated{}", step).into_bytes())
+                .unwrap();
+            expected
+                .update_leaf(index, format!("updated{}", step).into_bytes())
+                .unwrap();
+        }
+
+        // The LMDB tree reads proofs from the stored cache
+        let tree = LmdbMerkleTree::new(db_path).unwrap();
+        assert_eq!(tree.root().unwrap(), expected.root());
+        for index in 0..expected.num_leaves() {
+            assert_eq!(
+                tree.get_proof(index).unwrap(),
+                expected.get_proof(index).unwrap()
+            );
+        }
+    }
+}
+
+#[test]
+fn test_multiproof() {
+    let (tree, _temp_dir) = create_temp_tree();
+    let leaves = leaves(11);
+    tree.add_leaves(leaves.clone()).unwrap();
+    let root = tree.root().unwrap().unwrap();
+
+    let proof = tree.get_multiproof(&[10, 2, 3, 6]).unwrap();
+    assert_eq!(proof.indices, vec![2, 3, 6, 10]);
+
+    let proven: Vec<Vec<u8>> = proof.indices.iter().map(|&i| leaves[i].clone()).collect();
+    assert!(tree.verify_multiproof(&proven, &proof, &root));
+    assert!(!tree.verify_multiproof(&proven, &proof, &hex("wrong root")));
+
+    assert!(tree.get_multiproof(&[11]).is_err());
+}
+
+#[test]
+fn test_range_proof() {
+    let (tree, _temp_dir) = create_temp_tree();
+    let leaves = leaves(11);
+    tree.add_leaves(leaves.clone()).unwrap();
+    let root = tree.root().unwrap().unwrap();
+
+    let proof = tree.get_range_proof(2, 9).unwrap();
+    assert!(tree.verify_range_proof(&leaves[2..9], &proof, &root));
+    assert!(!tree.verify_range_proof(&leaves[2..9], &proof, &hex("wrong root")));
+
+    let old_root = tree.root_at(6).unwrap();
+    let proof = tree.get_range_proof_at(1, 6, 6).unwrap();
+    assert!(tree.verify_range_proof(&leaves[1..6], &proof, &old_root));
+    assert_eq!(
+        tree.snapshot()
+            .unwrap()
+            .get_range_proof_at(1, 6, 6)
+            .unwrap(),
+        proof
+    );
+
+    assert!(tree.get_range_proof(4, 12).is_err());
+}
+
+#[test]
+fn test_consistency_proof() {
+    use merkle_tree_api::config::{OddNodeStrategy, TreeConfig};
+
+    let temp_dir = TempDir::new().unwrap();
+    let db_path = temp_dir.path().join("test.db");
+    let config = TreeConfig {
+        odd_node: OddNodeStrategy::Promote,
+        ..TreeConfig::default()
+    };
+    let tree = LmdbMerkleTree::with_config(db_path.to_str().unwrap(), config).unwrap();
+
+    tree.add_leaves((0..7).map(|i| format!("leaf{}", i).into_bytes()).collect())
+        .unwrap();
+    let old_root = tree.root().unwrap().unwrap();
+    tree.add_leaves((7..13).map(|i| format!("leaf{}", i).into_bytes()).collect())
+        .unwrap();
+    let new_root = tree.root().unwrap().unwrap();
+
+    let proof = tree.consistency_proof(7, 13).unwrap();
+    assert!(tree.verify_consistency(&old_root, &new_root, &proof));
+    assert!(!tree.verify_consistency(&new_root, &old_root, &proof));
+    assert!(tree.consistency_proof(7, 14).is_err());
+}
+
+#[test]
+fn test_root_at_recorded_history() {
+    use merkle_tree_api::merkle_tree::IncrementalMerkleTree;
+
+    let temp_dir = TempDir::new().unwrap();
+    let db_path = temp_dir.path().join("test.db");
+    let db_path = db_path.to_str().unwrap();
+
+    let leaves = leaves(6);
+    let mut heap = IncrementalMerkleTree::new();
+    let mut roots = Vec::new();
+
+    {
+        let tree = LmdbMerkleTree::new(db_path).unwrap();
+        tree.add_leaf(leaves[0].clone()).unwrap();
+        heap.add_leaf(leaves[0].clone()).unwrap();
+        roots.push(heap.root().unwrap());
+        // Sizes passed through inside a batch are recorded too
+        tree.add_leaves(leaves[1..3].to_vec()).unwrap();
+        heap.add_leaves(leaves[1..3].to_vec()).unwrap();
+        for size in 2..=3 {
+            roots.push(heap.root_at(size).unwrap());
+        }
+
+        // An update at the current size is part of that size's root
+        tree.update_leaf(2, hex("updated")).unwrap();
+        heap.update_leaf(2, hex("updated")).unwrap();
+        roots[2] = heap.root().unwrap();
+        tree.add_leaves(leaves[3..].to_vec()).unwrap();
+        heap.add_leaves(leaves[3..].to_vec()).unwrap();
+        for size in 4..=6 {
+            roots.push(heap.root_at(size).unwrap());
+        }
+    }
+
+    // Later updates change only the current root, in both trees
+    let tree = LmdbMerkleTree::new(db_path).unwrap();
+    tree.update_leaf(0, hex("updated")).unwrap();
+    heap.update_leaf(0, hex("updated")).unwrap();
+    tree.update_leaf(0, hex("updated again")).unwrap();
+    heap.update_leaf(0, hex("updated again")).unwrap();
+    roots[5] = heap.root().unwrap();
+
+    for (size, root) in (1..).zip(&roots) {
+        assert_eq!(&tree.root_at(size).unwrap(), root);
+        assert_eq!(&heap.root_at(size).unwrap(), root);
+        assert_eq!(&heap.snapshot().root_at(size).unwrap(), root);
+    }
+    assert_eq!(tree.root_at(6).ok(), tree.root().unwrap());
+    assert!(tree.root_at(0).is_err());
+    assert!(tree.root_at(7).is_err());
+
+    // The current leaves no longer prove the earlier roots
+    for size in 1..6 {
+        for result in [tree.get_proof_at(0, size), heap.get_proof_at(0, size)] {
+            assert_eq!(result.unwrap_err().code(), "invalid_argument");
+        }
     }
+    assert!(tree.get_proof_at(0, 6).is_ok());
+    drop(tree);
+
+    // Either tree reads the other's history back from the same database
+    let mut stored_heap = IncrementalMerkleTree::new_with_storage(db_path).unwrap();
+    stored_heap.update_leaf(1, hex("updated")).unwrap();
+    roots[5] = stored_heap.root().unwrap();
+    stored_heap.add_leaf(hex("leaf6")).unwrap();
+    roots.push(stored_heap.root().unwrap());
+    drop(stored_heap);
+
+    let tree = LmdbMerkleTree::new(db_path).unwrap();
+    for (size, root) in (1..).zip(&roots) {
+        assert_eq!(&tree.root_at(size).unwrap(), root);
+    }
+}
+
+#[test]
+fn test_proof_at_historical_size() {
+    let (tree, _temp_dir) = create_temp_tree();
+    let leaves = leaves(10);
+
+    tree.add_leaves(leaves[..5].to_vec()).unwrap();
+    let old_root = tree.root().unwrap().unwrap();
+    tree.add_leaves(leaves[5..].to_vec()).unwrap();
+
+    let new_root = tree.root().unwrap().unwrap();
+    for (index, leaf) in leaves[..5].iter().enumerate() {
+        let proof = tree.get_proof_at(index, 5).unwrap();
+        assert!(tree.verify_proof_at(leaf, &proof, &old_root, index, 5));
+        assert!(!tree.verify_proof(leaf, &proof, &new_root, index));
+    }
+    assert!(tree.get_proof_at(5, 5).is_err());
+    assert!(tree.get_proof_at(0, 11).is_err());
+}
+
+#[test]
+fn test_inclusion_proof() {
+    let (tree, _temp_dir) = create_temp_tree();
+    let leaves = leaves(7);
+    tree.add_leaves(leaves.clone()).unwrap();
+
+    let proof = tree.get_inclusion_proof(4).unwrap();
+    assert_eq!(proof.leaf, ::hex::encode(&leaves[4]));
+    assert_eq!(proof.root, ::hex::encode(tree.root().unwrap().unwrap()));
+    assert!(proof.verify());
+
+    let old_proof = tree.get_inclusion_proof_at(1, 3).unwrap();
+    assert_eq!(old_proof.root, ::hex::encode(tree.root_at(3).unwrap()));
+    assert!(old_proof.verify());
+
+    assert!(tree.get_inclusion_proof(7).is_err());
+}
+
+#[test]
+fn test_preimages_stored_with_leaves() {
+    let (tree, _temp_dir) = create_temp_tree();
+
+    let leaf = tree.add_leaf_preimage(b"alice".to_vec(), true).unwrap();
+    assert_eq!(leaf, tree.config().hasher.hash(b"alice"));
+
+    tree.add_leaf_preimages(vec![b"bob".to_vec(), b"carol".to_vec()], false)
+        .unwrap();
+    tree.add_leaf_preimages(vec![b"dave".to_vec()], true)
+        .unwrap();
+
+    assert_eq!(tree.get_preimage(0).unwrap(), Some(b"alice".to_vec()));
+    assert_eq!(tree.get_preimage(1).unwrap(), None);
+    assert_eq!(tree.get_preimage(3).unwrap(), Some(b"dave".to_vec()));
+
+    let proof = tree.get_inclusion_proof(3).unwrap();
+    assert_eq!(proof.leaf, hex::encode(tree.config().hasher.hash(b"dave")));
+    assert!(proof.verify());
+
+    // An updated leaf no longer matches its preimage
+    tree.update_leaf(0, hex("other")).unwrap();
+    assert_eq!(tree.get_preimage(0).unwrap(), None);
+}
+
+#[test]
+fn test_large_batch_matches_heap_tree() {
+    use merkle_tree_api::config::{OddNodeStrategy, PARALLEL_LEVEL_SIZE, TreeConfig};
+    use merkle_tree_api::merkle_tree::IncrementalMerkleTree;
+
+    let temp_dir = TempDir::new().unwrap();
+    let db_path = temp_dir.path().join("test.db");
+    let config = TreeConfig {
+        odd_node: OddNodeStrategy::ZeroPad,
+        arity: 4,
+        ..TreeConfig::default()
+    };
+    let leaves = leaves(PARALLEL_LEVEL_SIZE * 4 + 1);
+
+    let tree = LmdbMerkleTree::with_config(db_path.to_str().unwrap(), config.clone()).unwrap();
+    tree.add_leaves(leaves.clone()).unwrap();
+
+    // The heap tree gets the leaves in small batches, hashed sequentially
+    let mut heap = IncrementalMerkleTree::with_config(config).unwrap();
+    for chunk in leaves.chunks(1000) {
+        heap.add_leaves(chunk.to_vec()).unwrap();
+    }
+
+    assert_eq!(tree.root().unwrap(), heap.root());
+    for size in [1, 1000, PARALLEL_LEVEL_SIZE, leaves.len()] {
+        assert_eq!(tree.root_at(size).unwrap(), heap.root_at(size).unwrap());
+    }
+}
+
+#[test]
+fn test_missing_cache_rebuilt_on_open() {
+    use merkle_tree_api::storage::LmdbStorage;
+
+    let temp_dir = TempDir::new().unwrap();
+    let db_path = temp_dir.path().join("test.db");
+    let db_path_str = db_path.to_str().unwrap();
+    let leaves = leaves(9);
+
+    let root = {
+        let tree = LmdbMerkleTree::new(db_path_str).unwrap();
+        tree.add_leaves(leaves.clone()).unwrap();
+        tree.root().unwrap().unwrap()
+    };
+
+    // As after an upgrade from the per-node layout, whose levels are skipped
+    {
+        let storage = LmdbStorage::new(db_path_str).unwrap();
+        storage.clear_cache().unwrap();
+        assert!(storage.get_all_cache_levels().unwrap().is_empty());
+    }
+
+    let tree = LmdbMerkleTree::new(db_path_str).unwrap();
+    assert_eq!(tree.root().unwrap().unwrap(), root);
+    let proof = tree.get_proof(8).unwrap();
+    assert!(tree.verify_proof(&leaves[8], &proof, &root, 8));
+}
+
+#[test]
+fn test_snapshot_matches_tree() {
+    let (tree, _temp_dir) = create_temp_tree();
+    assert!(tree.snapshot().unwrap().root().is_none());
+
+    tree.add_leaf_preimage(b"alice".to_vec(), true).unwrap();
+    tree.add_leaves(vec![hex("leaf1"), hex("leaf2")]).unwrap();
+    let snapshot = tree.snapshot().unwrap();
+
+    tree.add_leaf(hex("leaf3")).unwrap();
+    assert_eq!(snapshot.len(), 3);
+    assert_eq!(snapshot.root(), Some(tree.root_at(3).unwrap().as_slice()));
+    assert_eq!(
+        snapshot.get_inclusion_proof_at(2, 3).unwrap(),
+        tree.get_inclusion_proof_at(2, 3).unwrap()
+    );
+
+    // Preimages are read from storage, shared with the tree
+    assert_eq!(snapshot.get_preimage(0).unwrap(), Some(b"alice".to_vec()));
+    assert_eq!(snapshot.get_preimage(1).unwrap(), None);
+
+    // Later updates change neither the snapshot nor the roots it reports
+    tree.update_leaf(1, hex("updated")).unwrap();
+    let latest = tree.snapshot().unwrap();
+    assert_eq!(snapshot.root(), Some(tree.root_at(3).unwrap().as_slice()));
+    for size in 1..=4 {
+        assert_eq!(latest.root_at(size).unwrap(), tree.root_at(size).unwrap());
+    }
+    assert_eq!(latest.root(), tree.root().unwrap().as_deref());
+    assert_ne!(latest.root(), snapshot.root());
+    assert!(snapshot.get_proof_at(1, 3).is_ok());
+    assert!(latest.get_proof_at(1, 3).is_err());
+
+    // Leaves and preimages replaced since are still found by the snapshot
+    tree.update_leaf(0, hex("other")).unwrap();
+    let alice = tree.config().hasher.hash(b"alice");
+    assert_eq!(snapshot.get_leaf_indices(&alice).unwrap(), vec![0]);
+    assert_eq!(snapshot.get_leaf_indices(b"leaf1").unwrap(), vec![1]);
+    assert_eq!(snapshot.get_preimage(0).unwrap(), Some(b"alice".to_vec()));
+    assert!(tree.get_leaf_indices(&alice).unwrap().is_empty());
+    assert_eq!(tree.get_preimage(0).unwrap(), None);
+    let current = tree.snapshot().unwrap();
+    assert!(current.get_leaf_indices(b"leaf1").unwrap().is_empty());
+    assert_eq!(current.get_preimage(0).unwrap(), None);
+}
+
+#[test]
+fn test_leaf_indices() {
+    let temp_dir = TempDir::new().unwrap();
+    let db_path = temp_dir.path().join("test.db");
+    let path = db_path.to_str().unwrap();
+
+    {
+        let tree = LmdbMerkleTree::new(path).unwrap();
+        tree.add_leaves(vec![hex("a"), hex("b"), hex("a")]).unwrap();
+        tree.update_leaf(1, hex("a")).unwrap();
+        tree.update_leaf(0, hex("c")).unwrap();
+    }
+
+    // The index is stored, so a reopened tree finds the same positions
+    let tree = LmdbMerkleTree::new(path).unwrap();
+    assert_eq!(tree.get_leaf_indices(b"a").unwrap(), vec![1, 2]);
+    assert_eq!(tree.get_leaf_indices(b"c").unwrap(), vec![0]);
+    assert!(tree.get_leaf_indices(b"b").unwrap().is_empty());
+
+    // Snapshots look leaves up in storage and keep to their own size
+    let snapshot = tree.snapshot().unwrap();
+    tree.add_leaf(hex("a")).unwrap();
+    assert_eq!(snapshot.get_leaf_indices(b"a").unwrap(), vec![1, 2]);
+    assert_eq!(
+        tree.snapshot().unwrap().get_leaf_indices(b"a").unwrap(),
+        vec![1, 2, 3]
+    );
+}
+
+#[test]
+fn test_storage_errors_are_surfaced() {
+    use lmdb::{Environment, EnvironmentFlags, Transaction, WriteFlags};
+    use merkle_tree_api::error::MerkleError;
+
+    let temp_dir = TempDir::new().unwrap();
+    let db_path = temp_dir.path().join("test.db");
+    let path = db_path.to_str().unwrap();
+
+    LmdbMerkleTree::new(path)
+        .unwrap()
+        .add_leaves(vec![hex("a"), hex("b"), hex("c")])
+        .unwrap();
+
+    let edit_metadata = |edit: &dyn Fn(&mut lmdb::RwTransaction, lmdb::Database)| {
+        let env = Environment::new()
+            .set_flags(EnvironmentFlags::NO_SUB_DIR)
+            .set_max_dbs(10)
+            .open(&db_path)
+            .unwrap();
+        let db = env.open_db(Some("metadata")).unwrap();
+        let mut txn = env.begin_rw_txn().unwrap();
+        edit(&mut txn, db);
+        txn.commit().unwrap();
+    };
+
+    // A lost root is reported rather than read as an empty tree
+    edit_metadata(&|txn, db| txn.del(db, &"cached_root", None).unwrap());
+    let tree = LmdbMerkleTree::new(path).unwrap();
+    assert_eq!(tree.num_leaves().unwrap(), 3);
+    assert!(matches!(tree.root(), Err(MerkleError::Corrupted(_))));
+    drop(tree);
+
+    // Undecodable metadata fails the open instead of counting as no leaves
+    edit_metadata(&|txn, db| {
+        txn.put(db, &"tree_metadata", b"garbage", WriteFlags::empty())
+            .unwrap()
+    });
+    let error = LmdbMerkleTree::new(path).err().unwrap();
+    assert_eq!(error.code(), "corrupted_data");
+    assert_eq!(error.http_status(), 500);
 }
@@ -1,12 +1,12 @@
-use merkle_tree_api::merkle_tree::{IncrementalMerkleTree, MerkleProof};
+mod common;
 
-fn hex(s: &str) -> Vec<u8> {
-    s.as_bytes().to_vec()
-}
+use common::{hex, leaves, tree_with_strategy};
+use merkle_tree_api::error::MerkleError;
+use merkle_tree_api::merkle_tree::{IncrementalMerkleTree, MerkleProof};
 
 #[test]
 fn test_empty_tree() {
-    let mut tree = IncrementalMerkleTree::new();
+    let tree = IncrementalMerkleTree::new();
     assert_eq!(tree.num_leaves(), 0);
     assert!(tree.root().is_none());
 }
@@ -44,10 +44,16 @@ fn test_proof_generation_valid_and_invalid_index() {
     let _ = tree.add_leaves(vec![hex("a"), hex("b"), hex("c"), hex("d")]);
 
     let valid_proof = tree.get_proof(0);
-    assert!(valid_proof.is_some());
+    assert!(valid_proof.is_ok());
 
     let invalid_proof = tree.get_proof(999);
-    assert!(invalid_proof.is_none());
+    assert!(matches!(
+        invalid_proof,
+        Err(MerkleError::IndexOutOfRange {
+            index: 999,
+            size: 4
+        })
+    ));
 }
 
 #[test]
@@ -55,16 +61,14 @@ fn test_max_levels_enforced() {
     let test_max_levels = 8; // Reduced from 11 (256 vs 2048 leaves)
     let test_max_leaves = 1 << test_max_levels;
     let mut tree = IncrementalMerkleTree::_new_with_max(test_max_leaves);
-    let leaves: Vec<Vec<u8>> = (0..test_max_leaves)
-        .map(|i| format!("leaf{}", i).into_bytes())
-        .collect();
+    let leaves = leaves(test_max_leaves);
 
     // Use batch operation instead of individual adds
     assert!(tree.add_leaves(leaves).is_ok());
 
     let root = tree.root();
     assert!(root.is_some());
-    assert!(tree.cached_hashes.len() <= test_max_levels + 1); // +1 because root is an extra level
+    assert!(tree.cached_levels().len() <= test_max_levels + 1); // +1 because root is an extra level
 }
 
 #[test]
@@ -96,11 +100,11 @@ fn test_proof_structure() {
     let _ = tree.add_leaves(vec![hex("a"), hex("b"), hex("c"), hex("d")]);
 
     let proof = tree.get_proof(2);
-    assert!(proof.is_some());
+    assert!(proof.is_ok());
     let proof = proof.unwrap();
 
-    assert!(proof.siblings.len() > 0);
-    assert!(proof.siblings.len() > 0);
+    assert!(!proof.siblings.is_empty());
+    assert!(!proof.siblings.is_empty());
 }
 
 #[test]
@@ -165,3 +169,277 @@ fn test_proof_verification_single_leaf() {
 
     assert!(tree.verify_proof(&leaf, &proof, &root, 0));
 }
+
+fn reference_root(leaves: &[Vec<u8>]) -> Vec<u8> {
+    use sha3::{Digest, Keccak256};
+
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|chunk| {
+                let right = chunk.get(1).unwrap_or(&chunk[0]);
+                let mut hasher = Keccak256::new();
+                hasher.update(&chunk[0]);
+                hasher.update(right);
+                hasher.finalize().to_vec()
+            })
+            .collect();
+    }
+    level[0].clone()
+}
+
+#[test]
+fn test_incremental_append_matches_full_rebuild() {
+    let mut tree = IncrementalMerkleTree::new();
+    let leaves = leaves(70);
+
+    for (i, leaf) in leaves.iter().enumerate() {
+        tree.add_leaf(leaf.clone()).unwrap();
+        assert_eq!(tree.root().unwrap(), reference_root(&leaves[..=i]));
+    }
+
+    let root = tree.root().unwrap();
+    for (i, leaf) in leaves.iter().enumerate() {
+        let proof = tree.get_proof(i).unwrap();
+        assert!(tree.verify_proof(leaf, &proof, &root, i));
+    }
+}
+
+#[test]
+fn test_incremental_batches_match_single_batch() {
+    let leaves = leaves(45);
+
+    let mut batched = IncrementalMerkleTree::new();
+    for chunk in [&leaves[..1], &leaves[1..4], &leaves[4..17], &leaves[17..]] {
+        batched.add_leaves(chunk.to_vec()).unwrap();
+    }
+
+    let mut single = IncrementalMerkleTree::new();
+    single.add_leaves(leaves.clone()).unwrap();
+
+    assert_eq!(batched.root(), single.root());
+    assert_eq!(batched.root().unwrap(), reference_root(&leaves));
+    for i in 0..leaves.len() {
+        assert_eq!(
+            batched.get_proof(i).unwrap().siblings,
+            single.get_proof(i).unwrap().siblings
+        );
+    }
+}
+
+#[test]
+fn test_parallel_build_matches_reference_root() {
+    use merkle_tree_api::config::PARALLEL_LEVEL_SIZE;
+
+    // Large enough that the first levels are hashed on the thread pool
+    let leaves = leaves(PARALLEL_LEVEL_SIZE * 2 + 3);
+
+    let mut single = IncrementalMerkleTree::new();
+    single.add_leaves(leaves.clone()).unwrap();
+    assert_eq!(single.root().unwrap(), reference_root(&leaves));
+
+    // Extending a small tree by a large batch takes the parallel path too
+    let mut extended = IncrementalMerkleTree::new();
+    extended.add_leaves(leaves[..5].to_vec()).unwrap();
+    extended.add_leaves(leaves[5..].to_vec()).unwrap();
+    assert_eq!(extended.root(), single.root());
+    assert_eq!(
+        extended.get_proof(4097).unwrap(),
+        single.get_proof(4097).unwrap()
+    );
+}
+
+#[test]
+fn test_compact_levels() {
+    use merkle_tree_api::config::{HashScheme, OddNodeStrategy, TreeConfig};
+    use merkle_tree_api::nodes::Level;
+
+    let leaves = leaves(5);
+
+    // Raw leaves are level 0 themselves and are not copied into the cache
+    let mut raw = IncrementalMerkleTree::new();
+    raw.add_leaves(leaves.clone()).unwrap();
+    assert!(raw.cached_levels()[0].is_empty());
+    assert_eq!(raw.cached_levels()[1].len(), 3);
+
+    let mut hashed = IncrementalMerkleTree::with_config(TreeConfig {
+        scheme: HashScheme::Rfc6962,
+        ..TreeConfig::default()
+    })
+    .unwrap();
+    hashed.add_leaves(leaves.clone()).unwrap();
+    assert_eq!(hashed.cached_levels()[0].len(), 5);
+
+    // A promoted node is read from the level below instead of stored again
+    let mut promote = IncrementalMerkleTree::with_config(TreeConfig {
+        odd_node: OddNodeStrategy::Promote,
+        ..TreeConfig::default()
+    })
+    .unwrap();
+    promote.add_leaves(leaves.clone()).unwrap();
+    let sizes: Vec<usize> = promote.cached_levels().iter().map(Level::len).collect();
+    assert_eq!(sizes, vec![0, 2, 1, 1]);
+    let root = promote.root().unwrap();
+    let proof = promote.get_proof(4).unwrap();
+    assert!(promote.verify_proof(&leaves[4], &proof, &root, 4));
+}
+
+#[test]
+fn test_update_leaf_matches_rebuild() {
+    use merkle_tree_api::config::OddNodeStrategy;
+
+    let mut leaves = leaves(13);
+
+    for odd_node in [
+        OddNodeStrategy::Duplicate,
+        OddNodeStrategy::Promote,
+        OddNodeStrategy::ZeroPad,
+    ] {
+        let mut tree = tree_with_strategy(odd_node, &leaves);
+        for index in [0, 5, 12] {
+            leaves[index] = format!("updated{}", index).into_bytes();
+            tree.update_leaf(index, leaves[index].clone()).unwrap();
+
+            let rebuilt = tree_with_strategy(odd_node, &leaves);
+            assert_eq!(tree.root(), rebuilt.root(), "{:?}", odd_node);
+        }
+    }
+}
+
+#[test]
+fn test_update_leaf_invalidates_old_proofs() {
+    let mut tree = IncrementalMerkleTree::new();
+    tree.add_leaves(vec![hex("a"), hex("b"), hex("c")]).unwrap();
+    let old_proof = tree.get_proof(2).unwrap();
+
+    tree.update_leaf(0, hex("z")).unwrap();
+    let root = tree.root().unwrap();
+    assert_eq!(root, reference_root(&[hex("z"), hex("b"), hex("c")]));
+
+    assert!(!tree.verify_proof(&hex("c"), &old_proof, &root, 2));
+    let new_proof = tree.get_proof(2).unwrap();
+    assert!(tree.verify_proof(&hex("c"), &new_proof, &root, 2));
+
+    assert!(tree.update_leaf(3, hex("d")).is_err());
+}
+
+#[test]
+fn test_root_at_every_size() {
+    use merkle_tree_api::config::{OddNodeStrategy, TreeConfig};
+    use merkle_tree_api::hasher::Keccak256Hasher;
+
+    let leaves = leaves(19);
+    let configs = [
+        TreeConfig::default(),
+        TreeConfig {
+            odd_node: OddNodeStrategy::Promote,
+            ..TreeConfig::default()
+        },
+        TreeConfig {
+            odd_node: OddNodeStrategy::ZeroPad,
+            ..TreeConfig::default()
+        },
+        TreeConfig::fixed_depth(Keccak256Hasher, 6),
+    ];
+
+    for config in configs {
+        let mut tree = IncrementalMerkleTree::with_config(config.clone()).unwrap();
+        let mut roots = vec![tree.root()];
+        for leaf in &leaves {
+            tree.add_leaf(leaf.clone()).unwrap();
+            roots.push(tree.root());
+        }
+
+        for (size, root) in roots.iter().enumerate() {
+            assert_eq!(&tree.root_at(size).ok(), root, "size {}", size);
+        }
+        assert!(tree.root_at(leaves.len() + 1).is_err());
+    }
+}
+
+#[test]
+fn test_proof_at_historical_sizes() {
+    use merkle_tree_api::config::{OddNodeStrategy, TreeConfig};
+    use merkle_tree_api::hasher::Keccak256Hasher;
+
+    let leaves = leaves(13);
+    let configs = [
+        TreeConfig::default(),
+        TreeConfig {
+            odd_node: OddNodeStrategy::Promote,
+            ..TreeConfig::default()
+        },
+        TreeConfig {
+            odd_node: OddNodeStrategy::ZeroPad,
+            ..TreeConfig::default()
+        },
+        TreeConfig::fixed_depth(Keccak256Hasher, 5),
+    ];
+
+    for config in configs {
+        let mut tree = IncrementalMerkleTree::with_config(config.clone()).unwrap();
+        tree.add_leaves(leaves.clone()).unwrap();
+
+        for tree_size in 1..=leaves.len() {
+            let mut old_tree = IncrementalMerkleTree::with_config(config.clone()).unwrap();
+            old_tree.add_leaves(leaves[..tree_size].to_vec()).unwrap();
+            let old_root = old_tree.root().unwrap();
+
+            for (index, leaf) in leaves[..tree_size].iter().enumerate() {
+                let proof = tree.get_proof_at(index, tree_size).unwrap();
+                assert_eq!(
+                    proof.siblings,
+                    old_tree.get_proof(index).unwrap().siblings,
+                    "index {} at size {}",
+                    index,
+                    tree_size
+                );
+                assert!(tree.verify_proof_at(leaf, &proof, &old_root, index, tree_size));
+            }
+            assert!(tree.get_proof_at(tree_size, tree_size).is_err());
+        }
+        assert!(tree.get_proof_at(0, leaves.len() + 1).is_err());
+    }
+}
+
+#[test]
+fn test_leaf_indices() {
+    let mut tree = IncrementalMerkleTree::new();
+    tree.add_leaves(vec![hex("a"), hex("b"), hex("a")]).unwrap();
+    tree.add_leaf(hex("a")).unwrap();
+
+    assert_eq!(tree.get_leaf_indices(b"a"), vec![0, 2, 3]);
+    assert_eq!(tree.get_leaf_indices(b"b"), vec![1]);
+    assert!(tree.get_leaf_indices(b"c").is_empty());
+
+    // Updates move the leaf between values, keeping the indices sorted
+    tree.update_leaf(2, hex("b")).unwrap();
+    tree.update_leaf(0, hex("c")).unwrap();
+    assert_eq!(tree.get_leaf_indices(b"a"), vec![3]);
+    assert_eq!(tree.get_leaf_indices(b"b"), vec![1, 2]);
+    assert_eq!(tree.get_leaf_indices(b"c"), vec![0]);
+
+    // A rejected batch leaves the index as it was
+    let mut full = IncrementalMerkleTree::_new_with_max(2);
+    full.add_leaf(hex("a")).unwrap();
+    assert!(full.add_leaves(vec![hex("a"), hex("a")]).is_err());
+    assert_eq!(full.get_leaf_indices(b"a"), vec![0]);
+
+    // Snapshots share the index and keep to their own leaves
+    let snapshot = tree.snapshot();
+    tree.add_leaf(hex("b")).unwrap();
+    assert_eq!(snapshot.get_leaf_indices(b"b").unwrap(), vec![1, 2]);
+    assert_eq!(
+        tree.snapshot().get_leaf_indices(b"b").unwrap(),
+        vec![1, 2, 4]
+    );
+
+    // A snapshot still finds the leaves it holds once they are updated
+    tree.update_leaf(1, hex("d")).unwrap();
+    tree.update_leaf(1, hex("b")).unwrap();
+    tree.update_leaf(1, hex("d")).unwrap();
+    assert_eq!(snapshot.get_leaf_indices(b"b").unwrap(), vec![1, 2]);
+    assert_eq!(tree.get_leaf_indices(b"b"), vec![2, 4]);
+    assert_eq!(tree.get_leaf_indices(b"d"), vec![1]);
+}
@@ -0,0 +1,172 @@
+mod common;
+
+use common::leaves;
+use merkle_tree_api::config::{HashScheme, TreeConfig};
+use merkle_tree_api::error::MerkleError;
+use merkle_tree_api::hasher::{Keccak256Hasher, MerkleHasher, Sha256Hasher};
+use merkle_tree_api::mmr::{MerkleMountainRange, mmr_size, peak_positions};
+use merkle_tree_api::verify::verify_mmr_proof;
+use tempfile::TempDir;
+
+#[test]
+fn test_mmr_positions() {
+    let sizes: Vec<usize> = (1..=8).map(mmr_size).collect();
+    assert_eq!(sizes, vec![1, 3, 4, 7, 8, 10, 11, 15]);
+
+    assert_eq!(peak_positions(11), vec![(3, 14), (1, 17), (0, 18)]);
+    assert!(peak_positions(0).is_empty());
+}
+
+#[test]
+fn test_mmr_root_matches_manual() {
+    let h = |a: &[u8], b: &[u8]| Keccak256Hasher.hash_pair(a, b);
+    let leaves = leaves(7);
+    let mut mmr = MerkleMountainRange::new();
+    assert!(mmr.root().is_none());
+
+    mmr.add_leaves(leaves[..3].to_vec()).unwrap();
+    let ab = h(&leaves[0], &leaves[1]);
+    assert_eq!(mmr.peaks(), &[ab.clone(), leaves[2].clone()]);
+    assert_eq!(mmr.root().unwrap(), h(&leaves[2], &ab));
+
+    mmr.add_leaves(leaves[3..].to_vec()).unwrap();
+    let abcd = h(&ab, &h(&leaves[2], &leaves[3]));
+    let ef = h(&leaves[4], &leaves[5]);
+    assert_eq!(mmr.size(), 11);
+    assert_eq!(mmr.peaks(), &[abcd.clone(), ef.clone(), leaves[6].clone()]);
+    assert_eq!(mmr.root().unwrap(), h(&h(&leaves[6], &ef), &abcd));
+
+    // Appending one at a time builds the same nodes
+    let mut single = MerkleMountainRange::new();
+    for leaf in leaves {
+        single.add_leaf(leaf).unwrap();
+    }
+    assert_eq!(single.root(), mmr.root());
+}
+
+#[test]
+fn test_mmr_proofs_verify() {
+    let config = TreeConfig {
+        scheme: HashScheme::Rfc6962,
+        ..TreeConfig::new(Sha256Hasher)
+    };
+    let leaves = leaves(33);
+    let mut mmr = MerkleMountainRange::with_config(config.clone()).unwrap();
+
+    for (count, leaf) in leaves.iter().enumerate() {
+        mmr.add_leaf(leaf.clone()).unwrap();
+        let root = mmr.root().unwrap();
+
+        for (index, leaf) in leaves[..=count].iter().enumerate() {
+            let proof = mmr.get_proof(index).unwrap();
+            assert!(verify_mmr_proof(&config, leaf, &proof, &root));
+            assert!(!verify_mmr_proof(&config, b"other", &proof, &root));
+        }
+    }
+
+    // Earlier roots stay provable, since their nodes are never rewritten
+    let old_root = mmr.root_at(12).unwrap();
+    let proof = mmr.get_proof_at(5, 12).unwrap();
+    assert!(mmr.verify_proof(&leaves[5], &proof, &old_root));
+    assert!(!mmr.verify_proof(&leaves[5], &proof, &mmr.root().unwrap()));
+
+    assert!(matches!(
+        mmr.get_proof(33),
+        Err(MerkleError::IndexOutOfRange {
+            index: 33,
+            size: 33
+        })
+    ));
+    assert!(matches!(
+        mmr.root_at(34),
+        Err(MerkleError::InvalidArgument(_))
+    ));
+}
+
+#[test]
+fn test_mmr_proof_rejects_tampering() {
+    let config = TreeConfig::default();
+    let leaves = leaves(11);
+    let mut mmr = MerkleMountainRange::new();
+    mmr.add_leaves(leaves.clone()).unwrap();
+    let root = mmr.root().unwrap();
+    let proof = mmr.get_proof(9).unwrap();
+    assert!(verify_mmr_proof(&config, &leaves[9], &proof, &root));
+
+    let mut other_index = proof.clone();
+    other_index.leaf_index = 8;
+    assert!(!verify_mmr_proof(&config, &leaves[9], &other_index, &root));
+
+    let mut other_size = proof.clone();
+    other_size.num_leaves = 12;
+    assert!(!verify_mmr_proof(&config, &leaves[9], &other_size, &root));
+
+    let mut reordered = proof.clone();
+    reordered.peaks.reverse();
+    assert!(!verify_mmr_proof(&config, &leaves[9], &reordered, &root));
+
+    let mut missing_peak = proof;
+    missing_peak.peaks.pop();
+    assert!(!verify_mmr_proof(&config, &leaves[9], &missing_peak, &root));
+}
+
+#[test]
+fn test_mmr_config_validation() {
+    assert!(matches!(
+        MerkleMountainRange::with_config(TreeConfig {
+            arity: 4,
+            ..TreeConfig::default()
+        }),
+        Err(MerkleError::InvalidArgument(_))
+    ));
+
+    let temp_dir = TempDir::new().unwrap();
+    let db_path = temp_dir.path().join("test.db");
+    assert!(matches!(
+        MerkleMountainRange::new_with_storage_and_config(
+            db_path.to_str().unwrap(),
+            TreeConfig::fixed_depth(Keccak256Hasher, 4)
+        ),
+        Err(MerkleError::InvalidArgument(_))
+    ));
+}
+
+#[test]
+fn test_mmr_lmdb_persistence() {
+    let temp_dir = TempDir::new().unwrap();
+    let db_path = temp_dir.path().join("test.db");
+    let path = db_path.to_str().unwrap();
+    let config = TreeConfig::new(Sha256Hasher);
+    let leaves = leaves(21);
+
+    let mut memory = MerkleMountainRange::with_config(config.clone()).unwrap();
+    memory.add_leaves(leaves.clone()).unwrap();
+
+    {
+        let mut mmr =
+            MerkleMountainRange::new_with_storage_and_config(path, config.clone()).unwrap();
+        mmr.add_leaves(leaves[..13].to_vec()).unwrap();
+    }
+
+    // Reopening keeps the config and picks up the stored peaks
+    let mut mmr = MerkleMountainRange::new_with_storage(path).unwrap();
+    assert_eq!(mmr.config().hasher.id(), "sha256");
+    assert_eq!(mmr.num_leaves(), 13);
+    assert_eq!(mmr.root(), Some(memory.root_at(13).unwrap()));
+
+    mmr.add_leaves(leaves[13..].to_vec()).unwrap();
+    assert_eq!(mmr.size(), memory.size());
+    assert_eq!(mmr.root(), memory.root());
+    for index in [0, 12, 13, 20] {
+        assert_eq!(
+            mmr.get_proof(index).unwrap(),
+            memory.get_proof(index).unwrap()
+        );
+    }
+    drop(mmr);
+
+    assert!(matches!(
+        MerkleMountainRange::new_with_storage_and_config(path, TreeConfig::default()),
+        Err(MerkleError::ConfigMismatch(_))
+    ));
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use common::{hex, leaves, tree_with_strategy};
+use merkle_tree_api::error::MerkleError;
+use merkle_tree_api::merkle_tree::IncrementalMerkleTree;
+
+#[test]
+fn test_multiproof_all_strategies() {
+    use merkle_tree_api::config::OddNodeStrategy;
+
+    let leaves = leaves(21);
+    let index_sets: [&[usize]; 5] = [&[0], &[20], &[3, 4], &[0, 1, 2, 3], &[1, 7, 8, 15, 19, 20]];
+
+    for odd_node in [
+        OddNodeStrategy::Duplicate,
+        OddNodeStrategy::Promote,
+        OddNodeStrategy::ZeroPad,
+    ] {
+        let tree = tree_with_strategy(odd_node, &leaves);
+        let root = tree.root().unwrap();
+
+        for indices in index_sets {
+            let proof = tree.get_multiproof(indices).unwrap();
+            let proven: Vec<Vec<u8>> = indices.iter().map(|&i| leaves[i].clone()).collect();
+            assert!(
+                tree.verify_multiproof(&proven, &proof, &root),
+                "{:?} {:?}",
+                odd_node,
+                indices
+            );
+
+            let mut wrong = proven.clone();
+            wrong[0] = hex("wrong");
+            assert!(!tree.verify_multiproof(&wrong, &proof, &root));
+        }
+    }
+}
+
+#[test]
+fn test_multiproof_deduplicates_siblings() {
+    let leaves = leaves(16);
+    let mut tree = IncrementalMerkleTree::new();
+    tree.add_leaves(leaves.clone()).unwrap();
+    let root = tree.root().unwrap();
+
+    // Leaves 0..4 form a full subtree, so only its two uncle nodes are needed
+    let proof = tree.get_multiproof(&[3, 1, 0, 2]).unwrap();
+    assert_eq!(proof.indices, vec![0, 1, 2, 3]);
+    assert_eq!(proof.siblings.len(), 2);
+    assert!(tree.verify_multiproof(&leaves[..4], &proof, &root));
+
+    // Fewer siblings than the separate proofs combined
+    let separate: usize = [0, 9]
+        .iter()
+        .map(|&i| tree.get_proof(i).unwrap().siblings.len())
+        .sum();
+    let proof = tree.get_multiproof(&[0, 9]).unwrap();
+    assert!(proof.siblings.len() < separate);
+
+    let mut extra = proof.clone();
+    extra.siblings.push(extra.siblings[0].clone());
+    let proven = vec![leaves[0].clone(), leaves[9].clone()];
+    assert!(tree.verify_multiproof(&proven, &proof, &root));
+    assert!(!tree.verify_multiproof(&proven, &extra, &root));
+    assert!(!tree.verify_multiproof(&proven[..1], &proof, &root));
+
+    assert!(matches!(
+        tree.get_multiproof(&[]),
+        Err(MerkleError::InvalidArgument(_))
+    ));
+    assert!(tree.get_multiproof(&[0, 16]).is_err());
+}
+
+#[test]
+fn test_multiproof_fixed_depth() {
+    use merkle_tree_api::config::TreeConfig;
+
+    let leaves = leaves(5);
+    let mut tree = IncrementalMerkleTree::with_config(TreeConfig::fixed_depth(
+        merkle_tree_api::hasher::Keccak256Hasher,
+        8,
+    ))
+    .unwrap();
+    tree.add_leaves(leaves.clone()).unwrap();
+    let root = tree.root().unwrap();
+
+    let proof = tree.get_multiproof(&[1, 4]).unwrap();
+    let proven = vec![leaves[1].clone(), leaves[4].clone()];
+    assert!(tree.verify_multiproof(&proven, &proof, &root));
+}
@@ -0,0 +1,47 @@
+mod common;
+
+use common::rfc6962_test_leaves;
+use merkle_tree_api::merkle_tree::IncrementalMerkleTree;
+use merkle_tree_api::preimage::{LeafEncoding, decode_preimage};
+
+#[test]
+fn test_add_leaf_preimages() {
+    let mut tree = IncrementalMerkleTree::new();
+    let mut hashed = IncrementalMerkleTree::new();
+
+    let json = decode_preimage(r#"{ "b": 2, "a": 1 }"#, LeafEncoding::Json).unwrap();
+    assert_eq!(json, br#"{"a":1,"b":2}"#);
+    let base64 = decode_preimage("aGVsbG8=", LeafEncoding::Base64).unwrap();
+    assert_eq!(base64, b"hello");
+    assert!(decode_preimage("not base64!", LeafEncoding::Base64).is_err());
+
+    let leaf = tree.add_leaf_preimage(&json).unwrap();
+    let leaves = tree
+        .add_leaf_preimages(&[base64, b"world".to_vec()])
+        .unwrap();
+    assert_eq!(leaf, tree.config().hasher.hash(&json));
+
+    hashed.add_leaf(leaf).unwrap();
+    hashed.add_leaves(leaves).unwrap();
+    assert_eq!(tree.root(), hashed.root());
+}
+
+#[test]
+fn test_rfc6962_preimages_are_hashed_once() {
+    use merkle_tree_api::config::{HashScheme, TreeConfig};
+    use merkle_tree_api::hasher::Sha256Hasher;
+
+    let config = TreeConfig {
+        scheme: HashScheme::Rfc6962,
+        ..TreeConfig::new(Sha256Hasher)
+    };
+    let mut tree = IncrementalMerkleTree::with_config(config).unwrap();
+
+    // The reference leaves are the data itself, hashed as H(0x00 || data)
+    let leaves = tree.add_leaf_preimages(&rfc6962_test_leaves()).unwrap();
+    assert_eq!(leaves, rfc6962_test_leaves());
+    assert_eq!(
+        ::hex::encode(tree.root().unwrap()),
+        "5dc9da79a70659a9ad559cb701ded9a2ab9d823aad2f4960cfe370eff4604328"
+    );
+}
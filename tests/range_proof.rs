@@ -0,0 +1,87 @@
+mod common;
+
+use common::{hex, leaves, tree_with_strategy};
+use merkle_tree_api::error::MerkleError;
+use merkle_tree_api::merkle_tree::IncrementalMerkleTree;
+
+#[test]
+fn test_range_proof_all_strategies() {
+    use merkle_tree_api::config::OddNodeStrategy;
+
+    let leaves = leaves(21);
+    let ranges = [(0, 1), (20, 21), (3, 5), (0, 8), (5, 19), (0, 21)];
+
+    for odd_node in [
+        OddNodeStrategy::Duplicate,
+        OddNodeStrategy::Promote,
+        OddNodeStrategy::ZeroPad,
+    ] {
+        let tree = tree_with_strategy(odd_node, &leaves);
+        let root = tree.root().unwrap();
+
+        for (start, end) in ranges {
+            let proof = tree.get_range_proof(start, end).unwrap();
+            assert!(
+                tree.verify_range_proof(&leaves[start..end], &proof, &root),
+                "{:?} {}..{}",
+                odd_node,
+                start,
+                end
+            );
+            let multiproof = tree
+                .get_multiproof(&(start..end).collect::<Vec<_>>())
+                .unwrap();
+            assert_eq!(proof.siblings, multiproof.siblings);
+
+            let mut wrong = leaves[start..end].to_vec();
+            wrong[0] = hex("wrong");
+            assert!(!tree.verify_range_proof(&wrong, &proof, &root));
+        }
+
+        // Against an earlier root
+        let old_root = tree.root_at(13).unwrap();
+        let proof = tree.get_range_proof_at(9, 13, 13).unwrap();
+        assert!(tree.verify_range_proof(&leaves[9..13], &proof, &old_root));
+    }
+}
+
+#[test]
+fn test_range_proof_positions() {
+    let leaves = leaves(16);
+    let mut tree = IncrementalMerkleTree::new();
+    tree.add_leaves(leaves.clone()).unwrap();
+    let root = tree.root().unwrap();
+
+    // Leaves 4..12 need only the two subtrees beside them
+    let proof = tree.get_range_proof(4, 12).unwrap();
+    assert_eq!(proof.siblings.len(), 2);
+    assert!(tree.verify_range_proof(&leaves[4..12], &proof, &root));
+
+    // The same leaves do not verify at other positions or with a gap
+    let mut shifted = proof.clone();
+    shifted.start = 3;
+    shifted.end = 11;
+    assert!(!tree.verify_range_proof(&leaves[4..12], &shifted, &root));
+    assert!(!tree.verify_range_proof(&leaves[4..11], &proof, &root));
+    let mut gapped = leaves[4..12].to_vec();
+    gapped.remove(3);
+    gapped.push(leaves[12].clone());
+    assert!(!tree.verify_range_proof(&gapped, &proof, &root));
+
+    let mut extra = proof.clone();
+    extra.siblings.push(extra.siblings[0].clone());
+    assert!(!tree.verify_range_proof(&leaves[4..12], &extra, &root));
+
+    assert!(matches!(
+        tree.get_range_proof(5, 5),
+        Err(MerkleError::InvalidArgument(_))
+    ));
+    assert!(matches!(
+        tree.get_range_proof(10, 17),
+        Err(MerkleError::IndexOutOfRange {
+            index: 16,
+            size: 16
+        })
+    ));
+    assert!(tree.get_range_proof_at(0, 4, 17).is_err());
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use common::{hex, leaves};
+use merkle_tree_api::merkle_tree::IncrementalMerkleTree;
+
+#[test]
+fn test_snapshot_unchanged_by_writes() {
+    use merkle_tree_api::chunked::CHUNK_SIZE;
+
+    // Spans several chunks, so writes both share and copy them
+    let leaves = leaves(CHUNK_SIZE * 2 + 5);
+    let mut tree = IncrementalMerkleTree::new();
+    tree.add_leaves(leaves.clone()).unwrap();
+    let mut reference = IncrementalMerkleTree::new();
+    reference.add_leaves(leaves).unwrap();
+
+    let snapshot = tree.snapshot();
+    tree.update_leaf(3, hex("changed")).unwrap();
+    tree.update_leaf(CHUNK_SIZE + 1, hex("changed")).unwrap();
+    tree.add_leaves(vec![hex("more"); CHUNK_SIZE]).unwrap();
+
+    assert_eq!(snapshot.len(), reference.num_leaves());
+    assert_eq!(snapshot.root(), reference.root().as_deref());
+    for index in [0, 3, CHUNK_SIZE + 1, CHUNK_SIZE * 2 + 4] {
+        assert_eq!(
+            snapshot.get_proof(index).unwrap(),
+            reference.get_proof(index).unwrap()
+        );
+    }
+    assert!(snapshot.get_proof(CHUNK_SIZE * 2 + 5).is_err());
+
+    let latest = tree.snapshot();
+    assert_eq!(latest.len(), tree.num_leaves());
+    assert_eq!(latest.root(), tree.root().as_deref());
+    assert_eq!(latest.get_proof(3).unwrap(), tree.get_proof(3).unwrap());
+    assert_ne!(latest.root(), snapshot.root());
+}
+
+#[test]
+fn test_snapshot_from_parts() {
+    use merkle_tree_api::chunked::ChunkedVec;
+    use merkle_tree_api::nodes::LeafUpdate;
+    use merkle_tree_api::snapshot::TreeSnapshot;
+
+    let mut tree = IncrementalMerkleTree::new();
+    tree.add_leaves(vec![hex("a"), hex("b"), hex("c")]).unwrap();
+    tree.update_leaf(0, hex("updated")).unwrap();
+    tree.add_leaf(hex("d")).unwrap();
+
+    // Backends outside the crate build their snapshots from the same parts
+    let config = tree.config().clone();
+    let updates = ChunkedVec::from(vec![LeafUpdate {
+        index: 0,
+        size: 3,
+        previous: hex("a"),
+    }]);
+    let snapshot = TreeSnapshot::new(
+        config.clone(),
+        vec![hex("updated"), hex("b"), hex("c"), hex("d")].into(),
+        tree.cached_levels().to_vec(),
+        updates,
+    )
+    .unwrap();
+
+    assert_eq!(snapshot.root(), tree.root().as_deref());
+    for size in 1..=4 {
+        assert_eq!(snapshot.root_at(size).unwrap(), tree.root_at(size).unwrap());
+    }
+    assert_eq!(snapshot.get_proof(2).unwrap(), tree.get_proof(2).unwrap());
+}
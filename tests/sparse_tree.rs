@@ -0,0 +1,292 @@
+use merkle_tree_api::hasher::{Keccak256Hasher, MerkleHasher, Sha256Hasher};
+use merkle_tree_api::sparse_tree::{SparseKey, SparseMerkleTree};
+use merkle_tree_api::verify::verify_sparse_proof;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+fn key(byte: u8) -> SparseKey {
+    let mut key = [0u8; 32];
+    key[0] = byte;
+    key[31] = byte;
+    key
+}
+
+#[test]
+fn test_insert_get_remove() {
+    let mut tree = SparseMerkleTree::new();
+
+    tree.insert(key(1), b"one".to_vec()).unwrap();
+    tree.insert(key(2), b"two".to_vec()).unwrap();
+    assert_eq!(tree.get(&key(1)).unwrap(), Some(b"one".to_vec()));
+    assert_eq!(tree.get(&key(3)).unwrap(), None);
+
+    tree.insert(key(1), b"uno".to_vec()).unwrap();
+    assert_eq!(tree.get(&key(1)).unwrap(), Some(b"uno".to_vec()));
+
+    assert_eq!(tree.remove(key(1)).unwrap(), Some(b"uno".to_vec()));
+    assert_eq!(tree.remove(key(1)).unwrap(), None);
+    assert_eq!(tree.get(&key(1)).unwrap(), None);
+}
+
+#[test]
+fn test_root_independent_of_insertion_order() {
+    let empty_root = SparseMerkleTree::new().root().unwrap();
+
+    let mut first = SparseMerkleTree::new();
+    first.insert(key(1), b"one".to_vec()).unwrap();
+    first.insert(key(2), b"two".to_vec()).unwrap();
+
+    let mut second = SparseMerkleTree::new();
+    second.insert(key(2), b"two".to_vec()).unwrap();
+    second.insert(key(1), b"one".to_vec()).unwrap();
+
+    assert_ne!(first.root().unwrap(), empty_root);
+    assert_eq!(first.root().unwrap(), second.root().unwrap());
+
+    // Removing every key returns the tree to the empty root
+    first.remove(key(1)).unwrap();
+    first.remove(key(2)).unwrap();
+    assert_eq!(first.root().unwrap(), empty_root);
+}
+
+#[test]
+fn test_membership_proof() {
+    let mut tree = SparseMerkleTree::new();
+    for byte in 1..=8 {
+        tree.insert(key(byte), vec![byte]).unwrap();
+    }
+    let root = tree.root().unwrap();
+
+    let proof = tree.get_proof(key(5)).unwrap();
+    assert_eq!(proof.value, Some(hex::encode([5u8])));
+    assert!(tree.verify_proof(&proof, &root));
+
+    // Claiming a different value for the key must fail
+    let mut forged = proof.clone();
+    forged.value = Some(hex::encode([6u8]));
+    assert!(!tree.verify_proof(&forged, &root));
+}
+
+#[test]
+fn test_non_membership_proof() {
+    let mut tree = SparseMerkleTree::new();
+    for byte in 1..=8 {
+        tree.insert(key(byte), vec![byte]).unwrap();
+    }
+    let root = tree.root().unwrap();
+
+    let proof = tree.get_proof(key(42)).unwrap();
+    assert_eq!(proof.value, None);
+    assert!(tree.verify_proof(&proof, &root));
+
+    // A non-membership proof for a present key must fail
+    let mut forged = tree.get_proof(key(3)).unwrap();
+    forged.value = None;
+    assert!(!tree.verify_proof(&forged, &root));
+
+    // Empty tree: every key is absent and the proof has no siblings
+    let empty = SparseMerkleTree::new();
+    let proof = empty.get_proof(key(1)).unwrap();
+    assert!(proof.siblings.is_empty());
+    assert!(empty.verify_proof(&proof, &empty.root().unwrap()));
+}
+
+#[test]
+fn test_leaf_and_branch_hashes_are_domain_separated() {
+    let hasher = Keccak256Hasher;
+    let leaf =
+        |key: &SparseKey, value: &[u8]| hasher.hash_parts(&[&[0x00], key, &hasher.hash(value)]);
+
+    // A single key is its own subtree, so the root is its leaf hash
+    let mut tree = SparseMerkleTree::new();
+    tree.insert(key(0x01), b"one".to_vec()).unwrap();
+    assert_eq!(tree.root().unwrap(), leaf(&key(0x01), b"one"));
+
+    // The keys differ in their first bit, so the root is their branch
+    tree.insert(key(0x80), b"two".to_vec()).unwrap();
+    let branch = hasher.hash_parts(&[
+        &[0x01],
+        &leaf(&key(0x01), b"one"),
+        &leaf(&key(0x80), b"two"),
+    ]);
+    assert_eq!(tree.root().unwrap(), branch);
+
+    assert_eq!(SparseMerkleTree::new().root().unwrap(), vec![0u8; 32]);
+}
+
+#[test]
+fn test_non_membership_proof_ends_at_other_leaf() {
+    let mut tree = SparseMerkleTree::new();
+    tree.insert(key(1), b"one".to_vec()).unwrap();
+    let root = tree.root().unwrap();
+
+    // The path of key 2 ends at the root, which holds only key 1
+    let proof = tree.get_proof(key(2)).unwrap();
+    assert_eq!(proof.depth, 0);
+    assert_eq!(proof.value, None);
+    assert_eq!(proof.other_leaf.as_ref().unwrap().key, hex::encode(key(1)));
+    assert!(verify_sparse_proof(&Keccak256Hasher, &proof, &root));
+
+    // The other leaf cannot be the key being proven absent
+    let mut forged = proof.clone();
+    forged.key = hex::encode(key(1));
+    assert!(!verify_sparse_proof(&Keccak256Hasher, &forged, &root));
+
+    // Nor a key outside the subtree where the path ends
+    tree.insert(key(0x80), b"two".to_vec()).unwrap();
+    let root = tree.root().unwrap();
+    let proof = tree.get_proof(key(2)).unwrap();
+    assert_eq!(proof.depth, 1);
+    assert!(verify_sparse_proof(&Keccak256Hasher, &proof, &root));
+    let mut forged = proof;
+    forged.key = hex::encode(key(0x81));
+    assert!(!verify_sparse_proof(&Keccak256Hasher, &forged, &root));
+}
+
+#[test]
+fn test_paths_grow_with_the_number_of_keys() {
+    let hasher = Keccak256Hasher;
+    let keys: Vec<SparseKey> = (0u32..1000)
+        .map(|i| hasher.hash(&i.to_be_bytes()).try_into().unwrap())
+        .collect();
+
+    let mut tree = SparseMerkleTree::new();
+    for key in &keys {
+        tree.insert(*key, key.to_vec()).unwrap();
+    }
+    let root = tree.root().unwrap();
+
+    // Spread keys are about log2(1000) levels deep, not 256
+    for key in &keys {
+        let proof = tree.get_proof(*key).unwrap();
+        assert!(proof.depth < 32);
+        assert!(verify_sparse_proof(&hasher, &proof, &root));
+    }
+}
+
+#[test]
+fn test_remove_moves_the_remaining_leaf_up() {
+    let low = [0u8; 32];
+    let mut high = [0u8; 32];
+    high[31] = 1;
+
+    let mut single = SparseMerkleTree::new();
+    single.insert(low, b"low".to_vec()).unwrap();
+
+    // Keys differing only in their last bit split at the bottom of the tree
+    let mut tree = SparseMerkleTree::new();
+    tree.insert(low, b"low".to_vec()).unwrap();
+    tree.insert(high, b"high".to_vec()).unwrap();
+    let proof = tree.get_proof(high).unwrap();
+    assert_eq!(proof.depth, 256);
+    assert_eq!(proof.siblings.len(), 1);
+    assert!(tree.verify_proof(&proof, &tree.root().unwrap()));
+
+    tree.remove(high).unwrap();
+    assert_eq!(tree.root().unwrap(), single.root().unwrap());
+    assert_eq!(tree.get_proof(low).unwrap().depth, 0);
+}
+
+#[test]
+fn test_removals_match_a_rebuilt_tree() {
+    let hasher = Keccak256Hasher;
+    let keys: Vec<SparseKey> = (0u32..200)
+        .map(|i| {
+            // Some keys share long prefixes, the rest are spread out
+            let mut key: SparseKey = hasher.hash(&(i / 4).to_be_bytes()).try_into().unwrap();
+            key[2] ^= (i % 4) as u8;
+            key
+        })
+        .collect();
+
+    let mut tree = SparseMerkleTree::new();
+    for key in &keys {
+        tree.insert(*key, key.to_vec()).unwrap();
+    }
+    let mut rebuilt = SparseMerkleTree::new();
+    for (i, key) in keys.iter().enumerate() {
+        if i % 3 == 0 {
+            tree.remove(*key).unwrap();
+        } else {
+            rebuilt.insert(*key, key.to_vec()).unwrap();
+        }
+    }
+    assert_eq!(tree.root().unwrap(), rebuilt.root().unwrap());
+
+    let root = tree.root().unwrap();
+    for key in &keys {
+        assert!(tree.verify_proof(&tree.get_proof(*key).unwrap(), &root));
+    }
+}
+
+#[test]
+fn test_tampered_proof_fails() {
+    let mut tree = SparseMerkleTree::new();
+    tree.insert(key(1), b"one".to_vec()).unwrap();
+    tree.insert(key(2), b"two".to_vec()).unwrap();
+    let root = tree.root().unwrap();
+
+    let proof = tree.get_proof(key(1)).unwrap();
+    assert!(!proof.siblings.is_empty());
+
+    let mut tampered = proof.clone();
+    tampered.siblings[0] = hex::encode([0xffu8; 32]);
+    assert!(!tree.verify_proof(&tampered, &root));
+
+    let mut truncated = proof.clone();
+    truncated.siblings.pop();
+    assert!(!tree.verify_proof(&truncated, &root));
+
+    let mut other_key = proof;
+    other_key.key = hex::encode(key(2));
+    assert!(!tree.verify_proof(&other_key, &root));
+}
+
+#[test]
+fn test_lmdb_sparse_tree_persisted_across_instances() {
+    let temp_dir = TempDir::new().unwrap();
+    let db_path = temp_dir.path().join("sparse.db");
+    let db_path = db_path.to_str().unwrap();
+
+    let mut memory = SparseMerkleTree::with_hasher(Arc::new(Sha256Hasher));
+    let root = {
+        let mut tree =
+            SparseMerkleTree::new_with_storage_and_hasher(db_path, Arc::new(Sha256Hasher)).unwrap();
+        for byte in 1..=4 {
+            tree.insert(key(byte), vec![byte]).unwrap();
+            memory.insert(key(byte), vec![byte]).unwrap();
+        }
+        tree.remove(key(2)).unwrap();
+        memory.remove(key(2)).unwrap();
+        tree.root().unwrap()
+    };
+    assert_eq!(root, memory.root().unwrap());
+
+    // Reopening adopts the stored hasher
+    let tree = SparseMerkleTree::new_with_storage(db_path).unwrap();
+    assert_eq!(tree.root().unwrap(), root);
+    assert_eq!(tree.get(&key(3)).unwrap(), Some(vec![3]));
+    assert_eq!(tree.get(&key(2)).unwrap(), None);
+
+    let proof = tree.get_proof(key(2)).unwrap();
+    assert!(tree.verify_proof(&proof, &root));
+    let proof = tree.get_proof(key(3)).unwrap();
+    assert!(verify_sparse_proof(&Sha256Hasher, &proof, &root));
+}
+
+#[test]
+fn test_lmdb_sparse_tree_rejects_other_hasher() {
+    let temp_dir = TempDir::new().unwrap();
+    let db_path = temp_dir.path().join("sparse.db");
+    let db_path = db_path.to_str().unwrap();
+
+    {
+        let mut tree =
+            SparseMerkleTree::new_with_storage_and_hasher(db_path, Arc::new(Sha256Hasher)).unwrap();
+        tree.insert(key(1), b"one".to_vec()).unwrap();
+    }
+
+    assert!(
+        SparseMerkleTree::new_with_storage_and_hasher(db_path, Arc::new(Keccak256Hasher)).is_err()
+    );
+}
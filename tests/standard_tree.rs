@@ -0,0 +1,129 @@
+use merkle_tree_api::config::{HashScheme, OddNodeStrategy, TreeConfig};
+use merkle_tree_api::merkle_tree::IncrementalMerkleTree;
+use merkle_tree_api::standard_tree::StandardMerkleTree;
+use merkle_tree_api::verify::{verify_proof, verify_sorted_proof};
+
+// abi.encode(address, uint256)
+fn encode_address_amount(address: u8, amount: u128) -> Vec<u8> {
+    let mut data = vec![0u8; 64];
+    data[12..32].fill(address);
+    data[48..].copy_from_slice(&amount.to_be_bytes());
+    data
+}
+
+fn leaves(count: usize) -> Vec<Vec<u8>> {
+    (0..count)
+        .map(|i| encode_address_amount(i as u8 + 1, 1000 * (i as u128 + 1)))
+        .collect()
+}
+
+#[test]
+fn test_matches_openzeppelin_standard_tree() {
+    // Example from the @openzeppelin/merkle-tree README
+    let values = vec![
+        encode_address_amount(0x11, 5_000_000_000_000_000_000),
+        encode_address_amount(0x22, 2_500_000_000_000_000_000),
+    ];
+    let tree = StandardMerkleTree::of(&values).unwrap();
+    assert_eq!(
+        hex::encode(tree.root()),
+        "d4dee0beab2d53f2cc83e567171bd2820e49898130a22622b10ead383e90bd77"
+    );
+
+    for (index, value) in values.iter().enumerate() {
+        let proof = tree.get_proof(index).unwrap();
+        assert!(tree.verify(value, &proof));
+    }
+
+    assert!(StandardMerkleTree::of(&[]).is_err());
+}
+
+#[test]
+fn test_openzeppelin_preimages_are_hashed_once() {
+    let config = TreeConfig {
+        scheme: HashScheme::OpenZeppelin,
+        ..TreeConfig::default()
+    };
+    let mut tree = IncrementalMerkleTree::with_config(config).unwrap();
+
+    // Leaves are keccak256(keccak256(abi.encode(values))), so the README
+    // example gives the same root as @openzeppelin/merkle-tree
+    tree.add_leaf_preimages(&[
+        encode_address_amount(0x11, 5_000_000_000_000_000_000),
+        encode_address_amount(0x22, 2_500_000_000_000_000_000),
+    ])
+    .unwrap();
+    assert_eq!(
+        hex::encode(tree.root().unwrap()),
+        "d4dee0beab2d53f2cc83e567171bd2820e49898130a22622b10ead383e90bd77"
+    );
+}
+
+#[test]
+fn test_standard_tree_layout() {
+    let leaves = leaves(5);
+    let tree = StandardMerkleTree::of_unsorted(&leaves).unwrap();
+    let config = tree.config().clone();
+    let h: Vec<Vec<u8>> = leaves.iter().map(|leaf| config.hash_leaf(leaf)).collect();
+
+    // The array layout puts the first leaves on the lowest level
+    let h01 = config.hash_node(&h[0], &h[1]);
+    let expected = config.hash_node(
+        &config.hash_node(&h01, &h[4]),
+        &config.hash_node(&h[2], &h[3]),
+    );
+    assert_eq!(tree.root(), expected);
+
+    // Proofs verify without an index, whatever the leaf order
+    let sorted = StandardMerkleTree::of(&leaves).unwrap();
+    let mut reversed = leaves.clone();
+    reversed.reverse();
+    assert_eq!(
+        sorted.root(),
+        StandardMerkleTree::of(&reversed).unwrap().root()
+    );
+
+    for (index, leaf) in leaves.iter().enumerate() {
+        let proof = sorted.get_proof(index).unwrap();
+        assert_eq!(sorted.leaf_hash(index), Some(h[index].clone()));
+        assert!(verify_sorted_proof(&config, leaf, &proof, &sorted.root()));
+        assert!(!sorted.verify(&leaves[(index + 1) % 5], &proof));
+    }
+}
+
+#[test]
+fn test_incremental_tree_with_openzeppelin_scheme() {
+    let config = TreeConfig {
+        scheme: HashScheme::OpenZeppelin,
+        ..TreeConfig::default()
+    };
+
+    // A power-of-two tree has the same shape in both layouts
+    let leaves = leaves(8);
+    let mut tree = IncrementalMerkleTree::with_config(config.clone()).unwrap();
+    tree.add_leaves(leaves.clone()).unwrap();
+    let root = tree.root().unwrap();
+    assert_eq!(
+        root,
+        StandardMerkleTree::of_unsorted(&leaves).unwrap().root()
+    );
+
+    for strategy in [OddNodeStrategy::Duplicate, OddNodeStrategy::Promote] {
+        let config = TreeConfig {
+            odd_node: strategy,
+            ..config.clone()
+        };
+        let leaves = leaves[..7].to_vec();
+        let mut tree = IncrementalMerkleTree::with_config(config.clone()).unwrap();
+        tree.add_leaves(leaves.clone()).unwrap();
+        let root = tree.root().unwrap();
+
+        for (index, leaf) in leaves.iter().enumerate() {
+            let proof = tree.get_proof(index).unwrap();
+            assert!(verify_sorted_proof(&config, leaf, &proof, &root));
+            // The index does not matter to the sorted-pair hash, but the
+            // number of siblings on the path does
+            assert!(verify_proof(&config, leaf, &proof, &root, index, 7));
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use merkle_tree_api::nodes::Level;
 use merkle_tree_api::storage::{LmdbStorage, TreeMetadata};
 use tempfile::TempDir;
 
@@ -66,8 +67,8 @@ fn test_append_leaves() {
 fn test_cache_operations() {
     let (storage, _temp_dir) = create_temp_storage();
 
-    let level0_hashes = vec![b"hash1".to_vec(), b"hash2".to_vec()];
-    let level1_hashes = vec![b"parent_hash".to_vec()];
+    let level0_hashes = vec![[1u8; 32], [2u8; 32]];
+    let level1_hashes = vec![[3u8; 32]];
 
     storage.store_cache_level(0, &level0_hashes).unwrap();
     storage.store_cache_level(1, &level1_hashes).unwrap();
@@ -86,10 +87,7 @@ fn test_cache_operations() {
 fn test_cache_batch_operations() {
     let (storage, _temp_dir) = create_temp_storage();
 
-    let cache_levels = vec![
-        vec![b"leaf1".to_vec(), b"leaf2".to_vec()],
-        vec![b"parent".to_vec()],
-    ];
+    let cache_levels: Vec<Level> = vec![vec![[1u8; 32], [2u8; 32]].into(), vec![[3u8; 32]].into()];
 
     storage.store_cache_batch(&cache_levels).unwrap();
 
@@ -103,7 +101,7 @@ fn test_cache_batch_operations() {
 fn test_clear_cache() {
     let (storage, _temp_dir) = create_temp_storage();
 
-    let cache_levels = vec![vec![b"leaf1".to_vec()], vec![b"parent".to_vec()]];
+    let cache_levels: Vec<Level> = vec![vec![[1u8; 32]].into(), vec![[2u8; 32]].into()];
 
     storage.store_cache_batch(&cache_levels).unwrap();
     let before_clear = storage.get_all_cache_levels().unwrap();
@@ -121,6 +119,7 @@ fn test_metadata_operations() {
     let metadata = TreeMetadata {
         num_leaves: 10,
         max_leaves: 1024,
+        ..Default::default()
     };
 
     storage.store_metadata(&metadata).unwrap();
@@ -149,12 +148,13 @@ fn test_clear_all() {
     let leaves = vec![b"leaf1".to_vec()];
     storage.store_leaves_batch(&leaves).unwrap();
 
-    let cache_levels = vec![vec![b"hash1".to_vec()]];
+    let cache_levels: Vec<Level> = vec![vec![[1u8; 32]].into()];
     storage.store_cache_batch(&cache_levels).unwrap();
 
     let metadata = TreeMetadata {
         num_leaves: 1,
         max_leaves: 1024,
+        ..Default::default()
     };
     storage.store_metadata(&metadata).unwrap();
 
@@ -191,3 +191,74 @@ fn test_sync() {
     let retrieved = storage.get_leaf(0).unwrap();
     assert_eq!(retrieved, Some(leaf));
 }
+
+#[test]
+fn test_cache_levels_ordered_past_ten() {
+    let (storage, _temp_dir) = create_temp_storage();
+
+    let cache_levels: Vec<Level> = (0..12)
+        .map(|level| vec![[level as u8; 32]].into())
+        .collect();
+    storage.store_cache_batch(&cache_levels).unwrap();
+
+    let all_levels = storage.get_all_cache_levels().unwrap();
+    assert_eq!(all_levels, cache_levels);
+}
+
+#[test]
+fn test_store_changed_cache_nodes() {
+    let (storage, _temp_dir) = create_temp_storage();
+
+    let mut cache_levels: Vec<Level> = vec![
+        (0..5u8).map(|i| [i; 32]).collect(),
+        (10..13u8).map(|i| [i; 32]).collect(),
+        vec![[20u8; 32], [21u8; 32]].into(),
+        vec![[30u8; 32]].into(),
+    ];
+    storage.store_cache_batch(&cache_levels).unwrap();
+
+    // Only the path above node 3 is written; the other nodes stay as stored
+    cache_levels[0][3] = [4u8; 32];
+    cache_levels[1][1] = [14u8; 32];
+    cache_levels[2][0] = [24u8; 32];
+    cache_levels[3][0] = [34u8; 32];
+    let mut batch = storage.begin_batch().unwrap();
+    batch.store_cache_nodes(&cache_levels, 3..4, 2).unwrap();
+    batch.commit().unwrap();
+    assert_eq!(storage.get_all_cache_levels().unwrap(), cache_levels);
+
+    // Nodes past the end of a level, and levels no longer there, are dropped
+    cache_levels.truncate(2);
+    cache_levels[1].truncate(1);
+    let mut batch = storage.begin_batch().unwrap();
+    batch.store_cache_nodes(&cache_levels, 5..5, 2).unwrap();
+    batch.commit().unwrap();
+    assert_eq!(storage.get_all_cache_levels().unwrap(), cache_levels);
+    assert_eq!(storage.get_cache_level(2).unwrap(), None);
+}
+
+#[test]
+fn test_leaf_index() {
+    let (storage, _temp_dir) = create_temp_storage();
+
+    // Longer than an LMDB key
+    let long_leaf = vec![7u8; 1000];
+    storage
+        .store_leaves_batch(&[b"a".to_vec(), long_leaf.clone()])
+        .unwrap();
+    storage.append_leaves(2, &[b"a".to_vec()]).unwrap();
+    assert_eq!(storage.get_leaf_indices(b"a", 3).unwrap(), vec![0, 2]);
+    assert_eq!(storage.get_leaf_indices(&long_leaf, 3).unwrap(), vec![1]);
+
+    storage.store_leaf(0, b"b").unwrap();
+    assert_eq!(storage.get_leaf_indices(b"a", 3).unwrap(), vec![2]);
+    assert_eq!(storage.get_leaf_indices(b"b", 3).unwrap(), vec![0]);
+
+    storage.rebuild_leaf_index().unwrap();
+    assert_eq!(storage.get_leaf_indices(b"a", 3).unwrap(), vec![2]);
+    assert!(storage.get_leaf_indices(b"c", 3).unwrap().is_empty());
+
+    // Leaves past the size of the tree are not part of it
+    assert!(storage.get_leaf_indices(b"a", 2).unwrap().is_empty());
+    assert_eq!(storage.get_leaf_indices(&long_leaf, 2).unwrap(), vec![1]);
+}
@@ -0,0 +1,107 @@
+mod common;
+
+use common::hex;
+use merkle_tree_api::config::{OddNodeStrategy, TreeConfig};
+use merkle_tree_api::error::MerkleError;
+use merkle_tree_api::lmdb_tree::LmdbMerkleTree;
+use merkle_tree_api::merkle_tree::IncrementalMerkleTree;
+use merkle_tree_api::tree::MerkleTree;
+use tempfile::TempDir;
+
+// Runs the same operations through the trait and returns the roots seen
+fn exercise<T: MerkleTree>(tree: &mut T) -> Vec<Vec<u8>> {
+    assert!(tree.is_empty().unwrap());
+    assert!(tree.root().unwrap().is_none());
+
+    tree.append(hex("a")).unwrap();
+    tree.append_batch(vec![hex("b"), hex("c"), hex("d"), hex("e")])
+        .unwrap();
+    assert_eq!(tree.len().unwrap(), 5);
+
+    let root = tree.root().unwrap().unwrap();
+    for (index, leaf) in ["a", "b", "c", "d", "e"].iter().enumerate() {
+        let proof = tree.proof(index).unwrap();
+        assert!(tree.verify(&hex(leaf), &proof, &root, index));
+        assert!(tree.inclusion_proof(index, 5).unwrap().verify());
+    }
+    assert!(matches!(
+        tree.proof(5),
+        Err(MerkleError::IndexOutOfRange { index: 5, size: 5 })
+    ));
+
+    let old_root = tree.root_at(3).unwrap();
+    let old_proof = tree.proof_at(1, 3).unwrap();
+    assert!(merkle_tree_api::verify::verify_proof(
+        tree.config(),
+        &hex("b"),
+        &old_proof,
+        &old_root,
+        1,
+        3
+    ));
+    assert!(tree.multiproof(&[0, 4]).is_ok());
+    assert!(tree.range_proof(1, 3, 3).is_ok());
+    assert!(tree.consistency_proof(3, 5).is_ok());
+
+    tree.update(2, hex("updated")).unwrap();
+    let new_root = tree.root().unwrap().unwrap();
+    assert_ne!(new_root, root);
+
+    // Earlier roots stay as they were, and can no longer be proven
+    assert_eq!(tree.root_at(3).unwrap(), old_root);
+    assert_eq!(tree.root_at(5).unwrap(), new_root);
+    assert!(matches!(
+        tree.proof_at(1, 3),
+        Err(MerkleError::InvalidArgument(_))
+    ));
+    assert!(tree.range_proof(0, 2, 3).is_err());
+    assert!(tree.consistency_proof(3, 5).is_err());
+    assert!(tree.proof_at(1, 5).is_ok());
+
+    vec![root, old_root, new_root]
+}
+
+#[test]
+fn test_backends_agree_through_trait() {
+    // Consistency proofs need RFC 6962 style odd nodes
+    let config = TreeConfig {
+        odd_node: OddNodeStrategy::Promote,
+        ..TreeConfig::default()
+    };
+
+    let mut heap = IncrementalMerkleTree::with_config(config.clone()).unwrap();
+    let heap_roots = exercise(&mut heap);
+
+    let temp_dir = TempDir::new().unwrap();
+    let db_path = temp_dir.path().join("test.db");
+    let mut lmdb = LmdbMerkleTree::with_config(db_path.to_str().unwrap(), config).unwrap();
+    let lmdb_roots = exercise(&mut lmdb);
+
+    assert_eq!(heap_roots, lmdb_roots);
+}
+
+#[test]
+fn test_append_preimages() {
+    let mut heap = IncrementalMerkleTree::new();
+    let leaves = heap
+        .append_preimages(vec![b"alice".to_vec(), b"bob".to_vec()], false)
+        .unwrap();
+    assert_eq!(leaves[0], heap.config().preimage_leaf(b"alice"));
+    assert_eq!(heap.preimage(0).unwrap(), None);
+
+    // The heap tree has nowhere to keep preimages and adds nothing
+    assert!(matches!(
+        heap.append_preimages(vec![b"carol".to_vec()], true),
+        Err(MerkleError::InvalidArgument(_))
+    ));
+    assert_eq!(heap.len().unwrap(), 2);
+
+    let temp_dir = TempDir::new().unwrap();
+    let db_path = temp_dir.path().join("test.db");
+    let mut lmdb = LmdbMerkleTree::new(db_path.to_str().unwrap()).unwrap();
+    let lmdb_leaves = lmdb
+        .append_preimages(vec![b"alice".to_vec(), b"bob".to_vec()], true)
+        .unwrap();
+    assert_eq!(lmdb_leaves, leaves);
+    assert_eq!(lmdb.preimage(1).unwrap(), Some(b"bob".to_vec()));
+}
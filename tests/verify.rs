@@ -0,0 +1,296 @@
+mod common;
+
+use common::{leaves, tree};
+use merkle_tree_api::config::{HashScheme, OddNodeStrategy, TreeConfig};
+use merkle_tree_api::hasher::Sha256Hasher;
+use merkle_tree_api::verify::{verify_consistency, verify_multiproof, verify_proof};
+
+#[test]
+fn test_verify_proof_without_tree() {
+    let leaves = leaves(11);
+    let configs = [
+        TreeConfig::default(),
+        TreeConfig {
+            scheme: HashScheme::Rfc6962,
+            odd_node: OddNodeStrategy::Promote,
+            ..TreeConfig::new(Sha256Hasher)
+        },
+        TreeConfig::fixed_depth(Sha256Hasher, 6),
+    ];
+
+    for config in configs {
+        let tree = tree(&config, &leaves);
+        let root = tree.root().unwrap();
+
+        for (index, leaf) in leaves.iter().enumerate() {
+            let proof = tree.get_proof(index).unwrap();
+            assert!(verify_proof(
+                &config,
+                leaf,
+                &proof,
+                &root,
+                index,
+                leaves.len()
+            ));
+            assert!(!verify_proof(
+                &config,
+                b"other",
+                &proof,
+                &root,
+                index,
+                leaves.len()
+            ));
+        }
+    }
+}
+
+#[test]
+fn test_verify_proof_needs_matching_parameters() {
+    let leaves = leaves(5);
+    let config = TreeConfig {
+        scheme: HashScheme::Rfc6962,
+        ..TreeConfig::new(Sha256Hasher)
+    };
+    let tree = tree(&config, &leaves);
+    let root = tree.root().unwrap();
+    let proof = tree.get_proof(2).unwrap();
+
+    assert!(verify_proof(&config, &leaves[2], &proof, &root, 2, 5));
+    assert!(!verify_proof(
+        &TreeConfig::default(),
+        &leaves[2],
+        &proof,
+        &root,
+        2,
+        5
+    ));
+    assert!(!verify_proof(
+        &TreeConfig::new(Sha256Hasher),
+        &leaves[2],
+        &proof,
+        &root,
+        2,
+        5
+    ));
+}
+
+#[test]
+fn test_verify_proof_rejects_index_past_tree_size() {
+    use merkle_tree_api::verify::MerkleProof;
+
+    let leaves = leaves(3);
+
+    // Leaf 2 pairs with a zero hash, which must not pass as leaf 3
+    let config = TreeConfig {
+        odd_node: OddNodeStrategy::ZeroPad,
+        ..TreeConfig::default()
+    };
+    let zero_pad = tree(&config, &leaves);
+    let root = zero_pad.root().unwrap();
+    let proof = zero_pad.get_proof(2).unwrap();
+    let zero = config.zero_hashes(0).swap_remove(0);
+    let forged = MerkleProof {
+        siblings: vec![
+            ::hex::encode(config.hash_leaf(&leaves[2])),
+            proof.siblings[1].clone(),
+        ],
+    };
+    assert!(verify_proof(&config, &leaves[2], &proof, &root, 2, 3));
+    assert!(!verify_proof(&config, &zero, &forged, &root, 3, 3));
+
+    // A duplicated leaf 2 hashes the same at index 3
+    let config = TreeConfig {
+        odd_node: OddNodeStrategy::Duplicate,
+        ..TreeConfig::default()
+    };
+    let duplicate = tree(&config, &leaves);
+    let root = duplicate.root().unwrap();
+    let proof = duplicate.get_proof(2).unwrap();
+    assert!(verify_proof(&config, &leaves[2], &proof, &root, 2, 3));
+    assert!(!verify_proof(&config, &leaves[2], &proof, &root, 3, 3));
+
+    let mut inclusion = duplicate.get_inclusion_proof(2).unwrap();
+    inclusion.leaf_index = 3;
+    assert!(!inclusion.verify());
+
+    // Fixed-depth trees only have room for arity^depth leaves
+    let config = TreeConfig::fixed_depth(Sha256Hasher, 2);
+    let fixed = tree(&config, &leaves);
+    let root = fixed.root().unwrap();
+    let proof = fixed.get_proof(0).unwrap();
+    assert!(verify_proof(&config, &leaves[0], &proof, &root, 0, 3));
+    assert!(!verify_proof(&config, &leaves[0], &proof, &root, 4, 5));
+}
+
+#[test]
+fn test_verify_proof_rejects_wrong_sibling_count() {
+    let leaves = leaves(7);
+    let configs = [
+        TreeConfig::default(),
+        TreeConfig {
+            odd_node: OddNodeStrategy::Promote,
+            ..TreeConfig::default()
+        },
+        TreeConfig {
+            arity: 4,
+            ..TreeConfig::default()
+        },
+        TreeConfig::fixed_depth(Sha256Hasher, 4),
+    ];
+
+    for config in configs {
+        let tree = tree(&config, &leaves);
+        let root = tree.root().unwrap();
+
+        for (index, leaf) in leaves.iter().enumerate() {
+            let proof = tree.get_proof(index).unwrap();
+            assert!(verify_proof(&config, leaf, &proof, &root, index, 7));
+
+            let mut extra = proof.clone();
+            extra.siblings.extend(std::iter::repeat_n(
+                proof.siblings[0].clone(),
+                config.arity - 1,
+            ));
+            assert!(!verify_proof(&config, leaf, &extra, &root, index, 7));
+
+            let mut short = proof.clone();
+            short
+                .siblings
+                .truncate(proof.siblings.len() - (config.arity - 1));
+            assert!(!verify_proof(&config, leaf, &short, &root, index, 7));
+        }
+    }
+}
+
+#[test]
+fn test_verify_range_proof_rejects_impossible_tree_size() {
+    use merkle_tree_api::verify::verify_range_proof;
+
+    // A full fixed-depth tree needs no siblings, whatever size is claimed
+    let leaves = leaves(4);
+    let config = TreeConfig::fixed_depth(Sha256Hasher, 2);
+    let tree = tree(&config, &leaves);
+    let root = tree.root().unwrap();
+    let mut proof = tree.get_range_proof(0, 4).unwrap();
+    assert!(verify_range_proof(&config, &leaves, &proof, &root));
+
+    proof.tree_size = 5;
+    assert!(!verify_range_proof(&config, &leaves, &proof, &root));
+}
+
+#[test]
+fn test_verify_multiproof_and_consistency_without_tree() {
+    let leaves = leaves(14);
+    let config = TreeConfig {
+        odd_node: OddNodeStrategy::Promote,
+        ..TreeConfig::default()
+    };
+    let tree = tree(&config, &leaves);
+    let root = tree.root().unwrap();
+
+    let proof = tree.get_multiproof(&[2, 9, 13]).unwrap();
+    let proven = vec![leaves[2].clone(), leaves[9].clone(), leaves[13].clone()];
+    assert!(verify_multiproof(
+        &config,
+        &proven,
+        &proof,
+        &root,
+        leaves.len()
+    ));
+
+    let old_root = tree.root_at(6).unwrap();
+    let proof = tree.consistency_proof(6, leaves.len()).unwrap();
+    assert!(verify_consistency(&config, &old_root, &root, &proof));
+}
+
+#[test]
+fn test_verify_consistency_needs_promoting_binary_tree() {
+    let leaves = leaves(14);
+    let config = TreeConfig {
+        odd_node: OddNodeStrategy::Promote,
+        ..TreeConfig::default()
+    };
+    let tree = tree(&config, &leaves);
+    let old_root = tree.root_at(6).unwrap();
+    let new_root = tree.root().unwrap();
+    let proof = tree.consistency_proof(6, leaves.len()).unwrap();
+    assert!(verify_consistency(&config, &old_root, &new_root, &proof));
+
+    // The same hashes prove nothing about trees the generator refuses
+    let others = [
+        TreeConfig::default(),
+        TreeConfig {
+            arity: 4,
+            ..config.clone()
+        },
+        TreeConfig {
+            depth: Some(4),
+            ..config.clone()
+        },
+    ];
+    for other in &others {
+        assert!(!verify_consistency(other, &old_root, &new_root, &proof));
+    }
+}
+
+#[test]
+fn test_inclusion_proof_round_trip() {
+    use merkle_tree_api::verify::{INCLUSION_PROOF_VERSION, InclusionProof};
+
+    let leaves = leaves(9);
+    let config = TreeConfig {
+        scheme: HashScheme::Rfc6962,
+        odd_node: OddNodeStrategy::Promote,
+        ..TreeConfig::new(Sha256Hasher)
+    };
+    let tree = tree(&config, &leaves);
+
+    let proof = tree.get_inclusion_proof(8).unwrap();
+    assert_eq!(proof.version, INCLUSION_PROOF_VERSION);
+    assert_eq!(proof.hash_algorithm, "sha256");
+    assert_eq!(proof.tree_size, 9);
+    assert_eq!(proof.leaf, ::hex::encode(&leaves[8]));
+    assert_eq!(proof.root, ::hex::encode(tree.root().unwrap()));
+
+    let json = serde_json::to_string(&proof).unwrap();
+    let decoded: InclusionProof = serde_json::from_str(&json).unwrap();
+    assert_eq!(decoded, proof);
+    assert!(decoded.verify());
+
+    // Binary trees leave the arity out, so older proofs still parse
+    assert!(!json.contains("arity"));
+
+    // Proofs against an earlier size carry that size and its root
+    let old_proof = tree.get_inclusion_proof_at(3, 5).unwrap();
+    assert_eq!(old_proof.root, ::hex::encode(tree.root_at(5).unwrap()));
+    assert!(old_proof.verify());
+}
+
+#[test]
+fn test_inclusion_proof_rejects_tampering() {
+    let leaves = leaves(6);
+    let tree = tree(&TreeConfig::default(), &leaves);
+    let proof = tree.get_inclusion_proof(2).unwrap();
+    assert!(proof.verify());
+
+    let mut other_leaf = proof.clone();
+    other_leaf.leaf = ::hex::encode(b"other");
+    assert!(!other_leaf.verify());
+
+    let mut other_index = proof.clone();
+    other_index.leaf_index = 3;
+    assert!(!other_index.verify());
+
+    let mut other_hasher = proof.clone();
+    other_hasher.hash_algorithm = "sha256".to_string();
+    assert!(!other_hasher.verify());
+
+    let mut unknown_hasher = proof.clone();
+    unknown_hasher.hash_algorithm = "md5".to_string();
+    assert!(unknown_hasher.config().is_err());
+    assert!(!unknown_hasher.verify());
+
+    let mut future_version = proof;
+    future_version.version += 1;
+    assert!(!future_version.verify());
+}